@@ -1,7 +1,41 @@
-use crate::{BacktestConfig, BacktestResult, BacktestOrder, GlickoRating, Result};
-use crate::data::MovingStats;
+use crate::{
+    AnnualizationBasis, BacktestConfig, BacktestResult, BacktestOrder, DrawdownThrottle, DuplicateRatings, EntryPriority, ExitReason, GlickoRating, IntrabarAssumption, KlineData, MarketCalendar,
+    OpenPositionReport, OrderSide, PricePoint, RebalanceWeighting, Result, ReturnBasis, SensitivityDeltas, SensitivityPoint, SensitivityReport, SizingBase, StopMode, SymbolStats,
+    TradeLedgerEntry, WindowUnit, ZScoreMethod,
+};
+use crate::data::{calculate_rsi, smooth_series, EmaStats, MovingStats};
+use crate::validation::infer_interval;
+use chrono::Timelike;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BacktestError {
+    #[error(
+        "Not enough rating history for {symbol}: {history_len} ratings but moving_averages is {period}; \
+         reduce moving_averages or supply more history"
+    )]
+    InsufficientHistory {
+        symbol: String,
+        history_len: usize,
+        period: usize,
+    },
+    #[error("Non-finite rating {rating} for {symbol} at {timestamp}; check the upstream ratings")]
+    NonFiniteRating {
+        symbol: String,
+        timestamp: i64,
+        rating: f64,
+    },
+    #[error("Duplicate rating for {symbol} at {timestamp}; dedupe the input or set duplicate_ratings to keep_last")]
+    DuplicateRating { symbol: String, timestamp: i64 },
+    #[error(
+        "Both profit_percent ({profit_percent}) and risk_reward_ratio ({risk_reward_ratio}) set the \
+         take-profit for percent stops; set only one"
+    )]
+    AmbiguousTakeProfit { profit_percent: f64, risk_reward_ratio: f64 },
+}
 
 /// Represents an open position in the portfolio.
 ///
@@ -14,6 +48,7 @@ use std::collections::HashMap;
 /// (since the position no longer exists). This mimics real OCO orders on Binance/Kraken.
 #[derive(Debug, Clone)]
 struct Position {
+    #[allow(dead_code)]
     symbol: String,
     quantity: f64,
     entry_price: f64,
@@ -22,48 +57,183 @@ struct Position {
     stop_loss_price: f64,
     /// Take profit threshold: price ≥ this triggers EXIT_PROFIT
     take_profit_price: f64,
+    /// Cash posted for the position; equals the notional cost when unleveraged
+    margin: f64,
+    leverage: f64,
+    /// Price at which equity falls to the maintenance margin (leveraged positions only)
+    liquidation_price: Option<f64>,
+    /// Opened during the warm-up; its orders are flagged and left out of the metrics
+    warmup: bool,
+}
+
+impl Position {
+    /// Margin plus unrealized P/L; what the position returns to cash if closed at `price`.
+    fn equity(&self, price: f64) -> f64 {
+        self.margin + self.quantity * (price - self.entry_price)
+    }
+
+    /// Solve `margin + q * (p - entry) = mm * q * p` for the liquidation price `p`.
+    fn compute_liquidation_price(&self, maintenance_margin: f64) -> Option<f64> {
+        if self.leverage <= 1.0 {
+            return None;
+        }
+        let price = (self.quantity * self.entry_price - self.margin)
+            / (self.quantity * (1.0 - maintenance_margin));
+        Some(price.max(0.0))
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Portfolio {
     cash: f64,
-    positions: HashMap<String, Position>,
+    /// Keyed in symbol order so summing over positions is reproducible run to run
+    positions: BTreeMap<String, Position>,
     equity_curve: Vec<(i64, f64)>,
+    /// `(timestamp, realized, unrealized)` alongside each equity point
+    pnl_curve: Vec<(i64, f64, f64)>,
     orders: Vec<BacktestOrder>,
+    /// Half spread as a fraction of the mid price
+    half_spread: f64,
+    diagnostics: Vec<String>,
+    /// Highest equity seen so far
+    peak_equity: f64,
+    drawdown_throttle: Option<DrawdownThrottle>,
+    throttled: bool,
+    /// Equity (net of fees) above which performance fees are charged; only ever rises
+    high_water_mark: f64,
+    /// Annual yield accrued on positive cash at each equity-curve update
+    cash_yield_annual: Option<f64>,
+    /// Ledger precision for cash postings (`BacktestConfig::cash_decimals`)
+    cash_decimals: Option<u32>,
+    /// Current value of one unit of each symbol's quote asset in the reporting currency
+    /// (`quote_asset`); symbols without an entry are quoted in it directly
+    quote_rates: HashMap<String, f64>,
+    /// Latest average true range per symbol, kept only for `StopMode::AtrMultiple`
+    atr: HashMap<String, f64>,
+    /// Whether the current bar is still inside `warmup_bars`
+    in_warmup: bool,
+    /// Timestamp of the first bar after the warm-up, once reached
+    warmup_end: Option<i64>,
 }
 
 impl Portfolio {
     fn new(initial_cash: f64) -> Self {
         Self {
             cash: initial_cash,
-            positions: HashMap::new(),
+            positions: BTreeMap::new(),
             equity_curve: vec![(0, initial_cash)],
+            pnl_curve: vec![(0, 0.0, 0.0)],
             orders: Vec::new(),
+            half_spread: 0.0,
+            diagnostics: Vec::new(),
+            peak_equity: initial_cash,
+            drawdown_throttle: None,
+            throttled: false,
+            high_water_mark: initial_cash,
+            cash_yield_annual: None,
+            cash_decimals: None,
+            quote_rates: HashMap::new(),
+            atr: HashMap::new(),
+            in_warmup: false,
+            warmup_end: None,
         }
     }
 
+    /// Price a buy fills at: the ask.
+    fn ask(&self, price: f64) -> f64 {
+        price * (1.0 + self.half_spread)
+    }
+
+    /// Price a sell fills at: the bid.
+    fn bid(&self, price: f64) -> f64 {
+        price * (1.0 - self.half_spread)
+    }
+
+    /// Add `amount` (negative for a debit) to cash, rounding the posting and the new
+    /// balance to `cash_decimals` when set.
+    fn post_cash(&mut self, amount: f64) {
+        let round = |value: f64| match self.cash_decimals {
+            Some(decimals) => {
+                let scale = 10f64.powi(decimals as i32);
+                (value * scale).round() / scale
+            }
+            None => value,
+        };
+        self.cash = round(self.cash + round(amount));
+    }
+
+    /// Multiplier from `symbol`'s quote asset into the reporting currency.
+    fn quote_rate(&self, symbol: &str) -> f64 {
+        self.quote_rates.get(symbol).copied().unwrap_or(1.0)
+    }
+
+    /// Split of the P&L so far at `current_prices` into `(realized, unrealized)`: open
+    /// positions against their cost basis, and the rest of the change in equity.
+    fn pnl_split(&self, current_prices: &HashMap<String, f64>) -> (f64, f64) {
+        let unrealized: f64 = self
+            .positions
+            .iter()
+            .filter_map(|(symbol, position)| {
+                let price = current_prices.get(symbol)?;
+                Some(position.quantity * (price - position.entry_price) * self.quote_rate(symbol))
+            })
+            .sum();
+        let initial_cash = self.equity_curve[0].1;
+        (self.get_portfolio_value(current_prices) - initial_cash - unrealized, unrealized)
+    }
+
+    /// Cash plus every position's equity, converted into the reporting currency.
     fn get_portfolio_value(&self, current_prices: &HashMap<String, f64>) -> f64 {
         let mut total_value = self.cash;
         
         for (symbol, position) in &self.positions {
             if let Some(&current_price) = current_prices.get(symbol) {
-                total_value += position.quantity * current_price;
+                total_value += position.equity(current_price) * self.quote_rate(symbol);
             }
         }
         
         total_value
     }
 
+    /// Equity as of the last mark, in `symbol`'s quote asset.
+    fn quote_equity(&self, symbol: &str) -> f64 {
+        self.equity_curve.last().map_or(self.cash, |&(_, value)| value) / self.quote_rate(symbol)
+    }
+
+    /// Margin an entry of `allocation_percent` posts under `sizing_base`, in the quote asset.
+    fn entry_margin(&self, symbol: &str, config: &BacktestConfig, allocation_percent: f64) -> f64 {
+        let rate = self.quote_rate(symbol);
+        match config.sizing_base {
+            SizingBase::AvailableCash => self.cash / rate * allocation_percent,
+            // Compounds off open positions too, but never commits more cash than is free
+            SizingBase::TotalEquity => {
+                let equity = self.equity_curve.last().map_or(self.cash, |&(_, value)| value);
+                (equity * allocation_percent).min(self.cash) / rate
+            }
+        }
+    }
+
+    /// Notional an entry of `allocation_percent` opens at before lot rounding, in the
+    /// quote asset: the levered margin, capped at `max_notional_multiple` x equity after
+    /// all other sizing so no combination of allocation and leverage can exceed it.
+    fn entry_notional(&self, symbol: &str, config: &BacktestConfig, allocation_percent: f64) -> f64 {
+        let notional = self.entry_margin(symbol, config, allocation_percent) * config.effective_leverage();
+        match config.max_notional_multiple {
+            Some(multiple) => notional.min(multiple * self.quote_equity(symbol)),
+            None => notional,
+        }
+    }
+
     /// Opens a new position with OCO (One-Cancels-Other) exit levels.
     ///
     /// Entry Rules:
-    /// - Position size = allocation_percent of available cash
+    /// - Position size = allocation_percent of available cash (or of total equity, see `SizingBase`)
     /// - Each BUY signal creates a position with:
     ///   - Entry price: current market price
     ///   - Quantity: allocated_value / entry_price
     ///
     /// OCO Exit Levels:
-    /// - Take Profit: entry_price * (1 + profit_percent/100)
+    /// - Take Profit: entry_price * (1 + profit_percent/100), or stop_loss_percent * risk_reward_ratio
     /// - Stop Loss: entry_price * (1 - stop_loss_percent/100)
     ///
     /// Example (2% profit_percent, 2.5% stop_loss_percent):
@@ -81,39 +251,115 @@ impl Portfolio {
         if self.positions.contains_key(&symbol) {
             return None; // Already have position
         }
+        let price = self.ask(price);
+        // Sizing happens in the pair's quote asset; cash is converted at the current rate
+        let rate = self.quote_rate(&symbol);
+
+        let leverage = config.effective_leverage();
+        let requested = self.entry_margin(&symbol, config, allocation_percent) * leverage;
+        let notional = self.entry_notional(&symbol, config, allocation_percent);
+        // Note when the max_notional_multiple cap bound
+        if let Some(multiple) = config.max_notional_multiple.filter(|_| notional < requested) {
+            self.diagnostics.push(format!(
+                "Clamped {} entry at {}: notional {:.2} above max_notional_multiple {} x equity {:.2}",
+                symbol,
+                timestamp,
+                requested,
+                multiple,
+                self.quote_equity(&symbol)
+            ));
+        }
+        let mut quantity = notional / price;
+        let mut margin = notional / leverage;
 
-        let position_value = self.cash * allocation_percent;
-        let quantity = position_value / price;
+        // Floor to the exchange step size; the unfilled remainder stays in cash
+        if let Some(step_size) = config.step_size.filter(|&step| step > 0.0) {
+            quantity = round_down_to_step(quantity, step_size);
+            margin = quantity * price / leverage;
+            if quantity <= 0.0 {
+                return None; // Not enough cash for a single lot
+            }
+        }
+
+        // Whole units only; the fractional remainder stays in cash
+        if config.integer_quantities.unwrap_or(false) {
+            let notional = quantity * price;
+            quantity = quantity.floor();
+            margin = quantity * price / leverage;
+            if quantity < 1.0 {
+                self.diagnostics.push(format!(
+                    "Skipped {} entry at {}: price {:.2} above the {:.2} available for one whole unit",
+                    symbol,
+                    timestamp,
+                    price,
+                    notional
+                ));
+                return None;
+            }
+        }
 
-        if quantity * price > self.cash {
+        if margin * rate > self.cash {
             return None; // Not enough cash
         }
 
+        if let Some(min_notional) = config.min_notional {
+            if quantity * price < min_notional {
+                self.diagnostics.push(format!(
+                    "Skipped {} entry at {}: notional {:.2} below min_notional {:.2}",
+                    symbol,
+                    timestamp,
+                    quantity * price,
+                    min_notional
+                ));
+                return None;
+            }
+        }
+
         // Calculate OCO exit levels
-        let take_profit_price = price * (1.0 + config.profit_percent / 100.0);
-        let stop_loss_price = price * (1.0 - config.stop_loss_percent / 100.0);
+        let (stop_loss_price, take_profit_price) = match &config.stop_mode {
+            StopMode::Percent => (
+                price * (1.0 - config.stop_loss_percent / 100.0),
+                price * (1.0 + config.effective_profit_percent() / 100.0),
+            ),
+            StopMode::AbsoluteOffset { stop_offset, profit_offset } => (price - stop_offset, price + profit_offset),
+            StopMode::AtrMultiple { stop_multiple, profit_multiple, .. } => {
+                let Some(atr) = self.atr.get(&symbol).copied().filter(|&atr| atr > 0.0) else {
+                    self.diagnostics.push(format!("Skipped {} entry at {}: no ATR for AtrMultiple stops", symbol, timestamp));
+                    return None;
+                };
+                (price - stop_multiple * atr, price + profit_multiple * atr)
+            }
+        };
 
-        let position = Position {
+        let mut position = Position {
             symbol: symbol.clone(),
             quantity,
             entry_price: price,
             entry_time: timestamp,
             stop_loss_price,
             take_profit_price,
+            margin,
+            leverage,
+            liquidation_price: None,
+            warmup: self.in_warmup,
         };
+        position.liquidation_price = position.compute_liquidation_price(maintenance_margin(config));
 
-        self.cash -= quantity * price;
+        self.post_cash(-margin * rate);
         self.positions.insert(symbol.clone(), position);
+        tracing::debug!(%symbol, price, quantity, timestamp, "Opened position");
 
         let order = BacktestOrder {
             symbol,
-            side: "BUY".to_string(),
+            side: OrderSide::Buy,
             quantity,
             price,
             timestamp,
-            reason: "ENTRY".to_string(),
+            timestamp_iso: None,
+            reason: ExitReason::Entry,
             profit_loss: None,
             profit_loss_percent: None,
+            warmup: self.in_warmup,
         };
 
         self.orders.push(order.clone());
@@ -125,24 +371,28 @@ impl Portfolio {
         symbol: &str,
         price: f64,
         timestamp: i64,
-        reason: &str,
+        reason: ExitReason,
     ) -> Option<BacktestOrder> {
         if let Some(position) = self.positions.remove(symbol) {
-            let proceeds = position.quantity * price;
-            self.cash += proceeds;
+            // Forced liquidations settle at the liquidation price itself
+            let price = if reason == ExitReason::ExitLiquidation { price } else { self.bid(price) };
+            self.post_cash(position.equity(price) * self.quote_rate(symbol));
 
-            let profit_loss = proceeds - (position.quantity * position.entry_price);
+            let profit_loss = position.quantity * (price - position.entry_price);
             let profit_loss_percent = (price - position.entry_price) / position.entry_price * 100.0;
+            tracing::debug!(symbol, price, ?reason, profit_loss, timestamp, "Closed position");
 
             let order = BacktestOrder {
                 symbol: symbol.to_string(),
-                side: "SELL".to_string(),
+                side: OrderSide::Sell,
                 quantity: position.quantity,
                 price,
                 timestamp,
-                reason: reason.to_string(),
+                timestamp_iso: None,
+                reason,
                 profit_loss: Some(profit_loss),
                 profit_loss_percent: Some(profit_loss_percent),
+                warmup: position.warmup,
             };
 
             self.orders.push(order.clone());
@@ -152,12 +402,297 @@ impl Portfolio {
         }
     }
 
+    /// Redistribute the value currently held in open positions according to `weights`.
+    ///
+    /// Overweight positions are trimmed first so the freed cash can top up underweight
+    /// ones; cash outside the positions is left untouched. Every adjustment is recorded
+    /// as a BUY/SELL order with reason "REBALANCE". Stop/take-profit levels are kept.
+    fn rebalance(
+        &mut self,
+        weights: &HashMap<String, f64>,
+        current_prices: &HashMap<String, f64>,
+        timestamp: i64,
+        maintenance_margin: f64,
+    ) {
+        let mut held: Vec<(String, f64, f64)> = self.positions
+            .keys()
+            .filter_map(|symbol| {
+                let price = *current_prices.get(symbol)?;
+                let weight = *weights.get(symbol)?;
+                Some((symbol.clone(), price, weight))
+            })
+            .collect();
+        held.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let invested: f64 = held.iter()
+            .map(|(symbol, price, _)| self.positions[symbol].quantity * price * self.quote_rate(symbol))
+            .sum();
+        let total_weight: f64 = held.iter().map(|(_, _, weight)| weight).sum();
+        if invested <= 0.0 || total_weight <= 0.0 {
+            return;
+        }
+
+        let mut adjustments: Vec<(String, f64, f64)> = held
+            .into_iter()
+            .map(|(symbol, price, weight)| {
+                let target_quantity = invested * weight / total_weight / (price * self.quote_rate(&symbol));
+                let delta = target_quantity - self.positions[&symbol].quantity;
+                (symbol, price, delta)
+            })
+            .filter(|(_, price, delta)| (delta * price).abs() > 1e-6)
+            .collect();
+        // Sells before buys so trims fund the top-ups
+        adjustments.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        for (symbol, mid_price, delta) in adjustments {
+            let price = if delta < 0.0 { self.bid(mid_price) } else { self.ask(mid_price) };
+            let rate = self.quote_rate(&symbol);
+            let position = self.positions.get_mut(&symbol).expect("rebalanced symbol is held");
+            let (side, quantity, profit_loss, cash_flow) = if delta < 0.0 {
+                // Realize the trimmed slice and release its share of the margin
+                let quantity = -delta;
+                let released_margin = position.margin * quantity / position.quantity;
+                let realized = quantity * (price - position.entry_price);
+                position.quantity -= quantity;
+                position.margin -= released_margin;
+                (OrderSide::Sell, quantity, Some(realized), (released_margin + realized) * rate)
+            } else {
+                let quantity = delta.min(self.cash / rate * position.leverage / price);
+                let cost_basis = position.quantity * position.entry_price + quantity * price;
+                position.quantity += quantity;
+                position.entry_price = cost_basis / position.quantity;
+                position.margin += quantity * price / position.leverage;
+                (OrderSide::Buy, quantity, None, -quantity * price / position.leverage * rate)
+            };
+            position.liquidation_price = position.compute_liquidation_price(maintenance_margin);
+            let warmup = position.warmup;
+            self.post_cash(cash_flow);
+            tracing::debug!(%symbol, ?side, price, quantity, timestamp, "Rebalanced position");
+
+            self.orders.push(BacktestOrder {
+                symbol,
+                side,
+                quantity,
+                price,
+                timestamp,
+                timestamp_iso: None,
+                reason: ExitReason::Rebalance,
+                profit_loss,
+                profit_loss_percent: None,
+                warmup,
+            });
+        }
+    }
+
+    /// Settle one funding payment: each open position pays `notional * funding_rate`
+    /// from cash, with notional marked at the current price.
+    fn apply_funding(&mut self, funding_rate: f64, current_prices: &HashMap<String, f64>, timestamp: i64) {
+        let payments: Vec<(String, f64)> = self
+            .positions
+            .iter()
+            .filter_map(|(symbol, position)| {
+                let price = current_prices.get(symbol)?;
+                Some((symbol.clone(), position.quantity * price * funding_rate * self.quote_rate(symbol)))
+            })
+            .collect();
+        for (symbol, payment) in payments {
+            self.post_cash(-payment);
+            tracing::debug!(%symbol, payment, timestamp, "Settled funding");
+        }
+    }
+
+    /// Charge `fee_percent` of the equity above the high-water mark, raising the mark to
+    /// the equity left after the fee. Nothing is charged until losses are recovered.
+    fn charge_performance_fee(&mut self, fee_percent: f64, current_prices: &HashMap<String, f64>, timestamp: i64) {
+        let equity = self.get_portfolio_value(current_prices);
+        if equity > self.high_water_mark {
+            let fee = (equity - self.high_water_mark) * fee_percent / 100.0;
+            self.post_cash(-fee);
+            self.high_water_mark = equity - fee;
+            tracing::debug!(fee, high_water_mark = self.high_water_mark, timestamp, "Charged performance fee");
+        }
+    }
+
     fn update_equity_curve(&mut self, timestamp: i64, current_prices: &HashMap<String, f64>) {
+        if let (Some(annual), Some(&(previous, _))) = (self.cash_yield_annual, self.equity_curve.last()) {
+            let elapsed = (timestamp - previous).max(0) as f64;
+            if self.cash > 0.0 {
+                self.post_cash(self.cash * ((1.0 + annual).powf(elapsed / YEAR_MS) - 1.0));
+            }
+        }
         let portfolio_value = self.get_portfolio_value(current_prices);
         self.equity_curve.push((timestamp, portfolio_value));
+        let (realized, unrealized) = self.pnl_split(current_prices);
+        self.pnl_curve.push((timestamp, realized, unrealized));
+
+        self.peak_equity = self.peak_equity.max(portfolio_value);
+        if let Some(throttle) = &self.drawdown_throttle {
+            let drawdown_percent = (1.0 - portfolio_value / self.peak_equity) * 100.0;
+            if drawdown_percent > throttle.throttle_trigger_percent {
+                self.throttled = true;
+            } else if drawdown_percent <= throttle.reset_percent {
+                self.throttled = false;
+            }
+        }
+    }
+
+    /// Multiplier on entry allocations: `throttle_factor` while throttled, else 1.
+    fn allocation_scale(&self) -> f64 {
+        match &self.drawdown_throttle {
+            Some(throttle) if self.throttled => throttle.throttle_factor,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Floor `quantity` to a whole number of `step_size` lots, tolerating float noise
+/// such as 1.23 / 0.01 = 122.99999999999999.
+fn round_down_to_step(quantity: f64, step_size: f64) -> f64 {
+    ((quantity / step_size) + 1e-9).floor() * step_size
+}
+
+/// Expected shortfall of a standard normal at 95%: pdf(1.645) / 0.05.
+const CVAR_95_MULTIPLIER: f64 = 2.0627;
+
+/// Standard deviation of the last `lookback` simple returns of `prices`.
+fn return_volatility(prices: &[f64], lookback: usize) -> f64 {
+    let start = prices.len().saturating_sub(lookback + 1);
+    let returns: Vec<f64> = prices[start..].windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+    if returns.len() < 2 {
+        return 0.0;
+    }
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64).sqrt()
+}
+
+/// Check a candidate entry against the portfolio risk budget.
+///
+/// Each position's risk is its one-period 95% CVaR under normal returns,
+/// `notional * sigma * 2.06`, with sigma from the last `moving_averages` returns.
+/// Risks are summed (full correlation, the conservative case) and compared with
+/// equity; returns the rejection reason when the budget would be exceeded.
+fn risk_budget_rejection(
+    portfolio: &Portfolio,
+    symbol: &str,
+    allocation: f64,
+    budget_percent: f64,
+    config: &BacktestConfig,
+    current_prices: &HashMap<String, f64>,
+    price_history: &HashMap<String, Vec<f64>>,
+) -> Option<String> {
+    if portfolio.positions.contains_key(symbol) {
+        return None;
+    }
+
+    let volatility = |symbol: &str| {
+        price_history
+            .get(symbol)
+            .map_or(0.0, |prices| return_volatility(prices, config.moving_averages))
+    };
+    let held_risk: f64 = portfolio.positions
+        .iter()
+        .filter_map(|(held, pos)| {
+            let price = current_prices.get(held)?;
+            Some(pos.quantity * price * portfolio.quote_rate(held) * volatility(held) * CVAR_95_MULTIPLIER)
+        })
+        .sum();
+    // Sized exactly as `open_position` would size the entry
+    let candidate_notional = portfolio.entry_notional(symbol, config, allocation) * portfolio.quote_rate(symbol);
+    let candidate_risk = candidate_notional * volatility(symbol) * CVAR_95_MULTIPLIER;
+
+    let equity = portfolio.get_portfolio_value(current_prices);
+    let risk_percent = (held_risk + candidate_risk) / equity * 100.0;
+    (risk_percent > budget_percent).then(|| {
+        format!(
+            "estimated portfolio CVaR {:.2}% exceeds max_portfolio_risk_percent {:.2}%",
+            risk_percent, budget_percent
+        )
+    })
+}
+
+/// Maintenance margin as a fraction of notional.
+fn maintenance_margin(config: &BacktestConfig) -> f64 {
+    config.maintenance_margin_percent.unwrap_or(0.5) / 100.0
+}
+
+/// One symbol's price bar in the backtest timeline.
+#[derive(Debug, Clone, Copy)]
+struct Bar {
+    /// Price that marks positions and triggers stops (`signal_price`)
+    price: f64,
+    /// Price orders placed on this bar fill at (`fill_price`)
+    fill_price: f64,
+    /// Bar range the OCO levels are checked against; both equal `price` without klines
+    low: f64,
+    high: f64,
+    rating: f64,
+    signal: Option<Signal>,
+    z_score: Option<f64>,
+}
+
+/// Trading action implied by a z-score; serialized as "BUY"/"SELL"/"HOLD".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Signal {
+    Buy,
+    Sell,
+    Hold,
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Signal::Buy => "BUY",
+            Signal::Sell => "SELL",
+            Signal::Hold => "HOLD",
+        })
+    }
+}
+
+/// Map a z-score to a signal. The band is exclusive: a z-score exactly at
+/// `±threshold` is still HOLD.
+pub fn classify_signal(z_score: f64, threshold: f64) -> Signal {
+    if z_score > threshold {
+        Signal::Buy // Strong upside deviation
+    } else if z_score < -threshold {
+        Signal::Sell // Strong downside deviation
+    } else {
+        Signal::Hold // Within neutral band
+    }
+}
+
+/// Glicko's starting RD; `confidence_scaled_threshold` measures RD as a fraction of it.
+const INITIAL_RD: f64 = 350.0;
+
+/// `z_score_threshold`, scaled by `1 + confidence_scale * RD / 350` under
+/// `confidence_scaled_threshold` so less certain ratings need a larger z-score to trade.
+fn effective_threshold(config: &BacktestConfig, rating_deviation: f64) -> f64 {
+    if config.confidence_scaled_threshold.unwrap_or(false) {
+        config.z_score_threshold * (1.0 + config.confidence_scale.unwrap_or(1.0) * rating_deviation / INITIAL_RD)
+    } else {
+        config.z_score_threshold
     }
 }
 
+/// A single z-score evaluation for one symbol at one timestamp.
+///
+/// `upper_band`/`lower_band` are the Bollinger-style `mean ± k*std` levels of the
+/// rating window and are only populated when `with_bands` is enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZScoreSignal {
+    pub timestamp: i64,
+    pub z_score: f64,
+    pub signal: Signal,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upper_band: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lower_band: Option<f64>,
+}
+
+/// Per-symbol signal series in chronological order, keyed in symbol order so the
+/// serialized output is reproducible.
+pub type SymbolSignals = BTreeMap<String, Vec<ZScoreSignal>>;
+
 /// Calculate Z-score based trading signals from Glicko-2 ratings.
 ///
 /// Z-SCORE CALCULATION:
@@ -176,70 +711,372 @@ impl Portfolio {
 /// - Negative z_score: Glicko rating is falling (bearish momentum)
 /// - Reversal: When z_score crosses threshold, signal is generated
 ///
-/// PARAMETERS:
-/// - moving_averages_period: Window size (number of periods for rolling calculation)
-/// - threshold: Z-score boundary for signal generation (typically 1.5-2.5)
-fn calculate_z_score_signals(
+/// PARAMETERS (from config):
+/// - moving_averages: Window size (number of periods for rolling calculation)
+/// - z_score_threshold: Z-score boundary for signal generation (typically 1.5-2.5)
+/// - z_score_method: SMA (equal weights) or EMA (recent ratings weighted more,
+///   smoothing factor `ema_smoothing`, default 2 / (moving_averages + 1))
+/// - with_bands / band_multiplier: also emit `mean ± k*std` bands (k defaults to
+///   z_score_threshold, so the rating is inside the bands exactly when it is HOLD)
+/// - rsi_filter: BUY needs RSI < oversold, SELL needs RSI > overbought, else HOLD
+/// - min_performance_score: BUY needs the mean performance_score of the last
+///   `performance_score_window` ratings (default 5) to reach it, else HOLD
+///
+/// ERRORS:
+/// - `NonFiniteRating` for any NaN or infinite rating
+/// - `DuplicateRating` for a repeated (symbol, timestamp) under `DuplicateRatings::Error`;
+///   with `KeepLast` only the last such rating is used
+/// - `InsufficientHistory` if every symbol has no more ratings than the window needs,
+///   since nothing could ever produce a signal. When only some symbols are that short
+///   they are left out; `calculate_z_score_signals_with_skipped` reports which.
+pub fn calculate_z_score_signals(
+    ratings: &[GlickoRating],
+    config: &BacktestConfig,
+) -> std::result::Result<SymbolSignals, BacktestError> {
+    calculate_z_score_signals_with_skipped(ratings, config).map(|(signals, _)| signals)
+}
+
+/// Drop ratings repeated for the same symbol and timestamp, which would otherwise count
+/// twice in the moving-average window. Under `KeepLast` the last occurrence survives (in
+/// its original position) and the number dropped is returned; under `Error` the first
+/// repeat is rejected.
+pub fn dedupe_ratings(
+    ratings: Vec<GlickoRating>,
+    policy: DuplicateRatings,
+) -> std::result::Result<(Vec<GlickoRating>, usize), BacktestError> {
+    if policy == DuplicateRatings::Error {
+        let mut seen = HashSet::new();
+        for rating in &ratings {
+            if !seen.insert((rating.symbol.as_str(), rating.timestamp)) {
+                return Err(BacktestError::DuplicateRating {
+                    symbol: rating.symbol.clone(),
+                    timestamp: rating.timestamp,
+                });
+            }
+        }
+        return Ok((ratings, 0));
+    }
+
+    let total = ratings.len();
+    let mut seen = HashSet::new();
+    let mut kept: Vec<GlickoRating> = ratings
+        .into_iter()
+        .rev()
+        .filter(|rating| seen.insert((rating.symbol.clone(), rating.timestamp)))
+        .collect();
+    kept.reverse();
+    let dropped = total - kept.len();
+    Ok((kept, dropped))
+}
+
+/// `calculate_z_score_signals`, also returning `(symbol, history length)` for every
+/// symbol that never reached the moving-average window and so has no signals.
+pub fn calculate_z_score_signals_with_skipped(
     ratings: &[GlickoRating],
-    moving_averages_period: usize,
-    threshold: f64,
-) -> HashMap<String, Vec<(i64, f64, String)>> {
-    let mut symbol_ratings: HashMap<String, Vec<(i64, f64)>> = HashMap::new();
+    config: &BacktestConfig,
+) -> std::result::Result<(SymbolSignals, Vec<(String, usize)>), BacktestError> {
+    let moving_averages_period = config.moving_averages;
+    let threshold = config.z_score_threshold;
+    let ema_alpha = config
+        .ema_smoothing
+        .unwrap_or(2.0 / (moving_averages_period as f64 + 1.0));
+    let band_multiplier = config.band_multiplier.unwrap_or(threshold);
+    let mut symbol_ratings: BTreeMap<String, Vec<(i64, f64, f64, f64)>> = BTreeMap::new();
 
-    // Group ratings by symbol for independent signal calculation
+    // Group ratings by symbol for independent signal calculation. A NaN/inf rating would
+    // make every z-score it touches NaN (and so HOLD), so it is rejected outright.
     for rating in ratings {
+        if !rating.rating.is_finite() {
+            return Err(BacktestError::NonFiniteRating {
+                symbol: rating.symbol.clone(),
+                timestamp: rating.timestamp,
+                rating: rating.rating,
+            });
+        }
         symbol_ratings
             .entry(rating.symbol.clone())
-            .or_insert_with(Vec::new)
-            .push((rating.timestamp, rating.rating));
+            .or_default()
+            .push((rating.timestamp, rating.rating, rating.rating_deviation, rating.performance_score));
     }
 
-    let mut signals = HashMap::new();
+    let mut signals = BTreeMap::new();
+    let mut skipped_symbols = Vec::new();
 
     for (symbol, mut rating_history) in symbol_ratings {
         // Sort by timestamp to ensure chronological order
-        rating_history.sort_by_key(|(timestamp, _)| *timestamp);
+        rating_history.sort_by_key(|(timestamp, _, _, _)| *timestamp);
+
+        // A repeated timestamp would count twice in the window; the sort is stable, so
+        // the last rating in input order is the last of its run (as in `dedupe_ratings`)
+        match config.duplicate_ratings {
+            DuplicateRatings::KeepLast => rating_history.dedup_by(|later, kept| {
+                let duplicate = later.0 == kept.0;
+                if duplicate {
+                    *kept = *later;
+                }
+                duplicate
+            }),
+            DuplicateRatings::Error => {
+                if let Some(w) = rating_history.windows(2).find(|w| w[0].0 == w[1].0) {
+                    return Err(BacktestError::DuplicateRating { symbol, timestamp: w[0].0 });
+                }
+            }
+        }
+
+        if moving_averages_period >= rating_history.len() {
+            tracing::warn!(
+                symbol = %symbol,
+                history_len = rating_history.len(),
+                period = moving_averages_period,
+                "Skipping symbol without enough rating history"
+            );
+            skipped_symbols.push((symbol, rating_history.len()));
+            continue;
+        }
+
+        // Optional pre-smoothing to damp noise-driven flip-flopping
+        let smoothing_period = config.smoothing_period.unwrap_or(1);
+        if smoothing_period > 1 {
+            let raw: Vec<f64> = rating_history.iter().map(|(_, rating, _, _)| *rating).collect();
+            let smoothed = smooth_series(&raw, config.smoothing, smoothing_period);
+            for ((_, rating, _, _), value) in rating_history.iter_mut().zip(smoothed) {
+                *rating = value;
+            }
+        }
+
+        let rsi = config.rsi_filter.as_ref().map(|filter| {
+            let values: Vec<f64> = rating_history.iter().map(|(_, rating, _, _)| *rating).collect();
+            calculate_rsi(&values, filter.rsi_period)
+        });
+
         let mut symbol_signals = Vec::new();
+        let confirmation_bars = config.signal_confirmation_bars.unwrap_or(1);
+        let mut breach: (Signal, usize) = (Signal::Hold, 0);
 
         // Calculate z-score for each period starting from moving_averages_period
         for window_end in moving_averages_period..rating_history.len() {
-            let current_timestamp = rating_history[window_end].0;
-            let current_rating = rating_history[window_end].1;
+            let (current_timestamp, current_rating, current_rd, _) = rating_history[window_end];
 
             // Extract the window of ratings for this period
             let window_ratings: Vec<f64> = rating_history
                 [(window_end - moving_averages_period)..window_end]
                 .iter()
-                .map(|(_, rating)| *rating)
+                .map(|(_, rating, _, _)| *rating)
                 .collect();
 
             // Calculate z-score using current rating against window
-            let stats = MovingStats::calculate(&window_ratings, current_rating);
+            let (z_score, mean, std_dev) = match config.z_score_method {
+                ZScoreMethod::Sma => {
+                    let stats = MovingStats::calculate(&window_ratings, current_rating);
+                    (stats.z_score, stats.mean, stats.std_dev)
+                }
+                ZScoreMethod::Ema => {
+                    let stats = EmaStats::calculate(&window_ratings, current_rating, ema_alpha);
+                    (stats.z_score, stats.mean, stats.std_dev)
+                }
+            };
+
+            // Generate signal based on z-score threshold, widened for uncertain ratings
+            let signal = classify_signal(z_score, effective_threshold(config, current_rd));
+
+            // Debounce: count consecutive breaches on the same side and hold until confirmed
+            breach = match signal {
+                Signal::Hold => (Signal::Hold, 0),
+                _ if signal == breach.0 => (signal, breach.1 + 1),
+                _ => (signal, 1),
+            };
+            let signal = if breach.1 >= confirmation_bars { signal } else { Signal::Hold };
+
+            // Contrarian mode: enter on downside extremes, exit on upside ones
+            let signal = match (config.invert_signals.unwrap_or(false), signal) {
+                (true, Signal::Buy) => Signal::Sell,
+                (true, Signal::Sell) => Signal::Buy,
+                _ => signal,
+            };
+
+            // Optional RSI confirmation: unconfirmed entries/exits fall back to HOLD
+            let rsi_value = rsi.as_ref().and_then(|values| values[window_end]);
+            let signal = match (&config.rsi_filter, signal) {
+                (Some(filter), Signal::Buy) if !rsi_value.is_some_and(|v| v < filter.rsi_oversold) => Signal::Hold,
+                (Some(filter), Signal::Sell) if !rsi_value.is_some_and(|v| v > filter.rsi_overbought) => Signal::Hold,
+                _ => signal,
+            };
+
+            // Optional match-quality confirmation: a BUY needs a strong recent performance_score
+            let signal = match config.min_performance_score {
+                Some(min_score) if signal == Signal::Buy => {
+                    let window = config.performance_score_window.unwrap_or(5).max(1);
+                    let recent = &rating_history[(window_end + 1).saturating_sub(window)..=window_end];
+                    let mean_score = recent.iter().map(|(_, _, _, score)| score).sum::<f64>() / recent.len() as f64;
+                    if mean_score >= min_score { signal } else { Signal::Hold }
+                }
+                _ => signal,
+            };
 
-            // Generate signal based on z-score threshold
-            let signal = if stats.z_score > threshold {
-                "BUY"  // Strong upside deviation
-            } else if stats.z_score < -threshold {
-                "SELL" // Strong downside deviation
+            let (upper_band, lower_band) = if config.with_bands {
+                (
+                    Some(mean + band_multiplier * std_dev),
+                    Some(mean - band_multiplier * std_dev),
+                )
             } else {
-                "HOLD" // Within neutral band
+                (None, None)
             };
 
-            symbol_signals.push((current_timestamp, stats.z_score, signal.to_string()));
+            symbol_signals.push(ZScoreSignal {
+                timestamp: current_timestamp,
+                z_score,
+                signal,
+                upper_band,
+                lower_band,
+            });
         }
 
         signals.insert(symbol, symbol_signals);
     }
 
-    signals
+    if signals.is_empty() && !skipped_symbols.is_empty() {
+        let (symbol, history_len) = skipped_symbols.swap_remove(0);
+        return Err(BacktestError::InsufficientHistory {
+            symbol,
+            history_len,
+            period: moving_averages_period,
+        });
+    }
+
+    Ok((signals, skipped_symbols))
+}
+
+/// Whether `timestamp_ms` falls in the UTC hour window `[start, end)`; a window with
+/// `start > end` wraps past midnight, e.g. `(22, 6)`.
+fn in_session(timestamp_ms: i64, (start, end): (u8, u8)) -> bool {
+    let Some(hour) = chrono::DateTime::from_timestamp_millis(timestamp_ms).map(|dt| dt.hour() as u8) else {
+        return false;
+    };
+    if start <= end {
+        (start..end).contains(&hour)
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+/// Annualization used when neither `trading_periods_per_year` nor `rating_period_ms` is
+/// known: one period per calendar day.
+const DEFAULT_PERIODS_PER_YEAR: f64 = 365.25;
+
+/// Return periods per year for the risk metrics: `trading_periods_per_year`, else one
+/// per `rating_period_ms` of the calendar's year, else `DEFAULT_PERIODS_PER_YEAR`.
+fn periods_per_year(config: &BacktestConfig) -> f64 {
+    config
+        .trading_periods_per_year
+        .or_else(|| {
+            let period_ms = config.rating_period_ms.filter(|&ms| ms > 0)?;
+            Some(config.market_calendar.year_ms() / period_ms as f64)
+        })
+        .unwrap_or(DEFAULT_PERIODS_PER_YEAR)
+}
+
+const YEAR_MS: f64 = 365.25 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Value of a sorted `(timestamp, value)` curve at `timestamp`: the latest point at or
+/// before it, or the first point before the curve starts; `None` for an empty curve.
+fn step_held(curve: &[(i64, f64)], timestamp: i64) -> Option<f64> {
+    let index = curve.partition_point(|&(point, _)| point <= timestamp);
+    curve.get(index.saturating_sub(1)).map(|&(_, value)| value)
+}
+
+/// Annual risk-free rate in force at `timestamp` on `risk_free_curve`, or the flat
+/// `risk_free_rate` without a curve.
+fn risk_free_rate_at(config: &BacktestConfig, timestamp: i64) -> f64 {
+    config
+        .risk_free_curve
+        .as_deref()
+        .and_then(|curve| step_held(curve, timestamp))
+        .unwrap_or(config.risk_free_rate)
+}
+
+/// Per-period risk-free rate for each return of `equity_curve`, taken at the return's end.
+fn per_period_risk_free(config: &BacktestConfig, equity_curve: &[(i64, f64)], periods_per_year: f64) -> Vec<f64> {
+    equity_curve
+        .iter()
+        .skip(1)
+        .map(|&(timestamp, _)| risk_free_rate_at(config, timestamp) / periods_per_year)
+        .collect()
+}
+
+/// Conversion series for each traded symbol quoted in one of `quote_conversions`'
+/// assets, matched on the longest asset the symbol name ends with.
+fn symbol_conversions(config: &BacktestConfig, symbols: &[String]) -> BTreeMap<String, Vec<(i64, f64)>> {
+    let Some(conversions) = &config.quote_conversions else {
+        return BTreeMap::new();
+    };
+    symbols
+        .iter()
+        .filter_map(|symbol| {
+            let series = conversions
+                .iter()
+                .filter(|(asset, _)| symbol.ends_with(asset.as_str()) && symbol.len() > asset.len())
+                .max_by_key(|(asset, _)| asset.len())?
+                .1;
+            Some((symbol.clone(), series.clone()))
+        })
+        .collect()
+}
+
+/// Sharpe ratio of per-period `returns` in excess of the matching per-period
+/// `risk_free` rates, annualized by sqrt(periods_per_year); 0 when the returns have no
+/// dispersion.
+fn annualized_sharpe(returns: &[f64], risk_free: &[f64], periods_per_year: f64) -> f64 {
+    if returns.is_empty() {
+        return 0.0;
+    }
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let std_dev = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
+    let risk_free_rate = risk_free.iter().sum::<f64>() / risk_free.len() as f64;
+    if std_dev > 0.0 {
+        (mean - risk_free_rate) / std_dev * periods_per_year.sqrt()
+    } else {
+        0.0
+    }
+}
+
+/// Summed holding time of every position (overlapping positions each count), with
+/// positions still open at the end held until the last equity point.
+fn time_in_market_ms(portfolio: &Portfolio, calendar: MarketCalendar) -> i64 {
+    let mut entries: HashMap<&str, i64> = HashMap::new();
+    let mut held = 0;
+    for order in portfolio.orders.iter().filter(|o| o.reason != ExitReason::Rebalance) {
+        match order.side {
+            OrderSide::Buy => {
+                entries.insert(order.symbol.as_str(), order.timestamp);
+            }
+            OrderSide::Sell => {
+                if let Some(entry_time) = entries.remove(order.symbol.as_str()) {
+                    held += calendar.elapsed_ms(entry_time, order.timestamp);
+                }
+            }
+        }
+    }
+    let last = portfolio.equity_curve.last().map_or(0, |&(timestamp, _)| timestamp);
+    held + entries.values().map(|&entry_time| calendar.elapsed_ms(entry_time, last).max(0)).sum::<i64>()
+}
+
+/// The equity curve that risk metrics are computed over: with a warm-up it starts from
+/// the last warm-up point, so only steady-state bars contribute.
+fn risk_curve<'a>(portfolio: &'a Portfolio, config: &BacktestConfig) -> &'a [(i64, f64)] {
+    let risk_start = match (config.warmup_bars, portfolio.warmup_end) {
+        (None, _) => 0,
+        (Some(_), Some(end)) => portfolio.equity_curve.partition_point(|&(timestamp, _)| timestamp < end).saturating_sub(1),
+        (Some(_), None) => portfolio.equity_curve.len() - 1,
+    };
+    &portfolio.equity_curve[risk_start..]
 }
 
 fn calculate_performance_metrics(
     portfolio: &Portfolio,
     initial_value: f64,
-    start_time: i64,
-    end_time: i64,
+    config: &BacktestConfig,
 ) -> PerformanceMetrics {
+    let trading_periods_per_year = config.trading_periods_per_year;
     if portfolio.equity_curve.is_empty() {
         return PerformanceMetrics::default();
     }
@@ -247,16 +1084,32 @@ fn calculate_performance_metrics(
     let final_value = portfolio.equity_curve.last().unwrap().1;
     let total_return = (final_value - initial_value) / initial_value;
     
-    // Calculate annualized return
-    let years = (end_time - start_time) as f64 / (365.25 * 24.0 * 60.0 * 60.0 * 1000.0);
-    let annualized_return = if years > 0.0 {
-        (final_value / initial_value).powf(1.0 / years) - 1.0
-    } else {
-        0.0
+    let periods_per_year = periods_per_year(config);
+
+    // Calculate annualized return. With an explicit period count (e.g. 252 trading days)
+    // years are measured in elapsed periods rather than calendar time; on the
+    // time-in-market basis only the time positions were held counts.
+    let years = match (config.annualization_basis, trading_periods_per_year) {
+        (AnnualizationBasis::TimeInMarket, _) => {
+            time_in_market_ms(portfolio, config.market_calendar) as f64 / config.market_calendar.year_ms()
+        }
+        (AnnualizationBasis::Calendar, Some(periods)) => {
+            portfolio.equity_curve.len().saturating_sub(1) as f64 / periods
+        }
+        (AnnualizationBasis::Calendar, None) => {
+            config.market_calendar.elapsed_ms(config.start_time, config.end_time) as f64
+                / config.market_calendar.year_ms()
+        }
+    };
+    let annualized_return = match config.return_basis {
+        _ if years <= 0.0 => 0.0,
+        ReturnBasis::Compounded => (final_value / initial_value).powf(1.0 / years) - 1.0,
+        ReturnBasis::Simple => total_return / years,
     };
 
-    // Calculate returns for risk metrics
-    let returns: Vec<f64> = portfolio.equity_curve
+    // Calculate returns for risk metrics over the steady-state part of the curve
+    let risk_curve = risk_curve(portfolio, config);
+    let returns: Vec<f64> = risk_curve
         .windows(2)
         .map(|w| {
             let prev_value = w[0].1;
@@ -269,19 +1122,41 @@ fn calculate_performance_metrics(
         })
         .collect();
 
-    let mean_return = returns.iter().sum::<f64>() / returns.len() as f64;
-    let variance = returns.iter()
-        .map(|r| (r - mean_return).powi(2))
-        .sum::<f64>() / returns.len() as f64;
+    // A single-point equity curve has no returns; without this guard the means below
+    // are 0/0 = NaN and poison every risk metric
+    let (mean_return, variance) = if returns.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let mean_return = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter()
+            .map(|r| (r - mean_return).powi(2))
+            .sum::<f64>() / returns.len() as f64;
+        (mean_return, variance)
+    };
     let volatility = variance.sqrt();
+    let annualized_volatility = volatility * periods_per_year.sqrt();
 
-    // Sharpe Ratio (assuming 2% risk-free rate)
-    let risk_free_rate = 0.02 / 365.25; // Daily risk-free rate
-    let sharpe_ratio = if volatility > 0.0 {
-        (mean_return - risk_free_rate) / volatility * (365.25_f64).sqrt()
-    } else {
+    // Sharpe Ratio against the per-period risk-free rate in force at each return's end
+    let risk_free = per_period_risk_free(config, risk_curve, periods_per_year);
+    let risk_free_rate = if risk_free.is_empty() {
         0.0
+    } else {
+        risk_free.iter().sum::<f64>() / risk_free.len() as f64
     };
+    let sharpe_ratio = annualized_sharpe(&returns, &risk_free, periods_per_year);
+
+    // Rolling Sharpe over each trailing window of returns, stamped with the window's
+    // last equity point; nothing until a full window is available
+    let rolling_sharpe = config.rolling_sharpe_window.filter(|&window| window > 0).map(|window| {
+        returns
+            .windows(window)
+            .enumerate()
+            .map(|(i, slice)| {
+                let timestamp = risk_curve[i + window].0;
+                (timestamp, annualized_sharpe(slice, &risk_free[i..i + window], periods_per_year))
+            })
+            .collect()
+    });
 
     // Sortino Ratio (downside deviation only)
     let negative_returns: Vec<f64> = returns.iter()
@@ -299,7 +1174,7 @@ fn calculate_performance_metrics(
     
     let downside_deviation = downside_variance.sqrt();
     let sortino_ratio = if downside_deviation > 0.0 {
-        (mean_return - risk_free_rate) / downside_deviation * (365.25_f64).sqrt()
+        (mean_return - risk_free_rate) / downside_deviation * periods_per_year.sqrt()
     } else {
         0.0
     };
@@ -307,8 +1182,9 @@ fn calculate_performance_metrics(
     // Max Drawdown
     let mut peak = initial_value;
     let mut max_drawdown = 0.0;
+    let mut drawdown_curve = Vec::with_capacity(portfolio.equity_curve.len());
     
-    for (_, value) in &portfolio.equity_curve {
+    for (timestamp, value) in &portfolio.equity_curve {
         if *value > peak {
             peak = *value;
         }
@@ -316,18 +1192,20 @@ fn calculate_performance_metrics(
         if drawdown > max_drawdown {
             max_drawdown = drawdown;
         }
+        drawdown_curve.push((*timestamp, drawdown));
     }
 
-    // Trade statistics
+    // Trade statistics (rebalance trims/top-ups are not round trips, warm-up trades don't count)
+    let is_exit = |o: &BacktestOrder| o.side == OrderSide::Sell && o.reason != ExitReason::Rebalance && !o.warmup;
     let profitable_trades = portfolio.orders
         .iter()
-        .filter(|o| o.side == "SELL")
+        .filter(|o| is_exit(o))
         .filter(|o| o.profit_loss.unwrap_or(0.0) > 0.0)
         .count();
     
     let total_trades = portfolio.orders
         .iter()
-        .filter(|o| o.side == "SELL")
+        .filter(|o| is_exit(o))
         .count();
     
     let win_ratio = if total_trades > 0 {
@@ -339,14 +1217,14 @@ fn calculate_performance_metrics(
     // Profit Factor
     let gross_profit: f64 = portfolio.orders
         .iter()
-        .filter(|o| o.side == "SELL")
+        .filter(|o| is_exit(o))
         .filter_map(|o| o.profit_loss)
         .filter(|&pl| pl > 0.0)
         .sum();
 
     let gross_loss: f64 = portfolio.orders
         .iter()
-        .filter(|o| o.side == "SELL")
+        .filter(|o| is_exit(o))
         .filter_map(|o| o.profit_loss)
         .filter(|&pl| pl < 0.0)
         .map(|pl| pl.abs())
@@ -360,16 +1238,39 @@ fn calculate_performance_metrics(
         0.0
     };
 
+    // Average win/loss and expectancy per trade
+    let losing_trades = portfolio.orders
+        .iter()
+        .filter(|o| is_exit(o))
+        .filter(|o| o.profit_loss.unwrap_or(0.0) < 0.0)
+        .count();
+    let avg_win = if profitable_trades > 0 {
+        gross_profit / profitable_trades as f64
+    } else {
+        0.0
+    };
+    let avg_loss = if losing_trades > 0 {
+        -gross_loss / losing_trades as f64
+    } else {
+        0.0
+    };
+    let expectancy = if total_trades > 0 {
+        win_ratio * avg_win - (1.0 - win_ratio) * avg_loss.abs()
+    } else {
+        0.0
+    };
+
     // Average trade duration
     let mut trade_durations = Vec::new();
     let mut open_positions: HashMap<String, i64> = HashMap::new();
 
-    for order in &portfolio.orders {
-        if order.side == "BUY" {
+    for order in portfolio.orders.iter().filter(|o| o.reason != ExitReason::Rebalance && !o.warmup) {
+        if order.side == OrderSide::Buy {
             open_positions.insert(order.symbol.clone(), order.timestamp);
-        } else if order.side == "SELL" {
+        } else if order.side == OrderSide::Sell {
             if let Some(entry_time) = open_positions.remove(&order.symbol) {
-                let duration = (order.timestamp - entry_time) as f64 / (1000.0 * 60.0 * 60.0); // hours
+                let duration = config.market_calendar.elapsed_ms(entry_time, order.timestamp) as f64
+                    / (1000.0 * 60.0 * 60.0); // hours
                 trade_durations.push(duration);
             }
         }
@@ -381,40 +1282,219 @@ fn calculate_performance_metrics(
         0.0
     };
 
+    // Best/worst closed trade
+    let trade_percents: Vec<f64> = portfolio.orders
+        .iter()
+        .filter(|o| is_exit(o))
+        .filter_map(|o| o.profit_loss_percent)
+        .collect();
+    let best_trade_percent = trade_percents.iter().copied().reduce(f64::max).unwrap_or(0.0);
+    let worst_trade_percent = trade_percents.iter().copied().reduce(f64::min).unwrap_or(0.0);
+
+    // Per-symbol attribution of closed trades
+    let mut symbol_trades: HashMap<&str, (usize, usize, f64)> = HashMap::new();
+    for order in portfolio.orders.iter().filter(|o| is_exit(o)) {
+        let profit_loss = order.profit_loss.unwrap_or(0.0);
+        let (trades, wins, net_pnl) = symbol_trades.entry(order.symbol.as_str()).or_default();
+        *trades += 1;
+        *wins += usize::from(profit_loss > 0.0);
+        *net_pnl += profit_loss;
+    }
+    let per_symbol: BTreeMap<String, SymbolStats> = symbol_trades
+        .into_iter()
+        .map(|(symbol, (total_trades, wins, net_pnl))| {
+            let stats = SymbolStats {
+                total_trades,
+                win_ratio: wins as f64 / total_trades as f64,
+                net_pnl,
+            };
+            (symbol.to_string(), stats)
+        })
+        .collect();
+
     PerformanceMetrics {
         total_return,
         annualized_return,
         sharpe_ratio,
         sortino_ratio,
-        alpha: 0.0, // Would need benchmark comparison
+        annualized_volatility,
         max_drawdown,
         win_ratio,
         total_trades,
         profit_factor,
         avg_trade_duration,
+        best_trade_percent,
+        worst_trade_percent,
+        avg_win,
+        avg_loss,
+        expectancy,
+        drawdown_curve,
+        per_symbol,
+        rolling_sharpe,
     }
 }
 
-#[derive(Debug, Default)]
-struct PerformanceMetrics {
-    total_return: f64,
-    annualized_return: f64,
-    sharpe_ratio: f64,
-    sortino_ratio: f64,
-    alpha: f64,
-    max_drawdown: f64,
-    win_ratio: f64,
-    total_trades: usize,
-    profit_factor: f64,
-    avg_trade_duration: f64,
+/// Benchmark price series, chronological: the symbol's kline closes when klines are
+/// supplied, otherwise its rating-derived prices.
+fn benchmark_prices(symbol: &str, ratings: &[GlickoRating], klines: &[KlineData]) -> Result<Vec<(i64, f64)>> {
+    let mut prices: Vec<(i64, f64)> = if klines.is_empty() {
+        ratings
+            .iter()
+            .filter(|r| r.symbol == symbol)
+            .map(|r| (r.timestamp, 100.0 * (r.rating / 1500.0)))
+            .collect()
+    } else {
+        let prices: Vec<(i64, f64)> = klines
+            .iter()
+            .filter(|k| k.symbol == symbol)
+            .map(|k| (k.open_time, k.close))
+            .collect();
+        if prices.is_empty() {
+            return Err(format!("No klines for benchmark symbol {}", symbol).into());
+        }
+        prices
+    };
+    prices.sort_by_key(|&(timestamp, _)| timestamp);
+    Ok(prices)
 }
 
-/// Run a complete backtest simulation with Z-score signals and OCO exit logic.
-///
-/// ALGORITHM FLOW:
-/// 1. Calculate Z-score signals from Glicko-2 ratings
-///    - Z-score = (current_rating - moving_avg) / std_dev
-///    - BUY signal when z_score > threshold
+/// Per-period strategy and buy-and-hold `benchmark` returns over the same equity
+/// intervals, with the benchmark forward-filled onto the equity timestamps. Equity
+/// points before the first benchmark price are ignored.
+fn aligned_returns(equity_curve: &[(i64, f64)], benchmark: &[(i64, f64)]) -> (Vec<f64>, Vec<f64>) {
+    aligned_intervals(equity_curve, benchmark)
+        .into_iter()
+        .map(|(_, strategy, market)| (strategy, market))
+        .unzip()
+}
+
+/// `aligned_returns` with the index of each equity interval (`equity_curve.windows(2)`).
+fn aligned_intervals(equity_curve: &[(i64, f64)], benchmark: &[(i64, f64)]) -> Vec<(usize, f64, f64)> {
+    let benchmark_at = |timestamp: i64| {
+        let index = benchmark.partition_point(|&(t, _)| t <= timestamp);
+        index.checked_sub(1).map(|i| benchmark[i].1)
+    };
+    equity_curve
+        .windows(2)
+        .enumerate()
+        .filter_map(|(i, w)| {
+            let (prev, curr) = (benchmark_at(w[0].0)?, benchmark_at(w[1].0)?);
+            (w[0].1 > 0.0 && prev > 0.0).then(|| (i, w[1].1 / w[0].1 - 1.0, curr / prev - 1.0))
+        })
+        .collect()
+}
+
+/// Annualized Jensen's alpha and beta of the equity curve against buy-and-hold of
+/// `benchmark` (see `aligned_returns`); `(0, 0)` without enough overlap. `risk_free`
+/// holds the per-period rate for each equity interval, as used by the Sharpe ratio.
+fn alpha_beta(
+    equity_curve: &[(i64, f64)],
+    benchmark: &[(i64, f64)],
+    risk_free: &[f64],
+    periods_per_year: f64,
+) -> (f64, f64) {
+    let intervals = aligned_intervals(equity_curve, benchmark);
+    if intervals.len() < 2 {
+        return (0.0, 0.0);
+    }
+    let strategy: Vec<f64> = intervals.iter().map(|&(_, strategy, _)| strategy).collect();
+    let market: Vec<f64> = intervals.iter().map(|&(_, _, market)| market).collect();
+
+    let n = strategy.len() as f64;
+    let mean_strategy = strategy.iter().sum::<f64>() / n;
+    let mean_market = market.iter().sum::<f64>() / n;
+    let covariance = strategy
+        .iter()
+        .zip(&market)
+        .map(|(s, m)| (s - mean_strategy) * (m - mean_market))
+        .sum::<f64>()
+        / n;
+    let market_variance = market.iter().map(|m| (m - mean_market).powi(2)).sum::<f64>() / n;
+    let beta = if market_variance > 0.0 { covariance / market_variance } else { 0.0 };
+
+    // Mean risk-free rate over the same intervals the returns cover
+    let risk_free_rate = intervals.iter().map(|&(i, _, _)| risk_free[i]).sum::<f64>() / n;
+    let alpha = ((mean_strategy - risk_free_rate) - beta * (mean_market - risk_free_rate)) * periods_per_year;
+    (alpha, beta)
+}
+
+/// Max drawdown of the strategy-minus-benchmark equity difference, with the benchmark
+/// bought with the strategy's equity at the first point it has a price, relative to that
+/// starting equity.
+fn relative_max_drawdown(equity_curve: &[(i64, f64)], benchmark: &[(i64, f64)]) -> f64 {
+    let benchmark_at = |timestamp: i64| {
+        let index = benchmark.partition_point(|&(t, _)| t <= timestamp);
+        index.checked_sub(1).map(|i| benchmark[i].1)
+    };
+    let mut points = equity_curve
+        .iter()
+        .filter_map(|&(timestamp, value)| Some((value, benchmark_at(timestamp)?)));
+    let Some((start_equity, start_price)) = points.next().filter(|&(equity, price)| equity > 0.0 && price > 0.0)
+    else {
+        return 0.0;
+    };
+
+    let mut peak = 0.0_f64;
+    let mut max_drawdown = 0.0_f64;
+    for (value, price) in points {
+        let difference = value - start_equity * price / start_price;
+        peak = peak.max(difference);
+        max_drawdown = max_drawdown.max(peak - difference);
+    }
+    max_drawdown / start_equity
+}
+
+/// `(upside_capture, downside_capture)`: the strategy's mean return over the periods the
+/// benchmark rose (fell), divided by the benchmark's mean return over those periods. A
+/// side without any such period reports 0.
+fn capture_ratios(equity_curve: &[(i64, f64)], benchmark: &[(i64, f64)]) -> (f64, f64) {
+    let (strategy, market) = aligned_returns(equity_curve, benchmark);
+    let capture = |up: bool| {
+        let periods: Vec<(f64, f64)> = strategy
+            .iter()
+            .zip(&market)
+            .filter(|(_, &m)| if up { m > 0.0 } else { m < 0.0 })
+            .map(|(&s, &m)| (s, m))
+            .collect();
+        if periods.is_empty() {
+            return 0.0;
+        }
+        let n = periods.len() as f64;
+        let mean_strategy = periods.iter().map(|(s, _)| s).sum::<f64>() / n;
+        let mean_market = periods.iter().map(|(_, m)| m).sum::<f64>() / n;
+        mean_strategy / mean_market
+    };
+    (capture(true), capture(false))
+}
+
+#[derive(Debug, Default)]
+struct PerformanceMetrics {
+    total_return: f64,
+    annualized_return: f64,
+    sharpe_ratio: f64,
+    sortino_ratio: f64,
+    annualized_volatility: f64,
+    max_drawdown: f64,
+    win_ratio: f64,
+    total_trades: usize,
+    profit_factor: f64,
+    avg_trade_duration: f64,
+    best_trade_percent: f64,
+    worst_trade_percent: f64,
+    avg_win: f64,
+    avg_loss: f64,
+    expectancy: f64,
+    drawdown_curve: Vec<(i64, f64)>,
+    per_symbol: BTreeMap<String, SymbolStats>,
+    rolling_sharpe: Option<Vec<(i64, f64)>>,
+}
+
+/// Run a complete backtest simulation with Z-score signals and OCO exit logic.
+///
+/// ALGORITHM FLOW:
+/// 1. Calculate Z-score signals from Glicko-2 ratings
+///    - Z-score = (current_rating - moving_avg) / std_dev
+///    - BUY signal when z_score > threshold
 ///    - SELL signal when z_score < -threshold
 ///
 /// 2. For each signal, execute entry/exit:
@@ -428,6 +1508,7 @@ struct PerformanceMetrics {
 /// SLIPPAGE MODEL:
 /// - Entry: Assumed at signal price (no slippage modeled for simplicity)
 /// - Exit: Assumed at actual price level (SL/TP/Z-score)
+/// - Spread: with `half_spread_bps`, buys fill at the ask and sells at the bid
 /// - Note: In live trading, actual execution may differ due to:
 ///   - Order book depth
 ///   - Market impact
@@ -437,188 +1518,849 @@ struct PerformanceMetrics {
 /// - Each BUY signal allocates 95% of available cash
 /// - Quantity = (cash * 0.95) / entry_price
 /// - Risk per trade = stop_loss_percent of position
+/// - With `drawdown_throttle`, the allocation is cut by `throttle_factor` while equity
+///   is in a deep enough drawdown from its peak
+/// - With `leverage`, notional = margin * leverage and the position is liquidated
+///   (EXIT_LIQUIDATION) once equity falls to `maintenance_margin_percent` of notional
 ///
 /// ORDER TYPES SIMULATED:
 /// - BUY: Market order at signal price
 /// - SELL via Z-score: Market order at signal price (EXIT_ZSCORE)
 /// - SELL via OCO: Market order at stop/profit level (EXIT_STOP/EXIT_PROFIT)
 pub fn run_backtest(config: BacktestConfig, ratings: Vec<GlickoRating>) -> Result<BacktestResult> {
-    let initial_cash = 10000.0; // Starting with $10,000
-    let mut portfolio = Portfolio::new(initial_cash);
-
-    // Calculate z-score signals
-    let signals = calculate_z_score_signals(
-        &ratings,
-        config.moving_averages,
-        config.z_score_threshold,
-    );
+    run_backtest_with_klines(config, ratings, &[])
+}
 
-    // Get price data from ratings (simplified - would normally use klines)
-    let mut price_data: HashMap<String, Vec<(i64, f64)>> = HashMap::new();
-    let symbol = format!("{}USDT", config.base_asset);
-    
-    // Simulate price movements based on Glicko ratings
-    // This is a simplified approach - in reality, you'd use actual price data
-    for rating in &ratings {
-        if rating.symbol == symbol {
-            // Simulate price based on rating (this is just for demonstration)
-            let simulated_price = 100.0 * (rating.rating / 1500.0);
-            price_data
-                .entry(symbol.clone())
-                .or_insert_with(Vec::new)
-                .push((rating.timestamp, simulated_price));
+/// `run_backtest`, but trading at kline closes instead of rating-derived prices.
+///
+/// Each rating is priced from the same symbol's kline with `open_time` equal to the
+/// rating timestamp: `signal_price` marks positions and triggers stops, `fill_price` is
+/// what orders execute at (both the close by default). Ratings without a matching kline
+/// are not traded. With no klines this is exactly `run_backtest`.
+pub fn run_backtest_with_klines(
+    config: BacktestConfig,
+    ratings: Vec<GlickoRating>,
+    klines: &[KlineData],
+) -> Result<BacktestResult> {
+    BacktestStepper::new(config, ratings, klines)?.finish()
+}
+
+/// State after one timestamp of the simulation, as yielded by `BacktestStepper`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestStep {
+    pub timestamp: i64,
+    /// Price of every symbol with a bar at this timestamp
+    pub prices: BTreeMap<String, f64>,
+    pub cash: f64,
+    /// Portfolio value after this timestamp (the equity-curve point)
+    pub equity: f64,
+    /// Positions held after this timestamp, marked to the latest prices
+    pub open_positions: Vec<OpenPositionReport>,
+    /// Orders filled during this timestamp
+    pub orders: Vec<BacktestOrder>,
+}
+
+/// Bar-by-bar driver behind `run_backtest_with_klines`.
+///
+/// Each `next_step` evaluates every bar at the next timestamp, exactly as the full run
+/// would, and returns the resulting state; `finish` runs whatever is left and produces
+/// the same `BacktestResult` as `run_backtest_with_klines`.
+pub struct BacktestStepper<'a> {
+    config: BacktestConfig,
+    ratings: Vec<GlickoRating>,
+    klines: &'a [KlineData],
+    started: Instant,
+    initial_cash: f64,
+    portfolio: Portfolio,
+    skipped_symbols: Vec<(String, usize)>,
+    symbols: Vec<String>,
+    timeline: std::collections::btree_map::IntoIter<i64, BTreeMap<String, Bar>>,
+    price_series: Option<Vec<PricePoint>>,
+    rebalance_interval: Option<i64>,
+    next_rebalance: Option<i64>,
+    funding: Option<(f64, i64)>,
+    performance_fee: Option<(f64, i64)>,
+    previous_timestamp: Option<i64>,
+    current_prices: HashMap<String, f64>,
+    latest_ratings: HashMap<String, f64>,
+    price_history: HashMap<String, Vec<f64>>,
+    last_entry_bar: HashMap<String, usize>,
+    circuit_breaker_triggered: bool,
+    /// Steps so far with at least one bar carrying a signal, for `warmup_bars`
+    signal_steps: usize,
+    quote_conversions: BTreeMap<String, Vec<(i64, f64)>>,
+    true_ranges: HashMap<String, Vec<f64>>,
+}
+
+impl<'a> BacktestStepper<'a> {
+    pub fn new(mut config: BacktestConfig, ratings: Vec<GlickoRating>, klines: &'a [KlineData]) -> Result<Self> {
+        let started = Instant::now();
+        if let (StopMode::Percent, Some(profit_percent), Some(risk_reward_ratio)) =
+            (&config.stop_mode, config.profit_percent, config.risk_reward_ratio)
+        {
+            return Err(BacktestError::AmbiguousTakeProfit { profit_percent, risk_reward_ratio }.into());
+        }
+        let (ratings, duplicate_ratings) = dedupe_ratings(ratings, config.duplicate_ratings)?;
+        if config.rating_period_ms.is_none() {
+            config.rating_period_ms = infer_interval(klines);
+        }
+        let initial_cash = 10000.0; // Starting with $10,000
+        let mut portfolio = Portfolio::new(initial_cash);
+        portfolio.equity_curve[0].0 = config.start_time;
+        portfolio.pnl_curve[0].0 = config.start_time;
+        portfolio.half_spread = config.half_spread_bps.unwrap_or(0.0) / 10_000.0;
+        portfolio.drawdown_throttle = config.drawdown_throttle.clone();
+        portfolio.cash_yield_annual = config.cash_yield_annual;
+        portfolio.cash_decimals = config.cash_decimals;
+        if duplicate_ratings > 0 {
+            portfolio
+                .diagnostics
+                .push(format!("Dropped {} duplicate ratings (same symbol and timestamp)", duplicate_ratings));
+        }
+
+        // Calculate z-score signals
+        let (signals, skipped_symbols) = calculate_z_score_signals_with_skipped(&ratings, &config)?;
+        tracing::debug!(
+            symbols = signals.len(),
+            signals = signals.values().map(Vec::len).sum::<usize>(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Generated z-score signals"
+        );
+
+        // Get price data from ratings (simplified - would normally use klines)
+        let symbols = config.traded_symbols();
+
+        // Timestamp-keyed merge of price bars and signals. Every price bar is evaluated in
+        // chronological order (symbols at the same timestamp in name order); a bar only
+        // carries a signal if a z-score exists at exactly that timestamp, so bars before the
+        // first full moving-average window are HOLD.
+        let mut timeline: BTreeMap<i64, BTreeMap<String, Bar>> = BTreeMap::new();
+
+        let klines_by_bar: HashMap<(&str, i64), &KlineData> = klines
+            .iter()
+            .map(|k| ((k.symbol.as_str(), k.open_time), k))
+            .collect();
+
+        // Simulate price movements based on Glicko ratings
+        // This is a simplified approach - in reality, you'd use actual price data
+        for rating in &ratings {
+            if symbols.contains(&rating.symbol) {
+                let (simulated_price, fill_price, low, high) = if klines.is_empty() {
+                    // Simulate price based on rating (this is just for demonstration)
+                    let price = 100.0 * (rating.rating / 1500.0);
+                    (price, price, price, price)
+                } else {
+                    match klines_by_bar.get(&(rating.symbol.as_str(), rating.timestamp)) {
+                        Some(kline) => {
+                            let price = config.signal_price.of(kline);
+                            (price, config.fill_price.of(kline), kline.low.min(price), kline.high.max(price))
+                        }
+                        None => continue,
+                    }
+                };
+                timeline.entry(rating.timestamp).or_default().insert(
+                    rating.symbol.clone(),
+                    Bar {
+                        price: simulated_price,
+                        fill_price,
+                        low,
+                        high,
+                        rating: rating.rating,
+                        signal: None,
+                        z_score: None,
+                    },
+                );
+            }
+        }
+
+        for (symbol, series) in &signals {
+            for point in series {
+                if let Some(bar) = timeline
+                    .get_mut(&point.timestamp)
+                    .and_then(|bars| bars.get_mut(symbol.as_str()))
+                {
+                    bar.signal = Some(point.signal);
+                    bar.z_score = Some(point.z_score);
+                }
+            }
         }
+
+        let rebalance_interval = config.rebalance_interval_ms.filter(|&interval| interval > 0);
+        let next_rebalance = rebalance_interval
+            .zip(timeline.keys().next())
+            .map(|(interval, &first)| first + interval);
+        let funding = config.funding_rate.zip(config.funding_interval_ms.filter(|&interval| interval > 0));
+        let performance_fee = config.performance_fee_percent.map(|fee_percent| {
+            let interval = config.performance_fee_interval_ms.filter(|&interval| interval > 0);
+            (fee_percent, interval.unwrap_or(YEAR_MS as i64))
+        });
+
+        let price_series = config.include_prices.then(|| {
+            timeline
+                .iter()
+                .flat_map(|(&timestamp, bars)| {
+                    bars.iter().map(move |(symbol, bar)| PricePoint {
+                        timestamp,
+                        symbol: symbol.clone(),
+                        price: bar.price,
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let quote_conversions = symbol_conversions(&config, &symbols);
+
+        Ok(Self {
+            config,
+            ratings,
+            klines,
+            started,
+            initial_cash,
+            portfolio,
+            skipped_symbols,
+            symbols,
+            timeline: timeline.into_iter(),
+            price_series,
+            rebalance_interval,
+            next_rebalance,
+            funding,
+            performance_fee,
+            previous_timestamp: None,
+            current_prices: HashMap::new(),
+            latest_ratings: HashMap::new(),
+            price_history: HashMap::new(),
+            last_entry_bar: HashMap::new(),
+            circuit_breaker_triggered: false,
+            signal_steps: 0,
+            quote_conversions,
+            true_ranges: HashMap::new(),
+        })
     }
 
-    // Sort price data by timestamp
-    if let Some(prices) = price_data.get_mut(&symbol) {
-        prices.sort_by_key(|(timestamp, _)| *timestamp);
+    /// Evaluate the bars at the next timestamp; `None` once the timeline is exhausted.
+    pub fn next_step(&mut self) -> Option<BacktestStep> {
+        let (timestamp, bars) = self.timeline.next()?;
+        let orders_before = self.portfolio.orders.len();
+        self.step(timestamp, &bars);
+
+        let open_positions = self
+            .portfolio
+            .positions
+            .values()
+            .map(|position| open_position_report(position, self.current_prices[&position.symbol]))
+            .collect();
+        Some(BacktestStep {
+            timestamp,
+            prices: bars.iter().map(|(symbol, bar)| (symbol.clone(), bar.price)).collect(),
+            cash: self.portfolio.cash,
+            equity: self.portfolio.equity_curve.last().map_or(self.portfolio.cash, |&(_, value)| value),
+            open_positions,
+            orders: self.portfolio.orders[orders_before..].to_vec(),
+        })
     }
 
-    // Run backtest simulation
-    if let (Some(symbol_signals), Some(symbol_prices)) = 
-        (signals.get(&symbol), price_data.get(&symbol)) {
-        
-        let mut signal_idx = 0;
-        let mut price_idx = 0;
+    fn step(&mut self, timestamp: i64, bars: &BTreeMap<String, Bar>) {
+        let config = &self.config;
+        let portfolio = &mut self.portfolio;
+
+        // Cross-quoted symbols are valued at this timestamp's conversion rates
+        for (symbol, series) in &self.quote_conversions {
+            if let Some(rate) = step_held(series, timestamp) {
+                portfolio.quote_rates.insert(symbol.clone(), rate);
+            }
+        }
+
+        for (symbol, bar) in bars {
+            self.current_prices.insert(symbol.clone(), bar.price);
+            self.latest_ratings.insert(symbol.clone(), bar.rating);
+            let history = self.price_history.entry(symbol.clone()).or_default();
+            if let StopMode::AtrMultiple { atr_period, .. } = config.stop_mode {
+                // True range against the previous bar's price (just high - low on the first)
+                let previous = history.last().copied().unwrap_or(bar.price);
+                let true_range = (bar.high - bar.low).max((bar.high - previous).abs()).max((bar.low - previous).abs());
+                let ranges = self.true_ranges.entry(symbol.clone()).or_default();
+                ranges.push(true_range);
+                let window = &ranges[ranges.len().saturating_sub(atr_period.max(1))..];
+                portfolio.atr.insert(symbol.clone(), window.iter().sum::<f64>() / window.len() as f64);
+            }
+            history.push(bar.price);
+        }
 
-        while signal_idx < symbol_signals.len() && price_idx < symbol_prices.len() {
-            let (signal_time, z_score, signal) = &symbol_signals[signal_idx];
-            let (price_time, price) = symbol_prices[price_idx];
+        // === FUNDING SETTLEMENT ===
+        // Positions carried over a funding timestamp since the previous bar pay funding once
+        // per settlement crossed, marked at the latest known price
+        if let (Some((funding_rate, interval)), Some(previous)) = (self.funding, self.previous_timestamp) {
+            let settlements = timestamp.div_euclid(interval) - previous.div_euclid(interval);
+            for _ in 0..settlements {
+                portfolio.apply_funding(funding_rate, &self.current_prices, timestamp);
+            }
+        }
+        // The performance fee crystallizes once at the end of a bar that crosses a fee
+        // period boundary
+        let fee_due = self
+            .performance_fee
+            .zip(self.previous_timestamp)
+            .filter(|((_, interval), previous)| timestamp.div_euclid(*interval) > previous.div_euclid(*interval))
+            .map(|((fee_percent, _), _)| fee_percent);
+        self.previous_timestamp = Some(timestamp);
+
+        // === WARM-UP ===
+        // The first `warmup_bars` signal-carrying steps trade as usual but are flagged
+        if let Some(warmup_bars) = config.warmup_bars {
+            self.signal_steps += usize::from(bars.values().any(|bar| bar.signal.is_some()));
+            portfolio.in_warmup = self.signal_steps <= warmup_bars;
+            if !portfolio.in_warmup && portfolio.warmup_end.is_none() {
+                portfolio.warmup_end = Some(timestamp);
+            }
+        }
+
+        // Bars at one timestamp are processed in entry-priority order (ties by symbol),
+        // so which candidate gets funded first never depends on map iteration order
+        let mut ordered: Vec<(&str, &Bar)> = bars.iter().map(|(symbol, bar)| (symbol.as_str(), bar)).collect();
+        match config.entry_priority {
+            EntryPriority::Alphabetical => {}
+            EntryPriority::HighestZScore => ordered.sort_by(|a, b| {
+                let magnitude = |bar: &Bar| bar.z_score.map_or(0.0, f64::abs);
+                magnitude(b.1).total_cmp(&magnitude(a.1))
+            }),
+            EntryPriority::HighestRating => ordered.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating)),
+        }
+
+        for (symbol, bar) in ordered {
+            let price = bar.price;
+            let fill_price = bar.fill_price;
+            let bar_index = self.price_history[symbol].len();
+            let too_soon = config
+                .min_bars_between_trades
+                .zip(self.last_entry_bar.get(symbol))
+                .is_some_and(|(gap, &last)| bar_index - last < gap);
 
-            // Align timestamps
-            if signal_time < &price_time {
-                signal_idx += 1;
-                continue;
-            } else if &price_time < signal_time {
-                price_idx += 1;
-                continue;
+            // === LIQUIDATION CHECK ===
+            // A leveraged position whose equity drops to the maintenance margin is force-closed
+            // at the liquidation price, ahead of signals and any stop that sits below it. Longs
+            // are checked against the bar low, so an intrabar breach counts even if it closes above.
+            // A stop above the liquidation price is reached first and is left to the OCO check
+            let liquidation = portfolio.positions
+                .get(symbol)
+                .and_then(|pos| {
+                    pos.liquidation_price
+                        .filter(|&liquidation_price| liquidation_price >= pos.stop_loss_price)
+                })
+                .filter(|&liquidation_price| bar.low <= liquidation_price);
+            if let Some(liquidation_price) = liquidation {
+                portfolio.close_position(symbol, liquidation_price, timestamp, ExitReason::ExitLiquidation);
             }
 
             // === SIGNAL EXECUTION ===
             // Process entry/exit signals from Z-score reversals
-            let current_prices = [(symbol.clone(), price)].iter().cloned().collect();
-
-            match signal.as_str() {
-                "BUY" => {
-                    // Z-score BUY signal: enter new position with OCO levels
-                    portfolio.open_position(
-                        symbol.clone(),
-                        price,
-                        *signal_time,
-                        &config,
-                        0.95, // Use 95% of available cash
-                    );
+            match bar.signal.unwrap_or(Signal::Hold) {
+                Signal::Buy if config.session_hours.is_some_and(|session| !in_session(timestamp, session)) => {
+                    // Outside the trading session: no new entries
+                }
+                Signal::Buy if too_soon || self.circuit_breaker_triggered => {
+                    // Within min_bars_between_trades of this symbol's last entry, or
+                    // trading halted by the circuit breaker
+                }
+                Signal::Buy => {
+                    // Z-score BUY signal: enter new position with OCO levels, splitting
+                    // 95% of available cash across the symbols not yet held, scaled down
+                    // while the drawdown throttle is engaged. `max_open_positions` caps the
+                    // slots; once they are all taken, new entries wait for an exit.
+                    let symbol_count = self.symbols.len();
+                    let slots = config.max_open_positions.map_or(symbol_count, |cap| cap.min(symbol_count));
+                    let free_slots = slots.saturating_sub(portfolio.positions.len()).max(1);
+                    let allocation = 0.95 / free_slots as f64 * portfolio.allocation_scale();
+                    let at_capacity = config
+                        .max_open_positions
+                        .filter(|&cap| portfolio.positions.len() >= cap && !portfolio.positions.contains_key(symbol));
+                    let rejection = if let Some(cap) = at_capacity {
+                        Some(format!("max_open_positions ({}) reached", cap))
+                    } else {
+                        config.max_portfolio_risk_percent.and_then(|budget| {
+                            risk_budget_rejection(
+                                portfolio,
+                                symbol,
+                                allocation,
+                                budget,
+                                config,
+                                &self.current_prices,
+                                &self.price_history,
+                            )
+                        })
+                    };
+                    match rejection {
+                        Some(reason) => portfolio.diagnostics.push(format!(
+                            "Skipped {} entry at {}: {}",
+                            symbol, timestamp, reason
+                        )),
+                        None => {
+                            if portfolio
+                                .open_position(symbol.to_string(), fill_price, timestamp, config, allocation)
+                                .is_some()
+                            {
+                                self.last_entry_bar.insert(symbol.to_string(), bar_index);
+                            }
+                        }
+                    }
                 }
-                "SELL" => {
+                Signal::Sell => {
                     // Z-score SELL signal: exit current position
                     // Reason: "EXIT_ZSCORE" - Z-score reversal from positive to negative
-                    portfolio.close_position(&symbol, price, *signal_time, "EXIT_ZSCORE");
+                    portfolio.close_position(symbol, fill_price, timestamp, ExitReason::ExitZScore);
+                }
+                Signal::Hold => {} // No action
+            }
+
+            // Hysteresis exit: a long closes as soon as its z-score reverts into the exit band
+            // (mirrored to rising above -exit_z_score when signals are inverted)
+            let inverted = config.invert_signals.unwrap_or(false);
+            let reverted = config.exit_z_score.zip(bar.z_score).is_some_and(|(exit_z_score, z_score)| {
+                if inverted {
+                    z_score > -exit_z_score
+                } else {
+                    z_score < exit_z_score
                 }
-                _ => {} // HOLD - no action
+            });
+            if reverted && portfolio.positions.contains_key(symbol) {
+                portfolio.close_position(symbol, fill_price, timestamp, ExitReason::ExitZScore);
             }
 
             // === OCO EXIT LEVEL CHECKING ===
-            // This is the One-Cancels-Other logic: automatically check if price hit either exit level
-            // Both levels are checked simultaneously; whichever is hit first closes the position
-            let positions_to_close: Vec<String> = portfolio.positions
-                .iter()
-                .filter_map(|(sym, pos)| {
-                    // OCO Check: price <= SL triggers stop-loss exit
-                    if price <= pos.stop_loss_price {
-                        Some((sym.clone(), "EXIT_STOP"))
-                    }
-                    // OCO Check: price >= TP triggers take-profit exit
-                    else if price >= pos.take_profit_price {
-                        Some((sym.clone(), "EXIT_PROFIT"))
-                    } else {
-                        None
-                    }
-                })
-                .map(|(sym, reason)| sym)
-                .collect();
+            // This is the One-Cancels-Other logic: automatically check if the bar's range hit
+            // either exit level. A bar whose range touches both is resolved by
+            // `intrabar_assumption`. Each position can only close once; after closing, the
+            // other level is automatically cancelled.
+            // A level reached by the bar's own price fills at `fill_price`; one only touched
+            // intrabar fills at the level itself.
+            let exit = portfolio.positions.get(symbol).and_then(|pos| {
+                // OCO Check: low <= SL touches the stop-loss, high >= TP the take-profit
+                let stop_fill = if price <= pos.stop_loss_price { fill_price } else { pos.stop_loss_price };
+                let profit_fill = if price >= pos.take_profit_price { fill_price } else { pos.take_profit_price };
+                let stop = (bar.low <= pos.stop_loss_price).then_some((ExitReason::ExitStop, stop_fill));
+                let profit = (bar.high >= pos.take_profit_price).then_some((ExitReason::ExitProfit, profit_fill));
+                match config.intrabar_assumption {
+                    IntrabarAssumption::PessimisticStopFirst => stop.or(profit),
+                    IntrabarAssumption::OptimisticProfitFirst => profit.or(stop),
+                }
+            });
+            if let Some((reason, exit_price)) = exit {
+                portfolio.close_position(symbol, exit_price, timestamp, reason);
+            }
+        }
+
+        // === PERIODIC REBALANCING ===
+        if let (Some(interval), Some(due)) = (self.rebalance_interval, self.next_rebalance) {
+            if timestamp >= due {
+                let weights: HashMap<String, f64> = portfolio.positions
+                    .keys()
+                    .map(|symbol| {
+                        let weight = match config.rebalance_weighting {
+                            RebalanceWeighting::Equal => 1.0,
+                            RebalanceWeighting::RatingWeighted => {
+                                self.latest_ratings.get(symbol).copied().unwrap_or(0.0)
+                            }
+                        };
+                        (symbol.clone(), weight)
+                    })
+                    .collect();
+                portfolio.rebalance(&weights, &self.current_prices, timestamp, maintenance_margin(config));
+                self.next_rebalance = Some(due + ((timestamp - due) / interval + 1) * interval);
+            }
+        }
+
+        if let Some(fee_percent) = fee_due {
+            portfolio.charge_performance_fee(fee_percent, &self.current_prices, timestamp);
+        }
+
+        // === CIRCUIT BREAKER ===
+        // A drawdown past the hard limit flattens the book at the latest prices and halts
+        // entries for good
+        if let Some(limit) = config.max_drawdown_stop_percent.filter(|_| !self.circuit_breaker_triggered) {
+            let equity = portfolio.get_portfolio_value(&self.current_prices);
+            let drawdown_percent = (1.0 - equity / portfolio.peak_equity) * 100.0;
+            if drawdown_percent >= limit {
+                let held: Vec<String> = portfolio.positions.keys().cloned().collect();
+                for symbol in held {
+                    let price = self.current_prices[&symbol];
+                    portfolio.close_position(&symbol, price, timestamp, ExitReason::ExitCircuitBreaker);
+                }
+                portfolio.diagnostics.push(format!(
+                    "Circuit breaker at {}: drawdown {:.2}% reached max_drawdown_stop_percent {}; trading halted",
+                    timestamp, drawdown_percent, limit
+                ));
+                self.circuit_breaker_triggered = true;
+            }
+        }
+
+        // Update equity curve
+        portfolio.update_equity_curve(timestamp, &self.current_prices);
+    }
+
+    /// Run any remaining steps, then settle open positions and compute the metrics.
+    pub fn finish(mut self) -> Result<BacktestResult> {
+        let mut end_time = self.previous_timestamp;
+        while let Some(step) = self.next_step() {
+            end_time = Some(step.timestamp);
+        }
+        let Self {
+            config,
+            ratings,
+            klines,
+            started,
+            initial_cash,
+            mut portfolio,
+            skipped_symbols,
+            symbols,
+            price_series,
+            current_prices,
+            circuit_breaker_triggered,
+            ..
+        } = self;
 
-            // Close positions that hit their OCO levels
-            // Each position can only close once; after closing, the other level is automatically cancelled
-            for pos_symbol in positions_to_close {
-                if price <= portfolio.positions[&pos_symbol].stop_loss_price {
-                    portfolio.close_position(&pos_symbol, price, *signal_time, "EXIT_STOP");
+        // === END OF DATA ===
+        // Positions still open are either realized at the final price or reported with their
+        // unrealized P&L, so neither silently drops out of the results
+        let mut open_symbols: Vec<String> = portfolio.positions.keys().cloned().collect();
+        open_symbols.sort();
+        let mut open_positions = Vec::new();
+        if let Some(end_time) = end_time {
+            for symbol in open_symbols {
+                let mark_price = current_prices[&symbol];
+                if config.close_at_end.unwrap_or(false) {
+                    portfolio.close_position(&symbol, mark_price, end_time, ExitReason::ExitEnd);
                 } else {
-                    portfolio.close_position(&pos_symbol, price, *signal_time, "EXIT_PROFIT");
+                    open_positions.push(open_position_report(&portfolio.positions[&symbol], mark_price));
                 }
             }
+            // Closing at the bid can move the final equity; restate the last point
+            let final_value = portfolio.get_portfolio_value(&current_prices);
+            if let Some(last) = portfolio.equity_curve.last_mut() {
+                last.1 = final_value;
+            }
+            let (realized, unrealized) = portfolio.pnl_split(&current_prices);
+            if let Some(last) = portfolio.pnl_curve.last_mut() {
+                (last.1, last.2) = (realized, unrealized);
+            }
+        }
 
-            // Update equity curve
-            portfolio.update_equity_curve(*signal_time, &current_prices);
+        if config.iso_timestamps {
+            for order in &mut portfolio.orders {
+                order.timestamp_iso = iso_timestamp(order.timestamp);
+            }
+        }
 
-            signal_idx += 1;
-            price_idx += 1;
+        // Calculate performance metrics
+        if portfolio.equity_curve.len() < 2 {
+            portfolio.diagnostics.push(format!(
+                "No price bars for {} between {} and {}; metrics are reported as zero",
+                symbols.join(", "),
+                config.start_time,
+                config.end_time
+            ));
         }
+        let metrics = calculate_performance_metrics(&portfolio, initial_cash, &config);
+        let benchmark = match &config.benchmark_symbol {
+            Some(symbol) => benchmark_prices(symbol, &ratings, klines)?,
+            None => symbols
+                .first()
+                .and_then(|symbol| benchmark_prices(symbol, &ratings, klines).ok())
+                .unwrap_or_default(),
+        };
+        let periods_per_year = periods_per_year(&config);
+        let risk_curve = risk_curve(&portfolio, &config);
+        let risk_free = per_period_risk_free(&config, risk_curve, periods_per_year);
+        let (alpha, beta) = alpha_beta(risk_curve, &benchmark, &risk_free, periods_per_year);
+        let (upside_capture, downside_capture) = capture_ratios(risk_curve, &benchmark);
+        let relative_max_drawdown = relative_max_drawdown(risk_curve, &benchmark);
+        tracing::info!(
+            trades = metrics.total_trades,
+            total_return = metrics.total_return,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Backtest complete"
+        );
+
+        Ok(BacktestResult {
+            total_return: metrics.total_return,
+            annualized_return: metrics.annualized_return,
+            sharpe_ratio: metrics.sharpe_ratio,
+            sortino_ratio: metrics.sortino_ratio,
+            annualized_volatility: metrics.annualized_volatility,
+            alpha,
+            beta,
+            upside_capture,
+            downside_capture,
+            max_drawdown: metrics.max_drawdown,
+            relative_max_drawdown,
+            win_ratio: metrics.win_ratio,
+            total_trades: metrics.total_trades,
+            profit_factor: metrics.profit_factor,
+            avg_trade_duration: metrics.avg_trade_duration,
+            best_trade_percent: metrics.best_trade_percent,
+            worst_trade_percent: metrics.worst_trade_percent,
+            avg_win: metrics.avg_win,
+            avg_loss: metrics.avg_loss,
+            expectancy: metrics.expectancy,
+            trade_ledger: trade_ledger(&portfolio.orders, &portfolio.equity_curve, config.market_calendar),
+            orders: portfolio.orders,
+            per_symbol: metrics.per_symbol,
+            open_positions,
+            diagnostics: portfolio.diagnostics,
+            skipped_symbols,
+            circuit_breaker_triggered,
+            price_series,
+            equity_curve: config.include_equity_curve.then_some(portfolio.equity_curve),
+            drawdown_curve: config.include_equity_curve.then_some(metrics.drawdown_curve),
+            pnl_curve: config.include_equity_curve.then_some(portfolio.pnl_curve),
+            rolling_sharpe: metrics.rolling_sharpe,
+        })
     }
+}
 
-    // Calculate performance metrics
-    let metrics = calculate_performance_metrics(
-        &portfolio,
-        initial_cash,
-        config.start_time,
-        config.end_time,
-    );
+impl Iterator for BacktestStepper<'_> {
+    type Item = BacktestStep;
 
-    Ok(BacktestResult {
-        total_return: metrics.total_return,
-        annualized_return: metrics.annualized_return,
-        sharpe_ratio: metrics.sharpe_ratio,
-        sortino_ratio: metrics.sortino_ratio,
-        alpha: metrics.alpha,
-        max_drawdown: metrics.max_drawdown,
-        win_ratio: metrics.win_ratio,
-        total_trades: metrics.total_trades,
-        profit_factor: metrics.profit_factor,
-        avg_trade_duration: metrics.avg_trade_duration,
-        orders: portfolio.orders,
-    })
+    fn next(&mut self) -> Option<BacktestStep> {
+        self.next_step()
+    }
 }
 
-pub fn run_windowed_backtest(
-    config: BacktestConfig,
-    ratings: Vec<GlickoRating>,
-) -> Result<Vec<BacktestResult>> {
+/// Pair each entry with the exit that closes it, folding rebalance trims realized in
+/// between into the trade's P&L. Trades still open at the end have no ledger entry.
+fn trade_ledger(
+    orders: &[BacktestOrder],
+    equity_curve: &[(i64, f64)],
+    calendar: MarketCalendar,
+) -> Vec<TradeLedgerEntry> {
+    let equity_before = |timestamp: i64| {
+        let index = equity_curve.partition_point(|&(t, _)| t < timestamp).saturating_sub(1);
+        equity_curve.get(index).map_or(0.0, |&(_, value)| value)
+    };
+    let equity_at = |timestamp: i64| {
+        let index = equity_curve.partition_point(|&(t, _)| t <= timestamp).saturating_sub(1);
+        equity_curve.get(index).map_or(0.0, |&(_, value)| value)
+    };
+
+    let mut open: HashMap<&str, (&BacktestOrder, f64)> = HashMap::new();
+    let mut ledger = Vec::new();
+    for order in orders {
+        match (order.side, order.reason) {
+            (OrderSide::Buy, ExitReason::Entry) => {
+                open.insert(order.symbol.as_str(), (order, 0.0));
+            }
+            (_, ExitReason::Rebalance) => {
+                if let Some((_, trimmed)) = open.get_mut(order.symbol.as_str()) {
+                    *trimmed += order.profit_loss.unwrap_or(0.0);
+                }
+            }
+            (OrderSide::Sell, reason) => {
+                if let Some((entry, trimmed)) = open.remove(order.symbol.as_str()) {
+                    ledger.push(TradeLedgerEntry {
+                        symbol: order.symbol.clone(),
+                        entry_time: entry.timestamp,
+                        exit_time: order.timestamp,
+                        entry_price: entry.price,
+                        exit_price: order.price,
+                        quantity: order.quantity,
+                        exit_reason: reason,
+                        profit_loss: order.profit_loss.unwrap_or(0.0) + trimmed,
+                        entry_equity: equity_before(entry.timestamp),
+                        exit_equity: equity_at(order.timestamp),
+                        duration_hours: calendar.elapsed_ms(entry.timestamp, order.timestamp) as f64
+                            / (1000.0 * 60.0 * 60.0),
+                    });
+                }
+            }
+            (OrderSide::Buy, _) => {}
+        }
+    }
+    ledger
+}
+
+/// A still-open position marked to `mark_price`.
+fn open_position_report(position: &Position, mark_price: f64) -> OpenPositionReport {
+    OpenPositionReport {
+        symbol: position.symbol.clone(),
+        quantity: position.quantity,
+        entry_price: position.entry_price,
+        entry_time: position.entry_time,
+        mark_price,
+        unrealized_pnl: position.quantity * (mark_price - position.entry_price),
+        unrealized_pnl_percent: (mark_price - position.entry_price) / position.entry_price * 100.0,
+    }
+}
+
+/// Format epoch milliseconds as an RFC 3339 UTC string, e.g. `2022-01-01T00:00:00.000Z`.
+pub fn iso_timestamp(timestamp_ms: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+/// Walk-forward window bounds `(start, end)` in ms: `window_size` months (default 12)
+/// stepped by half a window from `start_time` while the window fits before `end_time`.
+/// Under `WindowUnit::Bars` see `bar_window_bounds`.
+fn window_bounds(config: &BacktestConfig, ratings: &[GlickoRating]) -> Vec<(i64, i64)> {
+    if config.window_unit == WindowUnit::Bars {
+        return bar_window_bounds(config, ratings);
+    }
+
     let window_size_ms = config.window_size.unwrap_or(12) as i64 * 30 * 24 * 60 * 60 * 1000; // months to ms
     let step_size_ms = window_size_ms / 2; // 50% overlap
 
-    let mut results = Vec::new();
+    let mut bounds = Vec::new();
     let mut current_start = config.start_time;
 
-    while current_start + window_size_ms <= config.end_time {
-        let current_end = current_start + window_size_ms;
-        
-        // Filter ratings for current window
-        let window_ratings: Vec<GlickoRating> = ratings
-            .iter()
-            .filter(|r| r.timestamp >= current_start && r.timestamp <= current_end)
-            .cloned()
-            .collect();
+    while current_start + window_size_ms <= config.end_time && step_size_ms > 0 {
+        bounds.push((current_start, current_start + window_size_ms));
+        current_start += step_size_ms;
+    }
 
-        if !window_ratings.is_empty() {
-            let window_config = BacktestConfig {
-                start_time: current_start,
-                end_time: current_end,
-                ..config.clone()
-            };
+    bounds
+}
 
-            let result = run_backtest(window_config, window_ratings)?;
-            results.push(result);
-        }
+/// Bar-count windows: `window_size` consecutive distinct rating timestamps (default 12)
+/// within `start_time..=end_time`, starting every `window_step` bars (default half a
+/// window). Only whole windows are returned.
+fn bar_window_bounds(config: &BacktestConfig, ratings: &[GlickoRating]) -> Vec<(i64, i64)> {
+    let window_size = config.window_size.unwrap_or(12).max(1);
+    let step = config.window_step.unwrap_or(window_size / 2).max(1);
 
-        current_start += step_size_ms;
+    let mut timestamps: Vec<i64> = ratings
+        .iter()
+        .map(|r| r.timestamp)
+        .filter(|&timestamp| timestamp >= config.start_time && timestamp <= config.end_time)
+        .collect();
+    timestamps.sort_unstable();
+    timestamps.dedup();
+
+    timestamps
+        .windows(window_size)
+        .step_by(step)
+        .map(|window| (window[0], window[window_size - 1]))
+        .collect()
+}
+
+/// Backtest a single window; `None` when no ratings fall inside it or no symbol in it
+/// has more ratings than `moving_averages`, so a short window yields no trades rather
+/// than failing the whole walk-forward run.
+fn run_window(
+    config: &BacktestConfig,
+    ratings: &[GlickoRating],
+    (window_start, window_end): (i64, i64),
+) -> Option<Result<BacktestResult>> {
+    // Filter ratings for current window
+    let window_ratings: Vec<GlickoRating> = ratings
+        .iter()
+        .filter(|r| r.timestamp >= window_start && r.timestamp <= window_end)
+        .cloned()
+        .collect();
+
+    if window_ratings.is_empty() {
+        return None;
+    }
+
+    let window_config = BacktestConfig {
+        start_time: window_start,
+        end_time: window_end,
+        ..config.clone()
+    };
+
+    tracing::debug!(
+        window_start,
+        window_end,
+        ratings = window_ratings.len(),
+        "Processing window"
+    );
+    match run_backtest(window_config, window_ratings) {
+        Err(err) if matches!(err.downcast_ref(), Some(BacktestError::InsufficientHistory { .. })) => {
+            tracing::warn!(window_start, window_end, error = %err, "Skipping window");
+            None
+        }
+        result => Some(result),
     }
+}
+
+/// Run walk-forward windows in parallel; results are returned in window start order.
+pub fn run_windowed_backtest(
+    config: BacktestConfig,
+    ratings: Vec<GlickoRating>,
+) -> Result<Vec<BacktestResult>> {
+    let started = Instant::now();
+
+    let window_results: Vec<Option<Result<BacktestResult>>> = window_bounds(&config, &ratings)
+        .into_par_iter()
+        .map(|bounds| run_window(&config, &ratings, bounds))
+        .collect();
+    let results = window_results.into_iter().flatten().collect::<Result<Vec<_>>>()?;
+
+    tracing::info!(
+        windows = results.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Windowed backtest complete"
+    );
 
     Ok(results)
 }
 
+/// Re-run the backtest with each tuned parameter nudged down and up by its delta and
+/// report the Sharpe of every run. A sharp drop next to the baseline means the chosen
+/// parameters sit on a cliff, i.e. are likely overfit. Perturbations that would make a
+/// parameter invalid (non-positive, or `moving_averages` below 2) are skipped, as is
+/// `profit_percent` when it is unset (the take-profit comes from `risk_reward_ratio`).
+pub fn sensitivity(
+    config: &BacktestConfig,
+    ratings: &[GlickoRating],
+    klines: &[KlineData],
+    deltas: &SensitivityDeltas,
+) -> Result<SensitivityReport> {
+    let mut variants: Vec<(&'static str, f64, f64, BacktestConfig)> = Vec::new();
+    for sign in [-1.0, 1.0] {
+        let z_score_threshold = config.z_score_threshold + sign * deltas.z_score_threshold;
+        let profit_percent = config.profit_percent.map(|percent| percent + sign * deltas.profit_percent);
+        let stop_loss_percent = config.stop_loss_percent + sign * deltas.stop_loss_percent;
+        let moving_averages = config.moving_averages as f64 + sign * deltas.moving_averages as f64;
+
+        if deltas.z_score_threshold > 0.0 && z_score_threshold > 0.0 {
+            let variant = BacktestConfig { z_score_threshold, ..config.clone() };
+            variants.push(("z_score_threshold", sign * deltas.z_score_threshold, z_score_threshold, variant));
+        }
+        if let Some(profit_percent) = profit_percent.filter(|&percent| deltas.profit_percent > 0.0 && percent > 0.0) {
+            let variant = BacktestConfig { profit_percent: Some(profit_percent), ..config.clone() };
+            variants.push(("profit_percent", sign * deltas.profit_percent, profit_percent, variant));
+        }
+        if deltas.stop_loss_percent > 0.0 && stop_loss_percent > 0.0 {
+            let variant = BacktestConfig { stop_loss_percent, ..config.clone() };
+            variants.push(("stop_loss_percent", sign * deltas.stop_loss_percent, stop_loss_percent, variant));
+        }
+        if deltas.moving_averages > 0 && moving_averages >= 2.0 {
+            let variant = BacktestConfig { moving_averages: moving_averages as usize, ..config.clone() };
+            variants.push(("moving_averages", sign * deltas.moving_averages as f64, moving_averages, variant));
+        }
+    }
+
+    let baseline = run_backtest_with_klines(config.clone(), ratings.to_vec(), klines)?;
+    let perturbations = variants
+        .into_par_iter()
+        .map(|(parameter, delta, value, variant)| {
+            let result = run_backtest_with_klines(variant, ratings.to_vec(), klines)?;
+            Ok(SensitivityPoint {
+                parameter: parameter.to_string(),
+                delta,
+                value,
+                sharpe_ratio: result.sharpe_ratio,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(SensitivityReport {
+        baseline_sharpe: baseline.sharpe_ratio,
+        perturbations,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::SmoothingKind;
 
     #[test]
     fn test_portfolio_creation() {
@@ -636,11 +2378,12 @@ mod tests {
             quote_asset: "USDT".to_string(),
             z_score_threshold: 2.0,
             moving_averages: 200,
-            profit_percent: 5.0,
+            profit_percent: Some(5.0),
             stop_loss_percent: 2.5,
             start_time: 0,
             end_time: 1000000,
             window_size: Some(12),
+            ..BacktestConfig::default()
         };
 
         let order = portfolio.open_position(
@@ -657,27 +2400,2094 @@ mod tests {
     }
 
     #[test]
-    fn test_z_score_calculation() {
-        let ratings = vec![
-            GlickoRating {
-                symbol: "BTCUSDT".to_string(),
-                timestamp: 1000,
-                rating: 1500.0,
-                rating_deviation: 200.0,
-                volatility: 0.06,
-                performance_score: 0.5,
-            },
-            GlickoRating {
-                symbol: "BTCUSDT".to_string(),
-                timestamp: 2000,
-                rating: 1600.0,
-                rating_deviation: 190.0,
-                volatility: 0.06,
-                performance_score: 0.75,
-            },
-        ];
+    fn test_cash_decimals_keep_break_even_round_trips_exact() {
+        let config = BacktestConfig { cash_decimals: Some(2), ..BacktestConfig::default() };
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.cash_decimals = config.cash_decimals;
 
-        let signals = calculate_z_score_signals(&ratings, 1, 1.0);
-        assert!(signals.contains_key("BTCUSDT"));
+        for i in 0..5000 {
+            let price = 123.456789 + i as f64 * 0.731;
+            let timestamp = i as i64 * 1000;
+            portfolio.open_position("BTCUSDT".to_string(), price, timestamp, &config, 0.37).unwrap();
+            portfolio.close_position("BTCUSDT", price, timestamp, ExitReason::ExitZScore).unwrap();
+        }
+
+        assert!((portfolio.cash - 10000.0).abs() < 0.01, "cash drifted to {}", portfolio.cash);
+    }
+
+    #[test]
+    fn test_entry_below_min_notional_is_skipped() {
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.cash = 10.0; // Little left after earlier trades
+        let config = BacktestConfig {
+            min_notional: Some(10.0),
+            ..BacktestConfig::default()
+        };
+
+        let order = portfolio.open_position("BTCUSDT".to_string(), 50000.0, 1640995200000, &config, 0.95);
+
+        assert!(order.is_none());
+        assert!(portfolio.positions.is_empty());
+        assert_eq!(portfolio.cash, 10.0);
+        assert_eq!(portfolio.diagnostics.len(), 1);
+        assert!(portfolio.diagnostics[0].contains("below min_notional"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_performance_fee_only_charged_above_high_water_mark() {
+        let prices = HashMap::new();
+        let mut portfolio = Portfolio::new(10000.0);
+
+        // New high: 20% of the 1000 gain
+        portfolio.cash = 11000.0;
+        portfolio.charge_performance_fee(20.0, &prices, 1);
+        assert_eq!(portfolio.cash, 10800.0);
+
+        // Loss, then recovery back to the mark: no fee on recovered losses
+        portfolio.cash = 9000.0;
+        portfolio.charge_performance_fee(20.0, &prices, 2);
+        assert_eq!(portfolio.cash, 9000.0);
+        portfolio.cash = 10800.0;
+        portfolio.charge_performance_fee(20.0, &prices, 3);
+        assert_eq!(portfolio.cash, 10800.0);
+
+        // Only gains beyond the mark are charged again
+        portfolio.cash = 11800.0;
+        portfolio.charge_performance_fee(20.0, &prices, 4);
+        assert_eq!(portfolio.cash, 11600.0);
+    }
+
+    #[test]
+    fn test_stop_mode_sets_oco_levels() {
+        let absolute = BacktestConfig {
+            stop_mode: StopMode::AbsoluteOffset { stop_offset: 5.0, profit_offset: 8.0 },
+            ..BacktestConfig::default()
+        };
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &absolute, 0.95).unwrap();
+        assert_eq!(portfolio.positions["BTCUSDT"].stop_loss_price, 95.0);
+        assert_eq!(portfolio.positions["BTCUSDT"].take_profit_price, 108.0);
+
+        let atr = BacktestConfig {
+            stop_mode: StopMode::AtrMultiple { stop_multiple: 2.0, profit_multiple: 3.0, atr_period: 14 },
+            ..BacktestConfig::default()
+        };
+        let mut portfolio = Portfolio::new(10000.0);
+        assert!(portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &atr, 0.95).is_none());
+        portfolio.atr.insert("BTCUSDT".to_string(), 1.5);
+        portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &atr, 0.95).unwrap();
+        assert_eq!(portfolio.positions["BTCUSDT"].stop_loss_price, 97.0);
+        assert_eq!(portfolio.positions["BTCUSDT"].take_profit_price, 104.5);
+    }
+
+    #[test]
+    fn test_integer_quantities_floor_and_skip_unaffordable_unit() {
+        let config = BacktestConfig {
+            integer_quantities: Some(true),
+            ..BacktestConfig::default()
+        };
+
+        let mut portfolio = Portfolio::new(10000.0);
+        let order = portfolio
+            .open_position("ETHUSDT".to_string(), 3000.0, 1640995200000, &config, 0.95)
+            .unwrap();
+        assert_eq!(order.quantity, 3.0);
+        assert_eq!(portfolio.cash, 1000.0);
+
+        let mut portfolio = Portfolio::new(10000.0);
+        let order = portfolio.open_position("BTCUSDT".to_string(), 50000.0, 1640995200000, &config, 0.95);
+        assert!(order.is_none());
+        assert!(portfolio.positions.is_empty());
+        assert_eq!(portfolio.cash, 10000.0);
+        assert!(portfolio.diagnostics[0].contains("above the 9500.00 available for one whole unit"));
+
+        // The shortfall reflects the actual sizing base: half of 20,000 equity, not of cash
+        let total_equity = BacktestConfig { sizing_base: SizingBase::TotalEquity, ..config };
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.equity_curve.push((1, 20000.0));
+        assert!(portfolio.open_position("BTCUSDT".to_string(), 50000.0, 2, &total_equity, 0.5).is_none());
+        assert!(portfolio.diagnostics[0].contains("above the 10000.00 available"));
+    }
+
+    #[test]
+    fn test_entry_quantity_floored_to_step_size() {
+        let mut portfolio = Portfolio::new(123.7);
+        let config = BacktestConfig {
+            step_size: Some(0.01),
+            ..BacktestConfig::default()
+        };
+
+        // 123.7 / 100 = 1.237 units before rounding
+        let order = portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &config, 1.0).unwrap();
+
+        assert!((order.quantity - 1.23).abs() < 1e-12);
+        assert!((portfolio.cash - 0.7).abs() < 1e-9);
+        assert!((round_down_to_step(1.23, 0.01) - 1.23).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_z_score_calculation() {
+        let ratings = vec![
+            GlickoRating {
+                symbol: "BTCUSDT".to_string(),
+                timestamp: 1000,
+                rating: 1500.0,
+                rating_deviation: 200.0,
+                volatility: 0.06,
+                performance_score: 0.5,
+            },
+            GlickoRating {
+                symbol: "BTCUSDT".to_string(),
+                timestamp: 2000,
+                rating: 1600.0,
+                rating_deviation: 190.0,
+                volatility: 0.06,
+                performance_score: 0.75,
+            },
+        ];
+
+        let config = BacktestConfig {
+            moving_averages: 1,
+            z_score_threshold: 1.0,
+            ..BacktestConfig::default()
+        };
+        let signals = calculate_z_score_signals(&ratings, &config).unwrap();
+        assert!(signals.contains_key("BTCUSDT"));
+    }
+
+    #[test]
+    fn test_signal_confirmation_ignores_one_bar_spike() {
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1500.0, 1501.0, 1500.0, 1501.0, 1600.0, 1700.0, 1800.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let buys = |signal_confirmation_bars| -> Vec<i64> {
+            let config = BacktestConfig { moving_averages: 4, signal_confirmation_bars, ..BacktestConfig::default() };
+            calculate_z_score_signals(&ratings, &config).unwrap()["BTCUSDT"]
+                .iter()
+                .filter(|s| s.signal == Signal::Buy)
+                .map(|s| s.timestamp)
+                .collect()
+        };
+
+        assert_eq!(buys(None), vec![4000, 9000, 10000, 11000]);
+        assert_eq!(buys(Some(2)), vec![10000, 11000]);
+    }
+
+    #[test]
+    fn test_confidence_scaled_threshold_demands_more_of_uncertain_ratings() {
+        // Window mean 1500.5 / std 0.5, so the last bar scores z = (last - 1500.5) / 0.5
+        let ratings_for = |symbol: &str, rating_deviation: f64, last: f64| -> Vec<GlickoRating> {
+            let mut ratings = crate::test_utils::ratings(symbol, &[1500.0, 1501.0, 1500.0, 1501.0, last], 1000);
+            ratings.iter_mut().for_each(|r| r.rating_deviation = rating_deviation);
+            ratings
+        };
+        let last_signals = |last: f64, confidence_scaled_threshold| -> (Signal, Signal) {
+            let mut ratings = ratings_for("CERTAIN", 50.0, last);
+            ratings.extend(ratings_for("UNCERTAIN", 300.0, last));
+            let config = BacktestConfig {
+                moving_averages: 4,
+                z_score_threshold: 2.0,
+                confidence_scaled_threshold,
+                ..BacktestConfig::default()
+            };
+            let signals = calculate_z_score_signals(&ratings, &config).unwrap();
+            (signals["CERTAIN"][0].signal, signals["UNCERTAIN"][0].signal)
+        };
+
+        // z = 3: past 2 * (1 + 50/350) but short of 2 * (1 + 300/350)
+        assert_eq!(last_signals(1502.0, None), (Signal::Buy, Signal::Buy));
+        assert_eq!(last_signals(1502.0, Some(true)), (Signal::Buy, Signal::Hold));
+        // z = 5 clears both
+        assert_eq!(last_signals(1503.0, Some(true)), (Signal::Buy, Signal::Buy));
+    }
+
+    #[test]
+    fn test_z_score_insufficient_history_is_reported() {
+        let series: Vec<f64> = (0..5).map(|i| 1500.0 + i as f64).collect();
+        let ratings = crate::test_utils::ratings("ETHUSDT", &series, 1000);
+
+        let config = BacktestConfig {
+            moving_averages: 5,
+            z_score_threshold: 1.0,
+            ..BacktestConfig::default()
+        };
+        let err = calculate_z_score_signals(&ratings, &config).unwrap_err();
+        assert!(matches!(
+            err,
+            BacktestError::InsufficientHistory { ref symbol, history_len: 5, period: 5 } if symbol == "ETHUSDT"
+        ));
+        assert!(err.to_string().contains("ETHUSDT"));
+    }
+
+    #[test]
+    fn test_nan_rating_is_an_error_not_silent_hold() {
+        let series: Vec<f64> = (0..10)
+            .map(|i| if i == 6 { f64::NAN } else { 1500.0 + (i % 3) as f64 })
+            .collect();
+        let ratings = crate::test_utils::ratings("ETHUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 5,
+            ..BacktestConfig::default()
+        };
+
+        let err = calculate_z_score_signals(&ratings, &config).unwrap_err();
+        assert!(matches!(
+            err,
+            BacktestError::NonFiniteRating { ref symbol, timestamp: 6000, .. } if symbol == "ETHUSDT"
+        ));
+        assert!(run_backtest(config, ratings).is_err());
+    }
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_debug_logging_does_not_alter_result() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 300);
+        let ratings = crate::glicko::calculate_ratings(klines).unwrap();
+        let config = BacktestConfig {
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            z_score_threshold: 1.0,
+            moving_averages: 10,
+            profit_percent: Some(5.0),
+            stop_loss_percent: 2.5,
+            start_time: 0,
+            end_time: 1000000,
+            ..BacktestConfig::default()
+        };
+
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        let logged = tracing::subscriber::with_default(subscriber, || {
+            run_backtest(config.clone(), ratings.clone()).unwrap()
+        });
+        let silent = run_backtest(config, ratings).unwrap();
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("DEBUG"));
+        assert!(output.contains("Generated z-score signals"));
+        assert_eq!(
+            serde_json::to_string(&logged).unwrap(),
+            serde_json::to_string(&silent).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ema_z_scores_react_faster_on_trend() {
+        // Flat ratings followed by a steady climb
+        let series: Vec<f64> = (0..40)
+            .map(|i| if i < 20 { 1500.0 + (i % 2) as f64 } else { 1500.0 + (i - 19) as f64 * 10.0 })
+            .collect();
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+
+        let sma_config = BacktestConfig {
+            moving_averages: 10,
+            ..BacktestConfig::default()
+        };
+        let ema_config = BacktestConfig {
+            z_score_method: ZScoreMethod::Ema,
+            ..sma_config.clone()
+        };
+
+        let sma = calculate_z_score_signals(&ratings, &sma_config).unwrap();
+        let ema = calculate_z_score_signals(&ratings, &ema_config).unwrap();
+        let sma_last = sma["BTCUSDT"].last().unwrap().z_score;
+        let ema_last = ema["BTCUSDT"].last().unwrap().z_score;
+
+        // Deep into the trend the EMA baseline has caught up, so the z-score is less stretched
+        assert!(sma_last > 0.0 && ema_last > 0.0);
+        assert!(ema_last < sma_last);
+    }
+
+    #[test]
+    fn test_bands_bracket_rating_within_threshold() {
+        let series: Vec<f64> = (0..60).map(|i| 1500.0 + (i as f64 / 4.0).sin() * 40.0).collect();
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 10,
+            z_score_threshold: 1.5,
+            with_bands: true,
+            ..BacktestConfig::default()
+        };
+
+        let signals = calculate_z_score_signals(&ratings, &config).unwrap();
+        let rating_at: HashMap<i64, f64> = ratings.iter().map(|r| (r.timestamp, r.rating)).collect();
+
+        for point in &signals["BTCUSDT"] {
+            let upper = point.upper_band.unwrap();
+            let lower = point.lower_band.unwrap();
+            let rating = rating_at[&point.timestamp];
+            if point.z_score.abs() <= config.z_score_threshold {
+                assert!(lower <= rating + 1e-9 && rating <= upper + 1e-9);
+            } else {
+                assert!(rating > upper || rating < lower);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rsi_filter_suppresses_unconfirmed_buy() {
+        // A sharp rally: the z-score fires BUY, but RSI is overbought, not oversold
+        let series: Vec<f64> = (0..30)
+            .map(|i| if i < 25 { 1500.0 + (i % 3) as f64 } else { 1500.0 + (i - 24) as f64 * 50.0 })
+            .collect();
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 10,
+            z_score_threshold: 2.0,
+            ..BacktestConfig::default()
+        };
+        let filtered_config = BacktestConfig {
+            rsi_filter: Some(crate::RsiFilter::default()),
+            ..config.clone()
+        };
+
+        let unfiltered = calculate_z_score_signals(&ratings, &config).unwrap();
+        let filtered = calculate_z_score_signals(&ratings, &filtered_config).unwrap();
+
+        assert!(unfiltered["BTCUSDT"].iter().any(|s| s.signal == Signal::Buy));
+        assert!(filtered["BTCUSDT"].iter().all(|s| s.signal != Signal::Buy));
+    }
+
+    #[test]
+    fn test_low_performance_score_suppresses_buy() {
+        // The rally fires BUY, but the candles behind it were weak wins
+        let series: Vec<f64> = (0..30)
+            .map(|i| if i < 25 { 1500.0 + (i % 3) as f64 } else { 1500.0 + (i - 24) as f64 * 50.0 })
+            .collect();
+        let ratings_with_score = |score: f64| -> Vec<GlickoRating> {
+            let mut ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+            ratings[25..].iter_mut().for_each(|r| r.performance_score = score);
+            ratings
+        };
+        let config = BacktestConfig {
+            moving_averages: 10,
+            z_score_threshold: 2.0,
+            min_performance_score: Some(0.6),
+            performance_score_window: Some(3),
+            ..BacktestConfig::default()
+        };
+
+        let strong = calculate_z_score_signals(&ratings_with_score(0.9), &config).unwrap();
+        let weak = calculate_z_score_signals(&ratings_with_score(0.55), &config).unwrap();
+
+        assert!(strong["BTCUSDT"].iter().any(|s| s.signal == Signal::Buy));
+        assert!(weak["BTCUSDT"].iter().all(|s| s.signal != Signal::Buy));
+    }
+
+    #[test]
+    fn test_trades_fire_at_signal_timestamps() {
+        // Ratings are unevenly spaced and arrive out of order; the first 4 bars have no
+        // z-score yet, so prices and signals are series of different lengths.
+        let series = [
+            (0, 1500.0),
+            (1000, 1501.0),
+            (2500, 1500.0),
+            (2600, 1501.0),
+            (4000, 1500.0),
+            (7000, 1560.0), // breakout -> BUY
+            (7100, 1562.0),
+            (9000, 1440.0), // collapse -> SELL / stop
+        ];
+        let values: Vec<f64> = series.iter().map(|&(_, rating)| rating).collect();
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &values, 1000);
+        for (rating, &(timestamp, _)) in ratings.iter_mut().zip(&series) {
+            rating.timestamp = timestamp;
+        }
+        ratings.reverse();
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            z_score_threshold: 2.0,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        assert_eq!(result.orders.len(), 2);
+
+        let entry = &result.orders[0];
+        assert_eq!((entry.side, entry.timestamp), (OrderSide::Buy, 7000));
+        assert!((entry.price - 100.0 * 1560.0 / 1500.0).abs() < 1e-9);
+
+        let exit = &result.orders[1];
+        assert_eq!((exit.side, exit.timestamp), (OrderSide::Sell, 9000));
+        assert_eq!(exit.reason, ExitReason::ExitZScore);
+        assert!((exit.price - 100.0 * 1440.0 / 1500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rebalance_restores_equal_weights() {
+        // Both symbols break out together at t=4000, then drift apart
+        let btc = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1600.0, 1650.0, 1650.0];
+        let eth = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1540.0, 1530.0, 1530.0];
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &btc, 1000);
+        ratings.extend(crate::test_utils::ratings("ETHUSDT", &eth, 1000));
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            symbols: Some(vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()]),
+            rebalance_interval_ms: Some(6000),
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+
+        let entries: Vec<_> = result.orders.iter().filter(|o| o.reason == ExitReason::Entry).collect();
+        assert_eq!(entries.len(), 2);
+
+        let rebalances: Vec<_> = result.orders.iter().filter(|o| o.reason == ExitReason::Rebalance).collect();
+        assert_eq!(rebalances.len(), 2);
+        assert!(rebalances.iter().all(|o| o.timestamp == 6000));
+        // BTC outperformed, so it is trimmed and ETH topped up
+        assert_eq!((rebalances[0].symbol.as_str(), rebalances[0].side), ("BTCUSDT", OrderSide::Sell));
+        assert_eq!((rebalances[1].symbol.as_str(), rebalances[1].side), ("ETHUSDT", OrderSide::Buy));
+
+        let held_value = |symbol: &str| -> f64 {
+            result.orders
+                .iter()
+                .filter(|o| o.symbol == symbol)
+                .map(|o| if o.side == OrderSide::Buy { o.quantity } else { -o.quantity })
+                .sum::<f64>()
+                * rebalances[0..2].iter().find(|o| o.symbol == symbol).unwrap().price
+        };
+        let (btc_value, eth_value) = (held_value("BTCUSDT"), held_value("ETHUSDT"));
+        assert!((btc_value - eth_value).abs() < 1e-6 * btc_value, "{btc_value} vs {eth_value}");
+        assert_eq!(result.total_trades, 0);
+    }
+
+    #[test]
+    fn test_iso_timestamp_is_utc_rfc3339() {
+        assert_eq!(iso_timestamp(1640995200000).as_deref(), Some("2022-01-01T00:00:00.000Z"));
+        assert_eq!(iso_timestamp(1641002399999).as_deref(), Some("2022-01-01T01:59:59.999Z"));
+    }
+
+    #[test]
+    fn test_periods_per_year_scales_sharpe_by_sqrt_ratio() {
+        let mut portfolio = Portfolio::new(10000.0);
+        for (i, value) in [10100.0, 10050.0, 10200.0, 10150.0, 10300.0].iter().enumerate() {
+            portfolio.equity_curve.push((i as i64 + 1, *value));
+        }
+
+        let crypto = calculate_performance_metrics(&portfolio, 10000.0, &BacktestConfig::default());
+        let equities_config = BacktestConfig {
+            trading_periods_per_year: Some(252.0),
+            ..BacktestConfig::default()
+        };
+        let equities = calculate_performance_metrics(&portfolio, 10000.0, &equities_config);
+
+        // Strip the per-period risk-free rate so only the sqrt(periods) scaling remains
+        let returns: Vec<f64> = portfolio.equity_curve.windows(2).map(|w| w[1].1 / w[0].1 - 1.0).collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let volatility = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
+        let excess = |sharpe: f64, periods: f64| sharpe + 0.02 / periods / volatility * periods.sqrt();
+
+        let ratio = excess(crypto.sharpe_ratio, 365.25) / excess(equities.sharpe_ratio, 252.0);
+        assert!((ratio - (365.25_f64 / 252.0).sqrt()).abs() < 1e-9);
+        assert!(equities.annualized_return > crypto.annualized_return);
+    }
+
+    #[test]
+    fn test_leveraged_long_is_liquidated_before_stop() {
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1400.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 20.0,
+            leverage: Some(20.0),
+            max_leverage: Some(10.0),
+            maintenance_margin_percent: Some(0.5),
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        assert_eq!(result.orders.len(), 2);
+
+        // Leverage is capped at 10x: entry notional is 10x the posted margin
+        let entry = &result.orders[0];
+        let margin = 10000.0 * 0.95;
+        assert!((entry.quantity * entry.price - margin * 10.0).abs() < 1e-6);
+
+        // 10x long liquidates ~9.5% below entry, well before the 20% stop
+        let exit = &result.orders[1];
+        assert_eq!(exit.reason, ExitReason::ExitLiquidation);
+        assert_eq!(exit.timestamp, 5000);
+        let expected_liquidation = (entry.quantity * entry.price - margin) / (entry.quantity * 0.995);
+        assert!((exit.price - expected_liquidation).abs() < 1e-9);
+        assert!(exit.price > entry.price * 0.8);
+        assert!(result.total_return < -0.9);
+    }
+
+    #[test]
+    fn test_funding_accrues_on_held_long() {
+        // BUY at t=4000 at price 104, then flat price through four funding settlements
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1560.0, 1560.0, 1560.0, 1560.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            funding_rate: Some(0.001),
+            funding_interval_ms: Some(1000),
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        assert_eq!(result.orders.len(), 1);
+
+        // Notional 9500 pays 0.1% at t=5000, 6000, 7000 and 8000
+        let expected_funding = 4.0 * 9500.0 * 0.001;
+        assert!((result.total_return - (-expected_funding / 10000.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_half_spread_costs_both_legs_of_round_trip() {
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1440.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let spread_config = BacktestConfig {
+            half_spread_bps: Some(25.0),
+            ..config.clone()
+        };
+
+        let mid = run_backtest(config, ratings.clone()).unwrap();
+        let spread = run_backtest(spread_config, ratings).unwrap();
+        assert_eq!(spread.orders.len(), 2);
+
+        let (entry, exit) = (&spread.orders[0], &spread.orders[1]);
+        assert!((entry.price - mid.orders[0].price * 1.0025).abs() < 1e-9);
+        assert!((exit.price - mid.orders[1].price * 0.9975).abs() < 1e-9);
+
+        // Round trip loses the spread on both legs relative to trading at the mid
+        let expected_final = 9500.0 / entry.price * exit.price + 500.0;
+        assert!((spread.total_return - (expected_final / 10000.0 - 1.0)).abs() < 1e-9);
+        assert!(spread.total_return < mid.total_return);
+    }
+
+    #[test]
+    fn test_position_open_at_end_is_reported_or_closed() {
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+
+        let reported = run_backtest(config.clone(), ratings.clone()).unwrap();
+        assert_eq!(reported.total_trades, 0);
+        assert_eq!(reported.open_positions.len(), 1);
+        let open = &reported.open_positions[0];
+        assert_eq!((open.symbol.as_str(), open.entry_time), ("BTCUSDT", 4000));
+        assert!((open.mark_price - 100.0 * 1570.0 / 1500.0).abs() < 1e-9);
+        assert!((open.unrealized_pnl - open.quantity * (open.mark_price - open.entry_price)).abs() < 1e-9);
+
+        let closed = run_backtest(BacktestConfig { close_at_end: Some(true), ..config }, ratings).unwrap();
+        assert!(closed.open_positions.is_empty());
+        assert_eq!(closed.total_trades, 1);
+        let exit = closed.orders.last().unwrap();
+        assert_eq!((exit.reason, exit.timestamp), (ExitReason::ExitEnd, 5000));
+        assert!((exit.profit_loss.unwrap() - open.unrealized_pnl).abs() < 1e-9);
+        assert!((closed.total_return - reported.total_return).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_best_and_worst_trade_percent() {
+        let config = BacktestConfig {
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let mut portfolio = Portfolio::new(10000.0);
+        for (i, exit_price) in [112.0, 93.0, 104.0].iter().enumerate() {
+            let t = i as i64 * 10;
+            portfolio.open_position("BTCUSDT".to_string(), 100.0, t, &config, 0.5);
+            portfolio.close_position("BTCUSDT", *exit_price, t + 1, ExitReason::ExitZScore);
+        }
+
+        let metrics = calculate_performance_metrics(&portfolio, 10000.0, &BacktestConfig::default());
+        assert!((metrics.best_trade_percent - 12.0).abs() < 1e-9);
+        assert!((metrics.worst_trade_percent - -7.0).abs() < 1e-9);
+
+        let empty = calculate_performance_metrics(&Portfolio::new(10000.0), 10000.0, &BacktestConfig::default());
+        assert_eq!((empty.best_trade_percent, empty.worst_trade_percent), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_expectancy_from_average_win_and_loss() {
+        let config = BacktestConfig {
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let mut portfolio = Portfolio::new(10000.0);
+        // 10 units per trade: +100, +50, -60
+        for (i, exit_price) in [110.0, 105.0, 94.0].iter().enumerate() {
+            let t = i as i64 * 10;
+            let allocation = 1000.0 / portfolio.cash;
+            portfolio.open_position("BTCUSDT".to_string(), 100.0, t, &config, allocation);
+            portfolio.close_position("BTCUSDT", *exit_price, t + 1, ExitReason::ExitZScore);
+        }
+
+        let metrics = calculate_performance_metrics(&portfolio, 10000.0, &BacktestConfig::default());
+        assert!((metrics.avg_win - 75.0).abs() < 1e-9);
+        assert!((metrics.avg_loss - -60.0).abs() < 1e-9);
+        // 2/3 * 75 - 1/3 * 60 = 30
+        assert!((metrics.expectancy - 30.0).abs() < 1e-9);
+
+        let empty = calculate_performance_metrics(&Portfolio::new(10000.0), 10000.0, &BacktestConfig::default());
+        assert_eq!((empty.avg_win, empty.avg_loss, empty.expectancy), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_ema_smoothing_reduces_signal_flips() {
+        // Slow sine trend buried in deterministic pseudo-random noise
+        let mut seed: u64 = 42;
+        let series: Vec<f64> = (0..300)
+            .map(|i| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let noise = ((seed >> 33) as f64 / (1u64 << 31) as f64 - 0.5) * 40.0;
+                1500.0 + 100.0 * (i as f64 / 25.0).sin() + noise
+            })
+            .collect();
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+
+        let raw_config = BacktestConfig {
+            moving_averages: 10,
+            z_score_threshold: 1.0,
+            ..BacktestConfig::default()
+        };
+        let smoothed_config = BacktestConfig {
+            smoothing: SmoothingKind::Ema,
+            smoothing_period: Some(8),
+            ..raw_config.clone()
+        };
+
+        let flips = |config: &BacktestConfig| {
+            let signals = calculate_z_score_signals(&ratings, config).unwrap();
+            signals["BTCUSDT"].windows(2).filter(|w| w[0].signal != w[1].signal).count()
+        };
+        let (raw_flips, smoothed_flips) = (flips(&raw_config), flips(&smoothed_config));
+        assert!(smoothed_flips < raw_flips, "{smoothed_flips} vs {raw_flips}");
+
+        // SMA over a single period is the raw series
+        let identity_config = BacktestConfig {
+            smoothing_period: Some(1),
+            ..raw_config.clone()
+        };
+        assert_eq!(flips(&identity_config), raw_flips);
+    }
+
+    #[test]
+    fn test_exit_z_score_closes_on_reversion() {
+        // BUY at t=4000 (z ~ 3.4), then the rating drifts back toward its mean (z ~ 0.96)
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1540.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+
+        let baseline = run_backtest(config.clone(), ratings.clone()).unwrap();
+        assert_eq!(baseline.orders.len(), 1);
+
+        let hysteresis = run_backtest(
+            BacktestConfig {
+                exit_z_score: Some(1.5),
+                ..config
+            },
+            ratings,
+        )
+        .unwrap();
+        assert_eq!(hysteresis.orders.len(), 2);
+        let exit = &hysteresis.orders[1];
+        assert_eq!((exit.reason, exit.timestamp), (ExitReason::ExitZScore, 5000));
+    }
+
+    #[test]
+    fn test_parallel_windows_match_serial() {
+        let day_ms = 24 * 60 * 60 * 1000;
+        let series: Vec<f64> = (0..90 * 24)
+            .map(|i| 1500.0 + 80.0 * (i as f64 / 30.0).sin() + 15.0 * (i as f64 / 3.0).cos())
+            .collect();
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 60 * 60 * 1000);
+        let config = BacktestConfig {
+            moving_averages: 10,
+            z_score_threshold: 1.0,
+            start_time: 0,
+            end_time: 90 * day_ms,
+            window_size: Some(1),
+            ..BacktestConfig::default()
+        };
+
+        let serial: Vec<BacktestResult> = window_bounds(&config, &ratings)
+            .into_iter()
+            .filter_map(|bounds| run_window(&config, &ratings, bounds))
+            .collect::<Result<_>>()
+            .unwrap();
+        let parallel = run_windowed_backtest(config, ratings).unwrap();
+
+        assert_eq!(parallel.len(), 5);
+        assert_eq!(
+            serde_json::to_string(&serial).unwrap(),
+            serde_json::to_string(&parallel).unwrap()
+        );
+        assert!(parallel.iter().any(|r| r.total_trades > 0));
+    }
+
+    #[test]
+    fn test_bar_windows_hold_window_size_ratings() {
+        // Irregular spacing: wall-clock windows would hold uneven rating counts
+        let series: Vec<f64> = (0..50).map(|i| 1500.0 + 80.0 * (i as f64 / 4.0).sin()).collect();
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &series, 0);
+        let mut timestamp = 0;
+        for (i, rating) in ratings.iter_mut().enumerate() {
+            timestamp += if i % 7 == 0 { 10 * 60 * 60 * 1000 } else { 60 * 60 * 1000 };
+            rating.timestamp = timestamp;
+        }
+        let config = BacktestConfig {
+            moving_averages: 4,
+            start_time: 0,
+            end_time: timestamp,
+            window_size: Some(20),
+            window_unit: WindowUnit::Bars,
+            window_step: Some(10),
+            ..BacktestConfig::default()
+        };
+
+        let bounds = window_bounds(&config, &ratings);
+        assert_eq!(bounds.len(), 4);
+        for &(start, end) in &bounds {
+            let inside = ratings.iter().filter(|r| r.timestamp >= start && r.timestamp <= end).count();
+            assert_eq!(inside, 20);
+        }
+        assert_eq!(bounds[1].0, ratings[10].timestamp);
+        assert_eq!(run_windowed_backtest(config, ratings).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_short_window_is_skipped() {
+        let day_ms = 24 * 60 * 60 * 1000;
+        let hour_ms = 60 * 60 * 1000;
+        // Hourly ratings for 50 days, then a sparse tail of 5 ratings on day 80
+        let series: Vec<f64> = (0..50 * 24 + 5)
+            .map(|i| 1500.0 + 80.0 * (i as f64 / 30.0).sin())
+            .collect();
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &series, hour_ms);
+        for (i, rating) in ratings.iter_mut().skip(50 * 24).enumerate() {
+            rating.timestamp = 80 * day_ms + i as i64 * hour_ms;
+        }
+        let config = BacktestConfig {
+            moving_averages: 10,
+            start_time: 0,
+            end_time: 90 * day_ms,
+            window_size: Some(1),
+            ..BacktestConfig::default()
+        };
+
+        // The last window, [60d, 90d], holds only the 5-rating tail
+        let bounds = window_bounds(&config, &ratings);
+        assert_eq!(bounds.len(), 5);
+        let (start, end) = bounds[4];
+        assert_eq!(ratings.iter().filter(|r| r.timestamp >= start && r.timestamp <= end).count(), 5);
+
+        let results = run_windowed_backtest(config, ratings).unwrap();
+        assert_eq!(results.len(), 4);
+    }
+
+    #[test]
+    fn test_price_series_covers_every_evaluated_bar() {
+        let series: Vec<f64> = (0..30).map(|i| 1500.0 + (i as f64).sin() * 20.0).collect();
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        for rating in ratings.iter_mut().step_by(3) {
+            rating.symbol = "ETHUSDT".to_string();
+        }
+        let config = BacktestConfig {
+            moving_averages: 4,
+            symbols: Some(vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()]),
+            ..BacktestConfig::default()
+        };
+
+        let without = run_backtest(config.clone(), ratings.clone()).unwrap();
+        assert!(without.price_series.is_none());
+
+        let with = run_backtest(BacktestConfig { include_prices: true, ..config }, ratings.clone()).unwrap();
+        let prices = with.price_series.unwrap();
+        assert_eq!(prices.len(), ratings.len());
+        assert!(prices.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert!((prices[1].price - 100.0 * ratings[1].rating / 1500.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_risk_budget_blocks_volatile_entry() {
+        // Whipsawing ~6% per bar, then a breakout BUY at t=4000
+        let series = [1450.0, 1550.0, 1450.0, 1550.0, 1620.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            ..BacktestConfig::default()
+        };
+
+        let tight = run_backtest(
+            BacktestConfig {
+                max_portfolio_risk_percent: Some(5.0),
+                ..config.clone()
+            },
+            ratings.clone(),
+        )
+        .unwrap();
+        assert!(tight.orders.is_empty());
+        assert_eq!(tight.diagnostics.len(), 1);
+        assert!(tight.diagnostics[0].contains("CVaR"));
+
+        let loose = run_backtest(
+            BacktestConfig {
+                max_portfolio_risk_percent: Some(50.0),
+                ..config
+            },
+            ratings,
+        )
+        .unwrap();
+        assert_eq!(loose.orders.len(), 1);
+    }
+
+    #[test]
+    fn test_entry_priority_funds_highest_z_score_first() {
+        let mut ratings = Vec::new();
+        for (symbol, breakout) in [("AAAUSDT", 1520.0), ("BBBUSDT", 1560.0), ("CCCUSDT", 1540.0)] {
+            ratings.extend(crate::test_utils::ratings(symbol, &[1500.0, 1501.0, 1500.0, 1501.0, breakout], 1000));
+        }
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            symbols: Some(vec!["AAAUSDT".to_string(), "BBBUSDT".to_string(), "CCCUSDT".to_string()]),
+            ..BacktestConfig::default()
+        };
+
+        let entry_order = |config: BacktestConfig| -> Vec<String> {
+            let result = run_backtest(config, ratings.clone()).unwrap();
+            result.orders.iter().map(|o| o.symbol.clone()).collect()
+        };
+
+        assert_eq!(entry_order(config.clone()), ["AAAUSDT", "BBBUSDT", "CCCUSDT"]);
+        let by_z_score = entry_order(BacktestConfig {
+            entry_priority: EntryPriority::HighestZScore,
+            ..config
+        });
+        assert_eq!(by_z_score, ["BBBUSDT", "CCCUSDT", "AAAUSDT"]);
+    }
+
+    #[test]
+    fn test_all_winning_backtest_serializes_infinite_profit_factor() {
+        // Single trade: BUY at t=4000, take profit at t=5000
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1650.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        assert_eq!(result.orders.last().unwrap().reason, ExitReason::ExitProfit);
+        assert_eq!(result.profit_factor, f64::INFINITY);
+
+        let json: serde_json::Value = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["profit_factor"], "Infinity");
+        assert!(json["sharpe_ratio"].is_number());
+
+        let restored: BacktestResult = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.profit_factor, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_sensitivity_flat_surface_has_equal_sharpe() {
+        // Breakout and collapse are far beyond any nearby threshold, and the OCO levels are
+        // far from the traded prices, so small nudges should not change any trade.
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1500.0, 1501.0, 1620.0, 1625.0, 1380.0, 1380.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            z_score_threshold: 1.5,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let deltas = SensitivityDeltas {
+            z_score_threshold: 0.1,
+            profit_percent: 5.0,
+            stop_loss_percent: 5.0,
+            moving_averages: 1,
+        };
+
+        let report = sensitivity(&config, &ratings, &[], &deltas).unwrap();
+        assert_eq!(report.perturbations.len(), 8);
+        assert!(report.baseline_sharpe != 0.0);
+        for point in &report.perturbations {
+            assert!(
+                (point.sharpe_ratio - report.baseline_sharpe).abs() < 1e-6,
+                "{} {:+} moved Sharpe to {}",
+                point.parameter,
+                point.delta,
+                point.sharpe_ratio
+            );
+        }
+    }
+
+    #[test]
+    fn test_drawdown_throttle_shrinks_entries_until_recovery() {
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.drawdown_throttle = Some(DrawdownThrottle {
+            throttle_trigger_percent: 10.0,
+            throttle_factor: 0.5,
+            reset_percent: 2.0,
+        });
+        let config = BacktestConfig::default();
+        let mark = |symbol: &str, price: f64| HashMap::from([(symbol.to_string(), price)]);
+
+        // A 20% fall on a 95% allocation is a 19% drawdown
+        portfolio.open_position("BTCUSDT".to_string(), 100.0, 1, &config, 0.95 * portfolio.allocation_scale());
+        portfolio.update_equity_curve(2, &mark("BTCUSDT", 80.0));
+        portfolio.close_position("BTCUSDT", 80.0, 3, ExitReason::ExitStop);
+        assert_eq!(portfolio.allocation_scale(), 0.5);
+
+        let cash = portfolio.cash;
+        portfolio.open_position("ETHUSDT".to_string(), 10.0, 4, &config, 0.95 * portfolio.allocation_scale());
+        let throttled_qty = portfolio.positions["ETHUSDT"].quantity;
+        assert!((throttled_qty - cash * 0.95 * 0.5 / 10.0).abs() < 1e-9);
+
+        // Recovered to ~4% under the peak: below the trigger but not yet at the reset level
+        portfolio.update_equity_curve(5, &mark("ETHUSDT", 14.0));
+        assert!(portfolio.throttled);
+
+        // Back to a new high releases it
+        portfolio.close_position("ETHUSDT", 15.0, 6, ExitReason::ExitProfit);
+        portfolio.update_equity_curve(6, &HashMap::new());
+        assert!(portfolio.cash > 10000.0);
+        assert_eq!(portfolio.allocation_scale(), 1.0);
+
+        let cash = portfolio.cash;
+        portfolio.open_position("SOLUSDT".to_string(), 10.0, 7, &config, 0.95 * portfolio.allocation_scale());
+        assert!((portfolio.positions["SOLUSDT"].quantity - cash * 0.95 / 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_classify_signal_threshold_is_exclusive() {
+        assert_eq!(classify_signal(2.0, 2.0), Signal::Hold);
+        assert_eq!(classify_signal(-2.0, 2.0), Signal::Hold);
+        assert_eq!(classify_signal(2.0 + 1e-9, 2.0), Signal::Buy);
+        assert_eq!(classify_signal(-2.0 - 1e-9, 2.0), Signal::Sell);
+        assert_eq!(classify_signal(0.0, 2.0), Signal::Hold);
+
+        assert_eq!(serde_json::to_string(&Signal::Buy).unwrap(), "\"BUY\"");
+        assert_eq!(Signal::Sell.to_string(), "SELL");
+        assert_eq!(serde_json::from_str::<Signal>("\"HOLD\"").unwrap(), Signal::Hold);
+    }
+
+    #[test]
+    fn test_drawdown_curve_peak_matches_max_drawdown() {
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1580.0, 1530.0, 1545.0, 1440.0, 1450.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            z_score_threshold: 2.0,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+
+        let without = run_backtest(config.clone(), ratings.clone()).unwrap();
+        assert!(without.equity_curve.is_none() && without.drawdown_curve.is_none());
+
+        let with = run_backtest(BacktestConfig { include_equity_curve: true, ..config }, ratings).unwrap();
+        let equity = with.equity_curve.unwrap();
+        let drawdowns = with.drawdown_curve.unwrap();
+        assert_eq!(equity.len(), drawdowns.len());
+        assert_eq!(equity[0], (0, 10000.0));
+
+        let deepest = drawdowns.iter().map(|&(_, dd)| dd).fold(0.0, f64::max);
+        assert!(with.max_drawdown > 0.0);
+        assert_eq!(deepest, with.max_drawdown);
+    }
+
+    #[test]
+    fn test_benchmark_symbol_changes_alpha() {
+        // ETH breaks out and is traded; BTC drifts steadily up and is never traded
+        let eth = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1580.0, 1530.0, 1545.0, 1440.0, 1450.0];
+        let btc = [1500.0, 1505.0, 1512.0, 1518.0, 1521.0, 1530.0, 1534.0, 1541.0, 1547.0, 1552.0];
+        let mut ratings = crate::test_utils::ratings("ETHUSDT", &eth, 1000);
+        ratings.extend(crate::test_utils::ratings("BTCUSDT", &btc, 1000));
+        let config = BacktestConfig {
+            symbols: Some(vec!["ETHUSDT".to_string()]),
+            moving_averages: 4,
+            z_score_threshold: 2.0,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+
+        let self_benchmarked = run_backtest(config.clone(), ratings.clone()).unwrap();
+        let btc_benchmarked = run_backtest(
+            BacktestConfig { benchmark_symbol: Some("BTCUSDT".to_string()), ..config.clone() },
+            ratings.clone(),
+        )
+        .unwrap();
+
+        assert!(self_benchmarked.total_trades > 0);
+        assert_eq!(self_benchmarked.total_return, btc_benchmarked.total_return);
+        assert!((self_benchmarked.alpha - btc_benchmarked.alpha).abs() > 1e-6);
+        assert!((self_benchmarked.beta - btc_benchmarked.beta).abs() > 1e-6);
+
+        // A benchmark missing from the supplied klines is an error
+        let klines = vec![crate::test_utils::kline("ETHUSDT", 0, 100.0, 101.0)];
+        let missing = BacktestConfig { benchmark_symbol: Some("BTCUSDT".to_string()), ..config };
+        assert!(run_backtest_with_klines(missing, ratings, &klines).is_err());
+    }
+
+    #[test]
+    fn test_per_symbol_breakdown_isolates_each_symbol() {
+        let config = BacktestConfig {
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let mut portfolio = Portfolio::new(10000.0);
+        // 10 units per trade: BTC +100, +50; ETH -60
+        let trades = [("BTCUSDT", 110.0), ("ETHUSDT", 94.0), ("BTCUSDT", 105.0)];
+        for (i, (symbol, exit_price)) in trades.iter().enumerate() {
+            let t = i as i64 * 10;
+            let allocation = 1000.0 / portfolio.cash;
+            portfolio.open_position(symbol.to_string(), 100.0, t, &config, allocation);
+            portfolio.close_position(symbol, *exit_price, t + 1, ExitReason::ExitZScore);
+        }
+
+        let metrics = calculate_performance_metrics(&portfolio, 10000.0, &BacktestConfig::default());
+        let btc = &metrics.per_symbol["BTCUSDT"];
+        assert_eq!((btc.total_trades, btc.win_ratio), (2, 1.0));
+        assert!((btc.net_pnl - 150.0).abs() < 1e-9);
+
+        let eth = &metrics.per_symbol["ETHUSDT"];
+        assert_eq!((eth.total_trades, eth.win_ratio), (1, 0.0));
+        assert!((eth.net_pnl - -60.0).abs() < 1e-9);
+        assert_eq!(metrics.per_symbol.len(), 2);
+    }
+
+    #[test]
+    fn test_erratic_equity_has_higher_annualized_volatility() {
+        // Both curves end 10% up
+        let mut smooth = Portfolio::new(10000.0);
+        smooth.equity_curve = vec![(0, 10000.0), (1, 10250.0), (2, 10500.0), (3, 10750.0), (4, 11000.0)];
+        let mut erratic = Portfolio::new(10000.0);
+        erratic.equity_curve = vec![(0, 10000.0), (1, 11500.0), (2, 9500.0), (3, 12000.0), (4, 11000.0)];
+
+        let config = BacktestConfig {
+            trading_periods_per_year: Some(252.0),
+            ..BacktestConfig::default()
+        };
+        let smooth = calculate_performance_metrics(&smooth, 10000.0, &config);
+        let erratic = calculate_performance_metrics(&erratic, 10000.0, &config);
+        assert!((smooth.total_return - erratic.total_return).abs() < 1e-12);
+        assert!(erratic.annualized_volatility > smooth.annualized_volatility);
+        assert!(smooth.annualized_volatility > 0.0);
+    }
+
+    #[test]
+    fn test_single_point_equity_curve_yields_zero_metrics() {
+        // Only ETH is rated, so the BTC backtest never sees a bar
+        let series: Vec<f64> = (0..10).map(|i| 1500.0 + i as f64).collect();
+        let ratings = crate::test_utils::ratings("ETHUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        for metric in [
+            result.total_return,
+            result.annualized_return,
+            result.sharpe_ratio,
+            result.sortino_ratio,
+            result.annualized_volatility,
+            result.max_drawdown,
+            result.profit_factor,
+        ] {
+            assert_eq!(metric, 0.0);
+        }
+        assert!(result.diagnostics.iter().any(|d| d.contains("No price bars for BTCUSDT")));
+    }
+
+    #[test]
+    fn test_time_in_market_annualization_differs_for_half_exposure() {
+        let year_ms = YEAR_MS as i64;
+        let config = BacktestConfig {
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            start_time: 0,
+            end_time: year_ms,
+            ..BacktestConfig::default()
+        };
+        // In the market for the first half of the year, ending 10% up
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &config, 1.0);
+        portfolio.close_position("BTCUSDT", 110.0, year_ms / 2, ExitReason::ExitZScore);
+        portfolio.equity_curve.push((year_ms / 2, portfolio.cash));
+        portfolio.equity_curve.push((year_ms, portfolio.cash));
+
+        let calendar = calculate_performance_metrics(&portfolio, 10000.0, &config);
+        let in_market = calculate_performance_metrics(
+            &portfolio,
+            10000.0,
+            &BacktestConfig { annualization_basis: AnnualizationBasis::TimeInMarket, ..config },
+        );
+
+        assert!((calendar.annualized_return - 0.10).abs() < 1e-9);
+        assert!((in_market.annualized_return - 0.21).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rolling_sharpe_declines_with_degrading_edge() {
+        let mut portfolio = Portfolio::new(10000.0);
+        let mut value = 10000.0;
+        for i in 0..40 {
+            // Steady gains, then a choppy, losing second half
+            let step = if i < 20 { 0.01 + 0.002 * (i % 2) as f64 } else { [0.02, -0.03][i % 2] };
+            value *= 1.0 + step;
+            portfolio.equity_curve.push((i as i64 + 1, value));
+        }
+        let config = BacktestConfig {
+            rolling_sharpe_window: Some(10),
+            ..BacktestConfig::default()
+        };
+
+        let rolling = calculate_performance_metrics(&portfolio, 10000.0, &config).rolling_sharpe.unwrap();
+        assert_eq!(rolling.len(), 40 - 10 + 1);
+        assert_eq!(rolling[0].0, 10);
+        let (first, last) = (rolling[0].1, rolling.last().unwrap().1);
+        assert!(first > 0.0 && last < 0.0, "rolling Sharpe went from {} to {}", first, last);
+
+        assert!(calculate_performance_metrics(&portfolio, 10000.0, &BacktestConfig::default())
+            .rolling_sharpe
+            .is_none());
+    }
+
+    #[test]
+    fn test_inverted_signals_buy_where_original_sells() {
+        let series = [
+            1500.0, 1501.0, 1500.0, 1501.0, 1440.0, 1435.0, 1500.0, 1560.0, 1565.0, 1500.0, 1430.0, 1500.0,
+        ];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            z_score_threshold: 1.5,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let inverted_config = BacktestConfig { invert_signals: Some(true), ..config.clone() };
+
+        let original = calculate_z_score_signals(&ratings, &config).unwrap();
+        let sell_times: Vec<i64> = original["BTCUSDT"]
+            .iter()
+            .filter(|s| s.signal == Signal::Sell)
+            .map(|s| s.timestamp)
+            .collect();
+        assert!(!sell_times.is_empty());
+
+        let result = run_backtest(inverted_config, ratings).unwrap();
+        let buy_times: Vec<i64> = result
+            .orders
+            .iter()
+            .filter(|o| o.side == OrderSide::Buy)
+            .map(|o| o.timestamp)
+            .collect();
+        assert!(!buy_times.is_empty());
+        assert_eq!(buy_times[0], sell_times[0]);
+        assert!(buy_times.iter().all(|t| sell_times.contains(t)));
+    }
+
+    #[test]
+    fn test_session_hours_gate_entries_but_not_exits() {
+        // Hourly bars from midnight UTC: BUY at 04:00, SELL at 08:00
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1580.0, 1530.0, 1545.0, 1440.0, 1450.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, crate::test_utils::HOUR_MS);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            z_score_threshold: 2.0,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+
+        let inside = run_backtest(BacktestConfig { session_hours: Some((2, 6)), ..config.clone() }, ratings.clone())
+            .unwrap();
+        let sides: Vec<(OrderSide, i64)> = inside
+            .orders
+            .iter()
+            .map(|o| (o.side, o.timestamp / crate::test_utils::HOUR_MS))
+            .collect();
+        // The 08:00 exit fires even though it is outside the session
+        assert_eq!(sides, vec![(OrderSide::Buy, 4), (OrderSide::Sell, 8)]);
+
+        let outside = run_backtest(BacktestConfig { session_hours: Some((6, 12)), ..config }, ratings).unwrap();
+        assert!(outside.orders.is_empty());
+
+        assert!(in_session(23 * crate::test_utils::HOUR_MS, (22, 6)));
+        assert!(!in_session(12 * crate::test_utils::HOUR_MS, (22, 6)));
+    }
+
+    #[test]
+    fn test_max_open_positions_caps_simultaneous_entries() {
+        // All three symbols break out together at t=4000
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0];
+        let symbols = ["BTCUSDT", "ETHUSDT", "SOLUSDT"];
+        let ratings: Vec<GlickoRating> = symbols
+            .iter()
+            .flat_map(|symbol| crate::test_utils::ratings(symbol, &series, 1000))
+            .collect();
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            symbols: Some(symbols.iter().map(|s| s.to_string()).collect()),
+            max_open_positions: Some(2),
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        let entries: Vec<&str> = result
+            .orders
+            .iter()
+            .filter(|o| o.reason == ExitReason::Entry)
+            .map(|o| o.symbol.as_str())
+            .collect();
+        // Alphabetical entry priority funds the first two
+        assert_eq!(entries, vec!["BTCUSDT", "ETHUSDT"]);
+        assert_eq!(result.open_positions.len(), 2);
+        assert!(result
+            .diagnostics
+            .iter()
+            .any(|d| d.contains("SOLUSDT") && d.contains("max_open_positions (2) reached")));
+
+        // The two slots split the cash rather than reserving a third share
+        let btc = &result.orders[0];
+        assert!((btc.quantity * btc.price - 10000.0 * 0.95 / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_total_equity_sizing_compounds_off_open_positions() {
+        let entry_size = |sizing_base, allocation| {
+            let config = BacktestConfig { sizing_base, ..BacktestConfig::default() };
+            let mut portfolio = Portfolio::new(10000.0);
+            portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &config, 0.5).unwrap();
+            // BTC falls 20%: 5,000 cash + 4,000 of BTC
+            let prices = HashMap::from([("BTCUSDT".to_string(), 80.0)]);
+            portfolio.update_equity_curve(1, &prices);
+            portfolio.open_position("ETHUSDT".to_string(), 10.0, 1, &config, allocation).unwrap();
+            portfolio.positions["ETHUSDT"].quantity * 10.0
+        };
+
+        assert!((entry_size(SizingBase::AvailableCash, 0.5) - 2500.0).abs() < 1e-9);
+        assert!((entry_size(SizingBase::TotalEquity, 0.5) - 4500.0).abs() < 1e-9);
+        // 90% of 9,000 equity is more than the free cash, so only the cash is committed
+        assert!((entry_size(SizingBase::TotalEquity, 0.9) - 5000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_risk_budget_sizes_candidate_like_the_entry() {
+        let mut portfolio = Portfolio::new(10000.0);
+        let config = BacktestConfig::default();
+        portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &config, 0.5).unwrap();
+        // BTC falls 20%: 5,000 cash + 4,000 of BTC; BTC has no history, so carries no risk
+        let prices = HashMap::from([("BTCUSDT".to_string(), 80.0), ("ETHUSDT".to_string(), 10.0)]);
+        portfolio.update_equity_curve(1, &prices);
+        let eth: Vec<f64> = (0..30).map(|i| if i % 2 == 0 { 10.0 } else { 11.0 }).collect();
+        let volatility = return_volatility(&eth, config.moving_averages);
+        let history = HashMap::from([("ETHUSDT".to_string(), eth)]);
+
+        // A budget between a 2,500 (cash-sized) and a 4,500 (equity-sized) entry
+        let budget = 3500.0 * volatility * CVAR_95_MULTIPLIER / 9000.0 * 100.0;
+        let rejected = |config: &BacktestConfig| {
+            risk_budget_rejection(&portfolio, "ETHUSDT", 0.5, budget, config, &prices, &history).is_some()
+        };
+
+        assert!(!rejected(&config));
+        let total_equity = BacktestConfig { sizing_base: SizingBase::TotalEquity, ..config };
+        assert!(rejected(&total_equity));
+        // Capped at 0.3 x 9,000 equity the entry fits again
+        assert!(!rejected(&BacktestConfig { max_notional_multiple: Some(0.3), ..total_equity }));
+    }
+
+    #[test]
+    fn test_max_notional_multiple_clamps_leveraged_entry() {
+        let config = BacktestConfig {
+            leverage: Some(10.0),
+            max_notional_multiple: Some(3.0),
+            ..BacktestConfig::default()
+        };
+        let mut portfolio = Portfolio::new(10000.0);
+
+        // 95% at 10x would be 95,000 notional; the cap is 3 x 10,000
+        portfolio.open_position("BTCUSDT".to_string(), 100.0, 0, &config, 0.95);
+        let position = &portfolio.positions["BTCUSDT"];
+        assert!((position.quantity * 100.0 - 30000.0).abs() < 1e-9);
+        assert!((position.margin - 3000.0).abs() < 1e-9);
+        assert!(portfolio.diagnostics[0].contains("max_notional_multiple 3"));
+    }
+
+    #[test]
+    fn test_multi_symbol_result_json_is_reproducible() {
+        let symbols = ["SOLUSDT", "BTCUSDT", "ETHUSDT", "ADAUSDT"];
+        let mut ratings = Vec::new();
+        for (offset, symbol) in symbols.iter().enumerate() {
+            let series: Vec<f64> = (0..40)
+                .map(|i| 1500.0 + 60.0 * ((i as f64 + offset as f64 * 3.0) / 4.0).sin())
+                .collect();
+            ratings.extend(crate::test_utils::ratings(symbol, &series, 1000));
+        }
+        let config = BacktestConfig {
+            moving_averages: 5,
+            z_score_threshold: 1.0,
+            symbols: Some(symbols.iter().map(|s| s.to_string()).collect()),
+            ..BacktestConfig::default()
+        };
+
+        let first = run_backtest(config.clone(), ratings.clone()).unwrap();
+        assert!(first.per_symbol.len() > 1);
+        let first_json = serde_json::to_string(&first).unwrap();
+        for _ in 0..5 {
+            let again = serde_json::to_string(&run_backtest(config.clone(), ratings.clone()).unwrap()).unwrap();
+            assert_eq!(again, first_json);
+        }
+
+        let signals = serde_json::to_string(&calculate_z_score_signals(&ratings, &config).unwrap()).unwrap();
+        assert!(signals.find("ADAUSDT").unwrap() < signals.find("SOLUSDT").unwrap());
+    }
+
+    #[test]
+    fn test_typical_fill_price_differs_from_close_on_wide_candle() {
+        use crate::test_utils::{kline, HOUR_MS};
+        use crate::PriceSource;
+
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, HOUR_MS);
+        let mut klines: Vec<KlineData> = (0..series.len())
+            .map(|i| kline("BTCUSDT", i as i64 * HOUR_MS, 100.0, 100.0))
+            .collect();
+        // The BUY bar trades between 90 and 130 and closes at 104
+        klines[4] = KlineData { high: 130.0, low: 90.0, close: 104.0, ..klines[4].clone() };
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            ..BacktestConfig::default()
+        };
+        let entry_price = |fill_price: PriceSource| {
+            let config = BacktestConfig { fill_price, ..config.clone() };
+            run_backtest_with_klines(config, ratings.clone(), &klines).unwrap().orders[0].price
+        };
+
+        assert_eq!(entry_price(PriceSource::Close), 104.0);
+        assert!((entry_price(PriceSource::Typical) - (130.0 + 90.0 + 104.0) / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_under_length_symbol_is_reported_as_skipped() {
+        let series: Vec<f64> = (0..10).map(|i| 1500.0 + (i % 3) as f64).collect();
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        ratings.extend(crate::test_utils::ratings("ETHUSDT", &series[..3], 1000));
+
+        let config = BacktestConfig {
+            symbols: Some(vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()]),
+            moving_averages: 5,
+            ..BacktestConfig::default()
+        };
+        let (signals, skipped) = calculate_z_score_signals_with_skipped(&ratings, &config).unwrap();
+        assert_eq!(signals.keys().collect::<Vec<_>>(), ["BTCUSDT"]);
+        assert_eq!(signals["BTCUSDT"].len(), 5);
+        assert_eq!(skipped, vec![("ETHUSDT".to_string(), 3)]);
+
+        let result = run_backtest(config, ratings).unwrap();
+        assert_eq!(result.skipped_symbols, vec![("ETHUSDT".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_simple_return_basis_divides_total_return_by_years() {
+        let year_ms = YEAR_MS as i64;
+        let config = BacktestConfig { start_time: 0, end_time: 2 * year_ms, ..BacktestConfig::default() };
+        // 21% total over two years
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.equity_curve.push((2 * year_ms, 12100.0));
+
+        let compounded = calculate_performance_metrics(&portfolio, 10000.0, &config);
+        let simple = calculate_performance_metrics(
+            &portfolio,
+            10000.0,
+            &BacktestConfig { return_basis: ReturnBasis::Simple, ..config },
+        );
+
+        assert!((compounded.annualized_return - 0.10).abs() < 1e-9);
+        assert!((simple.annualized_return - 0.105).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_min_bars_between_trades_suppresses_quick_reentry() {
+        // BUY at bars 4, 6 and 9; the 2% stop closes each entry on the following bar
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1500.0, 1620.0, 1500.0, 1500.0, 1700.0, 1500.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 2.0,
+            ..BacktestConfig::default()
+        };
+        let entry_times = |config: BacktestConfig| -> Vec<i64> {
+            run_backtest(config, ratings.clone())
+                .unwrap()
+                .orders
+                .iter()
+                .filter(|o| o.reason == ExitReason::Entry)
+                .map(|o| o.timestamp)
+                .collect()
+        };
+
+        assert_eq!(entry_times(config.clone()), vec![4000, 6000, 9000]);
+        let throttled = BacktestConfig { min_bars_between_trades: Some(3), ..config };
+        assert_eq!(entry_times(throttled), vec![4000, 9000]);
+    }
+
+    #[test]
+    fn test_warmup_trades_are_flagged_and_excluded_from_metrics() {
+        // Same series as above: BUY at bars 4, 6 and 9, each stopped out on the next bar;
+        // signals start at bar 4
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1500.0, 1620.0, 1500.0, 1500.0, 1700.0, 1500.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, 1000);
+        let run = |warmup_bars| {
+            let config = BacktestConfig {
+                moving_averages: 4,
+                profit_percent: Some(50.0),
+                stop_loss_percent: 2.0,
+                warmup_bars,
+                ..BacktestConfig::default()
+            };
+            run_backtest(config, ratings.clone()).unwrap()
+        };
+
+        let baseline = run(None);
+        assert_eq!(baseline.total_trades, 3);
+        assert!(baseline.orders.iter().all(|o| !o.warmup));
+
+        for (warmup_bars, counted) in [(2, 2), (4, 1)] {
+            let result = run(Some(warmup_bars));
+            assert_eq!(result.total_trades, counted, "warmup_bars = {}", warmup_bars);
+            // Every order is still simulated; the warm-up round trips are flagged
+            assert_eq!(result.orders.len(), baseline.orders.len());
+            assert_eq!(result.orders.iter().filter(|o| o.warmup).count(), 2 * (3 - counted));
+            assert_eq!(result.total_return, baseline.total_return);
+        }
+    }
+
+    #[test]
+    fn test_warmup_holdings_are_excluded_from_avg_trade_duration() {
+        // BUY at bar 4 held for four bars until the collapse at bar 8, then a BUY at bar 12
+        // stopped out on the next bar
+        let series = [
+            1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1560.0, 1560.0, 1560.0, 1500.0, 1500.0, 1501.0, 1500.0, 1620.0,
+            1500.0,
+        ];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, crate::test_utils::HOUR_MS);
+        let run = |warmup_bars| {
+            let config = BacktestConfig {
+                moving_averages: 4,
+                profit_percent: Some(50.0),
+                stop_loss_percent: 2.0,
+                warmup_bars,
+                ..BacktestConfig::default()
+            };
+            run_backtest(config, ratings.clone()).unwrap()
+        };
+
+        let baseline = run(None);
+        assert_eq!(baseline.total_trades, 2);
+        assert!((baseline.avg_trade_duration - 2.5).abs() < 1e-9);
+
+        // Only the one-hour trade after the warm-up counts
+        let result = run(Some(1));
+        assert_eq!(result.total_trades, 1);
+        assert!((result.avg_trade_duration - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_warmup_is_excluded_from_benchmark_metrics() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 300);
+        let ratings = crate::glicko::calculate_ratings(klines.clone()).unwrap();
+        let run = |warmup_bars| {
+            let config = BacktestConfig {
+                moving_averages: 20,
+                z_score_threshold: 1.0,
+                start_time: klines[0].open_time,
+                end_time: klines.last().unwrap().open_time,
+                warmup_bars,
+                include_equity_curve: true,
+                ..BacktestConfig::default()
+            };
+            run_backtest_with_klines(config, ratings.clone(), &klines).unwrap()
+        };
+
+        let baseline = run(None);
+        let result = run(Some(100));
+        let curve = result.equity_curve.as_ref().unwrap();
+        assert_eq!(curve, baseline.equity_curve.as_ref().unwrap());
+
+        // Benchmark-relative metrics cover the same steady-state span as the Sharpe
+        let warmup_end = curve.iter().position(|&(timestamp, _)| timestamp == klines[100 + 20].open_time);
+        let risk_curve = &curve[warmup_end.unwrap() - 1..];
+        let benchmark = benchmark_prices("BTCUSDT", &ratings, &klines).unwrap();
+        let config = BacktestConfig { rating_period_ms: Some(crate::test_utils::HOUR_MS), ..BacktestConfig::default() };
+        let periods_per_year = periods_per_year(&config);
+        let risk_free = per_period_risk_free(&config, risk_curve, periods_per_year);
+        assert_eq!((result.alpha, result.beta), alpha_beta(risk_curve, &benchmark, &risk_free, periods_per_year));
+        assert_eq!(
+            (result.upside_capture, result.downside_capture),
+            capture_ratios(risk_curve, &benchmark)
+        );
+        assert_eq!(result.relative_max_drawdown, relative_max_drawdown(risk_curve, &benchmark));
+        assert_ne!(result.alpha, baseline.alpha);
+    }
+
+    #[test]
+    fn test_rating_period_defaults_to_the_inferred_kline_interval() {
+        use crate::test_utils::HOUR_MS;
+
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 600);
+        let ratings = crate::glicko::calculate_ratings(klines.clone()).unwrap();
+        let base = BacktestConfig {
+            moving_averages: 20,
+            z_score_threshold: 1.0,
+            start_time: klines[0].open_time,
+            end_time: klines.last().unwrap().open_time,
+            // Without a risk-free rate the Sharpe scales exactly with sqrt(periods per year)
+            risk_free_rate: 0.0,
+            ..BacktestConfig::default()
+        };
+        let sharpe = |config: BacktestConfig| {
+            run_backtest_with_klines(config, ratings.clone(), &klines).unwrap().sharpe_ratio
+        };
+
+        let inferred = sharpe(base.clone());
+        let hourly = sharpe(BacktestConfig { trading_periods_per_year: Some(365.25 * 24.0), ..base.clone() });
+        let daily = sharpe(BacktestConfig { rating_period_ms: Some(24 * HOUR_MS), ..base });
+        assert!(inferred != 0.0);
+        assert!((inferred - hourly).abs() < 1e-9);
+        assert!((inferred / daily - 24f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trade_ledger_pairs_round_trips() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 600);
+        let ratings = crate::glicko::calculate_ratings(klines.clone()).unwrap();
+        let config = BacktestConfig {
+            moving_averages: 20,
+            z_score_threshold: 1.0,
+            profit_percent: Some(3.0),
+            stop_loss_percent: 2.0,
+            start_time: klines[0].open_time,
+            end_time: klines.last().unwrap().open_time,
+            rebalance_interval_ms: Some(24 * crate::test_utils::HOUR_MS),
+            ..BacktestConfig::default()
+        };
+        let result = run_backtest_with_klines(config, ratings, &klines).unwrap();
+
+        let round_trips = result
+            .orders
+            .iter()
+            .filter(|o| o.side == OrderSide::Sell && o.reason != ExitReason::Rebalance)
+            .count();
+        assert!(round_trips > 1);
+        assert_eq!(result.trade_ledger.len(), round_trips);
+
+        let realized: f64 = result
+            .orders
+            .iter()
+            .filter(|o| o.side == OrderSide::Sell)
+            .filter(|o| !result.open_positions.iter().any(|p| p.symbol == o.symbol && p.entry_time <= o.timestamp))
+            .filter_map(|o| o.profit_loss)
+            .sum();
+        let ledger_pnl: f64 = result.trade_ledger.iter().map(|t| t.profit_loss).sum();
+        assert!((ledger_pnl - realized).abs() < 1e-6, "{} vs {}", ledger_pnl, realized);
+        for trade in &result.trade_ledger {
+            assert!(trade.exit_time >= trade.entry_time);
+            assert!(trade.entry_equity > 0.0 && trade.exit_equity > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_pnl_curve_is_unrealized_until_exit() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        // BUY at bar 4, never exited: the rating doesn't signal SELL and the OCO is wide
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0, 1570.0, 1571.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, HOUR_MS);
+        let closes = [100.0, 100.0, 100.0, 100.0, 100.0, 104.0, 97.0, 101.0];
+        let klines: Vec<KlineData> = closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| kline("BTCUSDT", i as i64 * HOUR_MS, close, close))
+            .collect();
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            include_equity_curve: true,
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest_with_klines(config, ratings, &klines).unwrap();
+        let entry = &result.orders[0];
+        assert_eq!((entry.side, entry.timestamp), (OrderSide::Buy, 4 * HOUR_MS));
+        assert!(result.orders.iter().all(|o| o.side == OrderSide::Buy));
+
+        let pnl_curve = result.pnl_curve.unwrap();
+        assert_eq!(pnl_curve.len(), result.equity_curve.unwrap().len());
+        for &(timestamp, realized, unrealized) in &pnl_curve {
+            assert!(realized.abs() < 1e-9);
+            let close = closes[(timestamp / HOUR_MS) as usize];
+            let expected = if timestamp >= entry.timestamp { entry.quantity * (close - entry.price) } else { 0.0 };
+            assert!((unrealized - expected).abs() < 1e-9, "{}: {} vs {}", timestamp, unrealized, expected);
+        }
+    }
+
+    #[test]
+    fn test_trading_days_calendar_skips_weekend_in_durations() {
+        let day_ms = 24 * 60 * 60 * 1000;
+        let monday = 1704067200000; // 2024-01-01
+        // BUY on Friday (day 4), SELL a week later on the following Friday (day 11)
+        let series = [
+            1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0, 1570.0, 1571.0, 1570.0, 1571.0, 1570.0, 1400.0,
+        ];
+        let mut ratings = crate::test_utils::ratings("BTCUSDT", &series, day_ms);
+        ratings.iter_mut().for_each(|r| r.timestamp += monday);
+        let run = |market_calendar| {
+            let config = BacktestConfig {
+                moving_averages: 4,
+                profit_percent: Some(50.0),
+                stop_loss_percent: 50.0,
+                start_time: monday,
+                end_time: monday + 11 * day_ms,
+                market_calendar,
+                ..BacktestConfig::default()
+            };
+            run_backtest(config, ratings.clone()).unwrap()
+        };
+
+        let continuous = run(MarketCalendar::Crypto24x7);
+        let trading_days = run(MarketCalendar::TradingDays);
+        assert_eq!(continuous.trade_ledger.len(), 1);
+        assert_eq!(continuous.avg_trade_duration, 7.0 * 24.0);
+        assert_eq!(trading_days.avg_trade_duration, 5.0 * 24.0);
+        assert_eq!(trading_days.trade_ledger[0].duration_hours, 5.0 * 24.0);
+        // The 11-day span from Monday holds 9 trading days of a 365.25 * 5/7 day year
+        assert_eq!(trading_days.total_return, continuous.total_return);
+        let years = 9.0 / (365.25 * 5.0 / 7.0);
+        let expected = (1.0 + trading_days.total_return).powf(1.0 / years) - 1.0;
+        assert!((trading_days.annualized_return - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_intrabar_wick_liquidates_leveraged_long() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, HOUR_MS);
+        let mut klines: Vec<KlineData> = (0..series.len())
+            .map(|i| kline("BTCUSDT", i as i64 * HOUR_MS, 100.0, 100.0))
+            .collect();
+        // 10x long from 100 liquidates near 90.5; the wick reaches 85 but the bar closes flat
+        klines[5] = KlineData { low: 85.0, ..klines[5].clone() };
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 20.0,
+            leverage: Some(10.0),
+            ..BacktestConfig::default()
+        };
+        let result = run_backtest_with_klines(config, ratings, &klines).unwrap();
+
+        let exit = &result.orders[1];
+        assert_eq!((exit.reason, exit.timestamp), (ExitReason::ExitLiquidation, 5 * HOUR_MS));
+        assert!(exit.price > 85.0 && exit.price < 100.0);
+    }
+
+    #[test]
+    fn test_intrabar_wick_stops_out_before_liquidation() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, HOUR_MS);
+        let mut klines: Vec<KlineData> = (0..series.len())
+            .map(|i| kline("BTCUSDT", i as i64 * HOUR_MS, 100.0, 100.0))
+            .collect();
+        // 10x long from 100 liquidates near 90.5, below the 5% stop at 95; the wick reaches both
+        klines[5] = KlineData { low: 85.0, ..klines[5].clone() };
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 5.0,
+            leverage: Some(10.0),
+            ..BacktestConfig::default()
+        };
+        let result = run_backtest_with_klines(config, ratings, &klines).unwrap();
+
+        let entry = &result.orders[0];
+        let exit = &result.orders[1];
+        assert_eq!((exit.reason, exit.timestamp), (ExitReason::ExitStop, 5 * HOUR_MS));
+        assert!((exit.price - entry.price * 0.95).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_intrabar_assumption_resolves_wide_candle() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        let series = [1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, HOUR_MS);
+        let mut klines: Vec<KlineData> = (0..series.len())
+            .map(|i| kline("BTCUSDT", i as i64 * HOUR_MS, 100.0, 100.0))
+            .collect();
+        // Entry at 100 sets SL 95 / TP 105; the next candle spans both and closes flat
+        klines[5] = KlineData { high: 110.0, low: 90.0, close: 100.0, ..klines[5].clone() };
+
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(5.0),
+            stop_loss_percent: 5.0,
+            ..BacktestConfig::default()
+        };
+        let exit = |intrabar_assumption| {
+            let config = BacktestConfig { intrabar_assumption, ..config.clone() };
+            let result = run_backtest_with_klines(config, ratings.clone(), &klines).unwrap();
+            let order = result.orders[1].clone();
+            (order.reason, order.price)
+        };
+
+        let (reason, price) = exit(IntrabarAssumption::PessimisticStopFirst);
+        assert_eq!(reason, ExitReason::ExitStop);
+        assert!((price - 95.0).abs() < 1e-9);
+        let (reason, price) = exit(IntrabarAssumption::OptimisticProfitFirst);
+        assert_eq!(reason, ExitReason::ExitProfit);
+        assert!((price - 105.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_alpha_uses_the_configured_risk_free_rate() {
+        const DAY_MS: i64 = 86_400_000;
+        let equity_curve: Vec<(i64, f64)> = (0..10)
+            .map(|day| (day * DAY_MS, 10000.0 * 1.01f64.powi(day as i32)))
+            .collect();
+        // A flat benchmark has zero beta, so alpha is the annualized excess return
+        let benchmark = vec![(0, 100.0)];
+        let alpha = |config: &BacktestConfig| {
+            let risk_free = per_period_risk_free(config, &equity_curve, 365.0);
+            alpha_beta(&equity_curve, &benchmark, &risk_free, 365.0).0
+        };
+
+        let default = alpha(&BacktestConfig::default());
+        let flat = alpha(&BacktestConfig { risk_free_rate: 0.10, ..BacktestConfig::default() });
+        let curve = alpha(&BacktestConfig { risk_free_curve: Some(vec![(0, 0.10)]), ..BacktestConfig::default() });
+        assert!((default - flat - 0.08).abs() < 1e-9);
+        assert!((curve - flat).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_rising_risk_free_curve_lowers_later_sharpe() {
+        const DAY_MS: i64 = 86_400_000;
+        // Alternating +2% / -1% days, so every 2-return window has the same raw Sharpe
+        let mut portfolio = Portfolio::new(10000.0);
+        let mut value = 10000.0;
+        for day in 1..=20 {
+            value *= if day % 2 == 1 { 1.02 } else { 0.99 };
+            portfolio.equity_curve.push((day * DAY_MS, value));
+        }
+        let flat = BacktestConfig {
+            rolling_sharpe_window: Some(2),
+            risk_free_curve: Some(vec![(0, 0.02)]),
+            ..BacktestConfig::default()
+        };
+        let rising = BacktestConfig {
+            risk_free_curve: Some(vec![(0, 0.02), (11 * DAY_MS, 0.50)]),
+            ..flat.clone()
+        };
+
+        let flat_metrics = calculate_performance_metrics(&portfolio, 10000.0, &flat);
+        let rising_metrics = calculate_performance_metrics(&portfolio, 10000.0, &rising);
+        let default_metrics = calculate_performance_metrics(
+            &portfolio,
+            10000.0,
+            &BacktestConfig { risk_free_curve: None, ..flat.clone() },
+        );
+
+        assert!((flat_metrics.sharpe_ratio - default_metrics.sharpe_ratio).abs() < 1e-9);
+        assert!(rising_metrics.sharpe_ratio < flat_metrics.sharpe_ratio);
+        assert!(rising_metrics.sortino_ratio < flat_metrics.sortino_ratio);
+        for (flat_point, rising_point) in flat_metrics
+            .rolling_sharpe
+            .unwrap()
+            .iter()
+            .zip(rising_metrics.rolling_sharpe.unwrap())
+        {
+            if rising_point.0 <= 10 * DAY_MS {
+                assert!((flat_point.1 - rising_point.1).abs() < 1e-9);
+            } else {
+                assert!(rising_point.1 < flat_point.1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_idle_cash_accrues_configured_yield() {
+        const DAY_MS: i64 = 86_400_000;
+        // A flat rating never leaves HOLD, so the whole balance stays in cash
+        let series = vec![1500.0; 366];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, DAY_MS);
+        let config = BacktestConfig {
+            start_time: 0,
+            end_time: 365 * DAY_MS,
+            cash_yield_annual: Some(0.05),
+            ..BacktestConfig::default()
+        };
+
+        let result = run_backtest(config, ratings).unwrap();
+        assert_eq!(result.total_trades, 0);
+        assert!((result.total_return - 0.05).abs() < 1e-3);
+        assert!((result.annualized_return - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_capture_ratios_for_strategy_sidestepping_declines() {
+        // Benchmark alternates +2% / -2%; the strategy matches the rallies and sits
+        // out the declines except for a small 0.2% loss
+        let mut benchmark = vec![(0, 100.0)];
+        let mut equity_curve = vec![(0, 10000.0)];
+        for i in 1..=10 {
+            let up = i % 2 == 1;
+            let (_, price) = *benchmark.last().unwrap();
+            let (_, value) = *equity_curve.last().unwrap();
+            benchmark.push((i, price * if up { 1.02 } else { 0.98 }));
+            equity_curve.push((i, value * if up { 1.02 } else { 0.998 }));
+        }
+
+        let (upside, downside) = capture_ratios(&equity_curve, &benchmark);
+        assert!((upside - 1.0).abs() < 1e-9);
+        assert!((downside - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_stepper_reproduces_run_backtest() {
+        let symbols = ["BTCUSDT", "ETHUSDT", "SOLUSDT"];
+        let mut ratings = Vec::new();
+        for (offset, symbol) in symbols.iter().enumerate() {
+            let series: Vec<f64> = (0..40)
+                .map(|i| 1500.0 + 60.0 * ((i as f64 + offset as f64 * 3.0) / 4.0).sin())
+                .collect();
+            ratings.extend(crate::test_utils::ratings(symbol, &series, 1000));
+        }
+        let config = BacktestConfig {
+            moving_averages: 5,
+            z_score_threshold: 1.0,
+            symbols: Some(symbols.iter().map(|s| s.to_string()).collect()),
+            include_equity_curve: true,
+            ..BacktestConfig::default()
+        };
+
+        let mut stepper = BacktestStepper::new(config.clone(), ratings.clone(), &[]).unwrap();
+        let mut steps = Vec::new();
+        while let Some(step) = stepper.next_step() {
+            steps.push(step);
+        }
+        let stepped = stepper.finish().unwrap();
+        let expected = run_backtest(config, ratings).unwrap();
+
+        assert_eq!(serde_json::to_string(&stepped).unwrap(), serde_json::to_string(&expected).unwrap());
+        assert_eq!(steps.len(), 40);
+        assert_eq!(steps[3].prices.len(), 3);
+        let step_orders: usize = steps.iter().map(|step| step.orders.len()).sum();
+        assert_eq!(step_orders, expected.orders.len());
+        let curve = expected.equity_curve.unwrap();
+        assert_eq!(steps[10].equity, curve[11].1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_flattens_and_halts_trading() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        // BUY at bar 4 and again at bar 12; the rating never signals SELL in between
+        let series = [
+            1500.0, 1501.0, 1500.0, 1501.0, 1560.0, 1570.0, 1570.0, 1570.0, 1570.0, 1571.0, 1570.0, 1571.0, 1640.0,
+        ];
+        let ratings = crate::test_utils::ratings("BTCUSDT", &series, HOUR_MS);
+        // The price collapses 30% right after the first entry
+        let klines: Vec<KlineData> = (0..series.len())
+            .map(|i| {
+                let close = if i < 5 { 100.0 } else { 70.0 };
+                kline("BTCUSDT", i as i64 * HOUR_MS, close, close)
+            })
+            .collect();
+        let config = BacktestConfig {
+            moving_averages: 4,
+            profit_percent: Some(50.0),
+            stop_loss_percent: 50.0,
+            max_drawdown_stop_percent: Some(20.0),
+            ..BacktestConfig::default()
+        };
+        let signals = calculate_z_score_signals(&ratings, &config).unwrap();
+        assert_eq!(signals["BTCUSDT"].last().unwrap().signal, Signal::Buy);
+
+        let result = run_backtest_with_klines(config, ratings, &klines).unwrap();
+        assert!(result.circuit_breaker_triggered);
+        let orders: Vec<(ExitReason, i64)> = result.orders.iter().map(|o| (o.reason, o.timestamp)).collect();
+        assert_eq!(
+            orders,
+            vec![(ExitReason::Entry, 4 * HOUR_MS), (ExitReason::ExitCircuitBreaker, 5 * HOUR_MS)]
+        );
+        assert!(result.open_positions.is_empty());
+    }
+
+    #[test]
+    fn test_two_quote_portfolio_is_valued_in_reporting_currency() {
+        let config = BacktestConfig {
+            symbols: Some(vec!["ETHBTC".to_string(), "SOLUSDT".to_string()]),
+            quote_conversions: Some(BTreeMap::from([("BTC".to_string(), vec![(0, 50000.0), (2000, 40000.0)])])),
+            ..BacktestConfig::default()
+        };
+        let conversions = symbol_conversions(&config, &config.traded_symbols());
+        assert_eq!(conversions.keys().collect::<Vec<_>>(), ["ETHBTC"]);
+
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.quote_rates.insert("ETHBTC".to_string(), step_held(&conversions["ETHBTC"], 1000).unwrap());
+        // 5000 USD buys 0.1 BTC of margin, i.e. 2 ETH at 0.05 BTC
+        let eth = portfolio.open_position("ETHBTC".to_string(), 0.05, 1000, &config, 0.5).unwrap();
+        assert!((eth.quantity - 2.0).abs() < 1e-9);
+        portfolio.open_position("SOLUSDT".to_string(), 100.0, 1000, &config, 1.0).unwrap();
+        assert!(portfolio.cash.abs() < 1e-9);
+
+        // ETH rises to 0.06 BTC while BTC falls to 40000 USD: 0.12 BTC = 4800 USD, plus 50 SOL at 110
+        portfolio.quote_rates.insert("ETHBTC".to_string(), step_held(&conversions["ETHBTC"], 2000).unwrap());
+        let prices = HashMap::from([("ETHBTC".to_string(), 0.06), ("SOLUSDT".to_string(), 110.0)]);
+        assert!((portfolio.get_portfolio_value(&prices) - 10300.0).abs() < 1e-6);
+
+        portfolio.close_position("ETHBTC", 0.06, 2000, ExitReason::ExitZScore);
+        assert!((portfolio.cash - 4800.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_falling_less_than_benchmark_has_no_relative_drawdown() {
+        // Benchmark halves while the strategy loses 20%, both in a straight line
+        let benchmark: Vec<(i64, f64)> = (0..=10).map(|i| (i, 100.0 - 5.0 * i as f64)).collect();
+        let equity_curve: Vec<(i64, f64)> = (0..=10).map(|i| (i, 10000.0 - 200.0 * i as f64)).collect();
+        let mut portfolio = Portfolio::new(10000.0);
+        portfolio.equity_curve = equity_curve.clone();
+
+        let absolute = calculate_performance_metrics(&portfolio, 10000.0, &BacktestConfig::default()).max_drawdown;
+        assert!((absolute - 0.2).abs() < 1e-9);
+        assert_eq!(relative_max_drawdown(&equity_curve, &benchmark), 0.0);
+
+        // Giving back 1000 of the lead while the benchmark is flat is a 10% relative drawdown
+        let mut lagging = equity_curve;
+        lagging.push((11, 7000.0));
+        let mut flat = benchmark;
+        flat.push((11, 50.0));
+        assert!((relative_max_drawdown(&lagging, &flat) - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_risk_reward_ratio_derives_take_profit() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 200);
+        let ratings = crate::glicko::calculate_ratings(klines.clone()).unwrap();
+        let config = BacktestConfig {
+            moving_averages: 20,
+            profit_percent: None,
+            stop_loss_percent: 2.0,
+            risk_reward_ratio: Some(2.0),
+            start_time: klines[0].open_time,
+            end_time: klines.last().unwrap().open_time,
+            ..BacktestConfig::default()
+        };
+        assert!((config.effective_profit_percent() - 4.0).abs() < 1e-12);
+        assert!(run_backtest_with_klines(config.clone(), ratings.clone(), &klines).is_ok());
+
+        // A zero take-profit without a ratio is still accepted
+        let zero = BacktestConfig { profit_percent: Some(0.0), risk_reward_ratio: None, ..config.clone() };
+        assert!(run_backtest_with_klines(zero, ratings.clone(), &klines).is_ok());
+
+        let both = BacktestConfig { profit_percent: Some(5.0), ..config };
+        let err = run_backtest_with_klines(both, ratings, &klines).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BacktestError>(),
+            Some(BacktestError::AmbiguousTakeProfit { risk_reward_ratio, .. }) if *risk_reward_ratio == 2.0
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_ratings_are_deduped_to_the_clean_signals() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 200);
+        let clean = crate::glicko::calculate_ratings(klines).unwrap();
+        let mut duplicated = clean.clone();
+        duplicated.extend(clean.iter().step_by(7).cloned());
+        let config = BacktestConfig { moving_averages: 20, z_score_threshold: 1.0, ..BacktestConfig::default() };
+
+        let (deduped, dropped) = dedupe_ratings(duplicated.clone(), DuplicateRatings::KeepLast).unwrap();
+        assert_eq!(dropped, clean.len().div_ceil(7));
+        let clean_signals = serde_json::to_string(&calculate_z_score_signals(&clean, &config).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&calculate_z_score_signals(&deduped, &config).unwrap()).unwrap(),
+            clean_signals
+        );
+        // Signal generation on its own (generate-signals) dedupes the same way
+        assert_eq!(
+            serde_json::to_string(&crate::generate_signals(&config, &duplicated).unwrap()).unwrap(),
+            clean_signals
+        );
+
+        assert!(matches!(
+            dedupe_ratings(duplicated.clone(), DuplicateRatings::Error),
+            Err(BacktestError::DuplicateRating { timestamp, .. }) if timestamp == clean[0].timestamp
+        ));
+        let strict = BacktestConfig { duplicate_ratings: DuplicateRatings::Error, ..config };
+        assert!(matches!(
+            calculate_z_score_signals(&duplicated, &strict),
+            Err(BacktestError::DuplicateRating { timestamp, .. }) if timestamp == clean[0].timestamp
+        ));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{KlineData, Result};
+use serde_json::Value;
+
+/// Binance caps a single `/api/v3/klines` response at 1000 candles.
+pub const MAX_KLINES_PER_REQUEST: usize = 1000;
+
+#[cfg(feature = "binance")]
+const KLINES_ENDPOINT: &str = "https://api.binance.com/api/v3/klines";
+#[cfg(feature = "binance")]
+const MAX_RETRIES: u32 = 5;
+
+/// Map a raw `/api/v3/klines` response body into `KlineData`.
+///
+/// Binance returns each candle as a positional array:
+/// `[open_time, open, high, low, close, volume, close_time, quote_asset_volume,
+///   number_of_trades, taker_buy_base_asset_volume, taker_buy_quote_asset_volume, ignore]`
+/// with prices and volumes encoded as strings.
+pub fn parse_klines_response(symbol: &str, body: &str) -> Result<Vec<KlineData>> {
+    let rows: Vec<Vec<Value>> = serde_json::from_str(body)?;
+
+    rows.iter()
+        .map(|row| {
+            if row.len() < 11 {
+                return Err(format!("Malformed kline row with {} fields", row.len()).into());
+            }
+
+            Ok(KlineData {
+                symbol: symbol.to_string(),
+                open_time: as_i64(&row[0])?,
+                close_time: as_i64(&row[6])?,
+                open: as_f64(&row[1])?,
+                high: as_f64(&row[2])?,
+                low: as_f64(&row[3])?,
+                close: as_f64(&row[4])?,
+                volume: as_f64(&row[5])?,
+                quote_asset_volume: as_f64(&row[7])?,
+                number_of_trades: as_i64(&row[8])? as u32,
+                taker_buy_base_asset_volume: as_f64(&row[9])?,
+                taker_buy_quote_asset_volume: as_f64(&row[10])?,
+            })
+        })
+        .collect()
+}
+
+fn as_i64(value: &Value) -> Result<i64> {
+    value
+        .as_i64()
+        .ok_or_else(|| format!("Expected integer kline field, got {}", value).into())
+}
+
+fn as_f64(value: &Value) -> Result<f64> {
+    match value {
+        Value::String(s) => Ok(s.parse::<f64>()?),
+        Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| format!("Invalid numeric kline field {}", n).into()),
+        other => Err(format!("Expected numeric kline field, got {}", other).into()),
+    }
+}
+
+/// Fetch klines for `symbol` between `start_time` and `end_time` (epoch ms, inclusive).
+///
+/// Requests are paginated over the 1000-candle limit by advancing `startTime` past the
+/// last returned candle. HTTP 429 (rate limited) and 418 (IP ban warning) responses are
+/// retried with exponential backoff, honouring `Retry-After` when Binance sends it.
+#[cfg(feature = "binance")]
+pub fn fetch_klines(
+    symbol: &str,
+    interval: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Vec<KlineData>> {
+    let client = reqwest::blocking::Client::new();
+    let mut klines = Vec::new();
+    let mut cursor = start_time;
+
+    while cursor <= end_time {
+        let body = fetch_page(&client, symbol, interval, cursor, end_time)?;
+        let page = parse_klines_response(symbol, &body)?;
+        let page_len = page.len();
+
+        let Some(last_open_time) = page.last().map(|k| k.open_time) else {
+            break;
+        };
+
+        klines.extend(page);
+
+        if page_len < MAX_KLINES_PER_REQUEST {
+            break;
+        }
+        cursor = last_open_time + 1;
+    }
+
+    Ok(klines)
+}
+
+#[cfg(feature = "binance")]
+fn fetch_page(
+    client: &reqwest::blocking::Client,
+    symbol: &str,
+    interval: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<String> {
+    use reqwest::StatusCode;
+    use std::time::Duration;
+
+    let mut attempt = 0;
+
+    loop {
+        let response = client
+            .get(KLINES_ENDPOINT)
+            .query(&[
+                ("symbol", symbol.to_string()),
+                ("interval", interval.to_string()),
+                ("startTime", start_time.to_string()),
+                ("endTime", end_time.to_string()),
+                ("limit", MAX_KLINES_PER_REQUEST.to_string()),
+            ])
+            .send()?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 418 {
+            if attempt >= MAX_RETRIES {
+                return Err(format!("Binance rate limit persisted after {} retries", MAX_RETRIES).into());
+            }
+
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(1 << attempt);
+
+            tracing::warn!(status = status.as_u16(), retry_after, attempt, "Binance rate limited, backing off");
+            std::thread::sleep(Duration::from_secs(retry_after));
+            attempt += 1;
+            continue;
+        }
+
+        if !status.is_success() {
+            return Err(format!("Binance request failed with status {}: {}", status, response.text()?).into());
+        }
+
+        return Ok(response.text()?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Recorded response from GET /api/v3/klines?symbol=BTCUSDT&interval=1h&limit=2
+    const RECORDED_RESPONSE: &str = r#"[
+        [1640995200000, "46216.93000000", "46731.39000000", "46208.37000000", "46656.13000000",
+         "1503.33095000", 1640998799999, "69849528.86542510", 38608, "806.69187000",
+         "37481468.53473580", "0"],
+        [1640998800000, "46656.14000000", "46949.99000000", "46574.06000000", "46778.14000000",
+         "943.81539000", 1641002399999, "44130453.00130350", 26157, "494.43981000",
+         "23117830.88591240", "0"]
+    ]"#;
+
+    #[test]
+    fn test_parse_klines_response_field_mapping() {
+        let klines = parse_klines_response("BTCUSDT", RECORDED_RESPONSE).unwrap();
+        assert_eq!(klines.len(), 2);
+
+        let first = &klines[0];
+        assert_eq!(first.symbol, "BTCUSDT");
+        assert_eq!(first.open_time, 1640995200000);
+        assert_eq!(first.close_time, 1640998799999);
+        assert_eq!(first.open, 46216.93);
+        assert_eq!(first.high, 46731.39);
+        assert_eq!(first.low, 46208.37);
+        assert_eq!(first.close, 46656.13);
+        assert_eq!(first.volume, 1503.33095);
+        assert_eq!(first.quote_asset_volume, 69849528.8654251);
+        assert_eq!(first.number_of_trades, 38608);
+        assert_eq!(first.taker_buy_base_asset_volume, 806.69187);
+        assert_eq!(first.taker_buy_quote_asset_volume, 37481468.5347358);
+    }
+
+    #[test]
+    fn test_parse_klines_response_rejects_short_rows() {
+        let result = parse_klines_response("BTCUSDT", "[[1640995200000, \"1.0\"]]");
+        assert!(result.is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::SmoothingKind;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,13 +10,44 @@ pub struct HybridScore {
     pub confidence: ScoreConfidence,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ScoreConfidence {
     High,
     Low,
     Neutral,
 }
 
+impl ScoreConfidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScoreConfidence::High => "high",
+            ScoreConfidence::Low => "low",
+            ScoreConfidence::Neutral => "neutral",
+        }
+    }
+}
+
+impl std::fmt::Display for ScoreConfidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ScoreConfidence {
+    type Err = String;
+
+    /// Parses the lowercase wire names, case-insensitively for CLI convenience.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "high" => Ok(ScoreConfidence::High),
+            "low" => Ok(ScoreConfidence::Low),
+            "neutral" => Ok(ScoreConfidence::Neutral),
+            other => Err(format!("Unknown score confidence `{}` (expected high, low or neutral)", other)),
+        }
+    }
+}
+
 impl HybridScore {
     pub fn calculate(
         open: f64,
@@ -33,7 +65,7 @@ impl HybridScore {
             0.5
         } else {
             // Continuous scaling bounded to [0.0, 1.0]
-            (0.5 + price_change * 50.0).max(0.0).min(1.0)
+            (0.5 + price_change * 50.0).clamp(0.0, 1.0)
         };
 
         // Determine price direction and dominance for metadata
@@ -41,21 +73,87 @@ impl HybridScore {
         let price_unchanged = (close - open).abs() < 0.001; // < 0.1% change threshold
         let taker_buy_dominant = taker_buy_volume > taker_sell_volume;
 
-        // Assign confidence level based on game result magnitude
-        let confidence = if (score - 0.5).abs() < 0.1 {
-            ScoreConfidence::Neutral  // Close to 0.5 (draw)
-        } else if (score - 0.5).abs() < 0.25 {
-            ScoreConfidence::Low      // Low-confidence move (0.25-0.75 range)
-        } else {
-            ScoreConfidence::High     // High-confidence move (0.0-0.25 or 0.75-1.0)
-        };
-
         Self {
             price_up,
             price_unchanged,
             taker_buy_dominant,
             score,
-            confidence,
+            confidence: Self::confidence_for(score),
+        }
+    }
+
+    /// Like `calculate`, but discounts thinly traded candles.
+    ///
+    /// With `number_of_trades` below `min_trades_for_full_confidence`, the score is pulled
+    /// toward 0.5 in proportion to the shortfall (e.g. half the trades keeps half the
+    /// distance from 0.5) and confidence is assigned from the damped score.
+    pub fn calculate_with_trades(
+        open: f64,
+        close: f64,
+        taker_buy_volume: f64,
+        taker_sell_volume: f64,
+        number_of_trades: Option<u32>,
+        min_trades_for_full_confidence: u32,
+    ) -> Self {
+        let mut hybrid = Self::calculate(open, close, taker_buy_volume, taker_sell_volume);
+
+        if let Some(trades) = number_of_trades {
+            if trades < min_trades_for_full_confidence {
+                let weight = trades as f64 / min_trades_for_full_confidence as f64;
+                hybrid.score = 0.5 + (hybrid.score - 0.5) * weight;
+                hybrid.confidence = Self::confidence_for(hybrid.score);
+            }
+        }
+
+        hybrid
+    }
+
+    /// Same as `calculate`, with confidence labelled using custom `bands`.
+    pub fn calculate_with_bands(
+        open: f64,
+        close: f64,
+        taker_buy_volume: f64,
+        taker_sell_volume: f64,
+        bands: &ConfidenceBands,
+    ) -> Self {
+        let mut hybrid = Self::calculate(open, close, taker_buy_volume, taker_sell_volume);
+        hybrid.confidence = bands.classify(hybrid.score);
+        hybrid
+    }
+
+    fn confidence_for(score: f64) -> ScoreConfidence {
+        ConfidenceBands::default().classify(score)
+    }
+}
+
+/// Distance-from-0.5 cut-offs used to label a hybrid score's confidence.
+///
+/// A score within `neutral` of 0.5 is Neutral, within `low` is Low, otherwise High.
+/// The defaults (0.1 and 0.25) match the live engine.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceBands {
+    pub neutral: f64,
+    pub low: f64,
+}
+
+impl Default for ConfidenceBands {
+    fn default() -> Self {
+        Self {
+            neutral: 0.1,
+            low: 0.25,
+        }
+    }
+}
+
+impl ConfidenceBands {
+    /// Assign confidence level based on game result magnitude
+    pub fn classify(&self, score: f64) -> ScoreConfidence {
+        if (score - 0.5).abs() < self.neutral {
+            ScoreConfidence::Neutral  // Close to 0.5 (draw)
+        } else if (score - 0.5).abs() < self.low {
+            ScoreConfidence::Low      // Low-confidence move (0.25-0.75 range by default)
+        } else {
+            ScoreConfidence::High     // High-confidence move (0.0-0.25 or 0.75-1.0 by default)
         }
     }
 }
@@ -68,8 +166,12 @@ pub struct MovingStats {
 }
 
 impl MovingStats {
+    /// Non-finite entries in `values` are skipped and a non-finite `current_value` scores
+    /// 0, so one bad rating cannot turn every z-score into NaN.
     pub fn calculate(values: &[f64], current_value: f64) -> Self {
-        if values.is_empty() {
+        let finite = || values.iter().copied().filter(|v| v.is_finite());
+        let count = finite().count();
+        if count == 0 {
             return Self {
                 mean: current_value,
                 std_dev: 0.0,
@@ -77,15 +179,63 @@ impl MovingStats {
             };
         }
 
-        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let mean = finite().sum::<f64>() / count as f64;
         
-        let variance = values
-            .iter()
+        let variance = finite()
             .map(|x| (x - mean).powi(2))
-            .sum::<f64>() / values.len() as f64;
+            .sum::<f64>() / count as f64;
         
         let std_dev = variance.sqrt();
         
+        let z_score = if std_dev > 0.0 && current_value.is_finite() {
+            (current_value - mean) / std_dev
+        } else {
+            0.0
+        };
+
+        Self {
+            mean,
+            std_dev,
+            z_score,
+        }
+    }
+}
+
+/// Exponentially weighted mean/std of a rating window.
+///
+/// Uses the incremental EW variance recurrence, seeded with the first window value:
+/// - diff = x - mean
+/// - mean += alpha * diff
+/// - variance = (1 - alpha) * (variance + alpha * diff^2)
+#[derive(Debug, Clone)]
+pub struct EmaStats {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub z_score: f64,
+}
+
+impl EmaStats {
+    pub fn calculate(values: &[f64], current_value: f64, alpha: f64) -> Self {
+        let Some((&first, rest)) = values.split_first() else {
+            return Self {
+                mean: current_value,
+                std_dev: 0.0,
+                z_score: 0.0,
+            };
+        };
+
+        let alpha = alpha.clamp(f64::EPSILON, 1.0);
+        let mut mean = first;
+        let mut variance = 0.0;
+
+        for &value in rest {
+            let diff = value - mean;
+            let increment = alpha * diff;
+            mean += increment;
+            variance = (1.0 - alpha) * (variance + diff * increment);
+        }
+
+        let std_dev = variance.sqrt();
         let z_score = if std_dev > 0.0 {
             (current_value - mean) / std_dev
         } else {
@@ -100,6 +250,80 @@ impl MovingStats {
     }
 }
 
+/// Wilder's Relative Strength Index over a value series.
+///
+/// Returns one entry per input value; the first `period` entries are `None` because
+/// the initial average gain/loss needs `period` changes. A series with no losses in
+/// the lookback reads 100, and a completely flat one reads 50.
+pub fn calculate_rsi(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut rsi = vec![None; values.len()];
+    if period == 0 || values.len() <= period {
+        return rsi;
+    }
+
+    let changes: Vec<f64> = values.windows(2).map(|w| w[1] - w[0]).collect();
+    let mut avg_gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f64>() / period as f64;
+    let mut avg_loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f64>() / period as f64;
+
+    let to_rsi = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            if gain == 0.0 { 50.0 } else { 100.0 }
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+
+    rsi[period] = Some(to_rsi(avg_gain, avg_loss));
+
+    for (i, change) in changes.iter().enumerate().skip(period) {
+        avg_gain = (avg_gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        avg_loss = (avg_loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        rsi[i + 1] = Some(to_rsi(avg_gain, avg_loss));
+    }
+
+    rsi
+}
+
+/// Causally smooth a series with the chosen moving average.
+///
+/// Each output only uses values up to and including its own index, so no future data
+/// leaks into a signal. The first `period - 1` outputs average the values available so
+/// far. A period of 0 or 1 returns the series unchanged.
+pub fn smooth_series(values: &[f64], kind: SmoothingKind, period: usize) -> Vec<f64> {
+    if period <= 1 {
+        return values.to_vec();
+    }
+
+    match kind {
+        SmoothingKind::Sma => (0..values.len())
+            .map(|i| {
+                let window = &values[(i + 1).saturating_sub(period)..=i];
+                window.iter().sum::<f64>() / window.len() as f64
+            })
+            .collect(),
+        SmoothingKind::Ema => {
+            let alpha = 2.0 / (period as f64 + 1.0);
+            let mut ema = None;
+            values
+                .iter()
+                .map(|&value| {
+                    let next = ema.map_or(value, |prev: f64| alpha * value + (1.0 - alpha) * prev);
+                    ema = Some(next);
+                    next
+                })
+                .collect()
+        }
+        SmoothingKind::Wma => (0..values.len())
+            .map(|i| {
+                let window = &values[(i + 1).saturating_sub(period)..=i];
+                let weights = (1..=window.len()).map(|w| w as f64);
+                let weighted: f64 = window.iter().zip(weights.clone()).map(|(v, w)| v * w).sum();
+                weighted / weights.sum::<f64>()
+            })
+            .collect(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,13 +339,38 @@ mod tests {
 
     #[test]
     fn test_hybrid_score_low_confidence_win() {
-        let score = HybridScore::calculate(100.0, 105.0, 500.0, 1000.0);
-        assert_eq!(score.score, 0.75);
+        let score = HybridScore::calculate(100.0, 100.3, 500.0, 1000.0);
+        assert!((score.score - 0.65).abs() < 1e-9);
         assert!(matches!(score.confidence, ScoreConfidence::Low));
         assert!(score.price_up);
         assert!(!score.taker_buy_dominant);
     }
 
+    #[test]
+    fn test_few_trades_damp_score_toward_neutral() {
+        // +1% move: raw score 1.0
+        let thin = HybridScore::calculate_with_trades(100.0, 101.0, 600.0, 400.0, Some(10), 1000);
+        let deep = HybridScore::calculate_with_trades(100.0, 101.0, 600.0, 400.0, Some(10_000), 1000);
+
+        assert_eq!(deep.score, 1.0);
+        assert!((thin.score - 0.505).abs() < 1e-9);
+        assert!((thin.score - 0.5).abs() < (deep.score - 0.5).abs());
+        assert_eq!(thin.confidence, ScoreConfidence::Neutral);
+        assert_eq!(deep.confidence, ScoreConfidence::High);
+    }
+
+    #[test]
+    fn test_custom_confidence_bands_reclassify_low_as_high() {
+        // +0.4% move: score 0.7, Low under the default bands
+        let default = HybridScore::calculate(100.0, 100.4, 600.0, 400.0);
+        assert_eq!(default.confidence, ScoreConfidence::Low);
+
+        let bands = ConfidenceBands { neutral: 0.05, low: 0.15 };
+        let tuned = HybridScore::calculate_with_bands(100.0, 100.4, 600.0, 400.0, &bands);
+        assert_eq!(tuned.confidence, ScoreConfidence::High);
+        assert_eq!(tuned.score, default.score);
+    }
+
     #[test]
     fn test_hybrid_score_draw() {
         let score = HybridScore::calculate(100.0, 100.0, 500.0, 1000.0);
@@ -139,4 +388,52 @@ mod tests {
         assert!(stats.std_dev > 0.0);
         assert!(stats.z_score > 0.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_moving_stats_skips_non_finite_values() {
+        let clean = MovingStats::calculate(&[1.0, 2.0, 3.0, 4.0, 5.0], 6.0);
+        let dirty = MovingStats::calculate(&[1.0, 2.0, f64::NAN, 3.0, 4.0, f64::INFINITY, 5.0], 6.0);
+        assert_eq!(dirty.mean, clean.mean);
+        assert_eq!(dirty.z_score, clean.z_score);
+
+        assert_eq!(MovingStats::calculate(&[1.0, 2.0, 3.0], f64::NAN).z_score, 0.0);
+    }
+
+    #[test]
+    fn test_ema_reacts_faster_than_sma_on_trend() {
+        let values: Vec<f64> = (0..20).map(|i| 1500.0 + i as f64 * 10.0).collect();
+        let current = 1700.0;
+
+        let sma = MovingStats::calculate(&values, current);
+        let ema = EmaStats::calculate(&values, current, 2.0 / 21.0);
+
+        // The EMA mean sits closer to the latest ratings, so the same point is less extreme
+        assert!(ema.mean > sma.mean);
+        assert!(ema.z_score.abs() < sma.z_score.abs());
+        assert!(ema.z_score > 0.0);
+    }
+
+    #[test]
+    fn test_score_confidence_lowercase_round_trip() {
+        assert_eq!(serde_json::to_string(&ScoreConfidence::High).unwrap(), "\"high\"");
+        let parsed: ScoreConfidence = serde_json::from_str("\"neutral\"").unwrap();
+        assert_eq!(parsed, ScoreConfidence::Neutral);
+
+        for confidence in [ScoreConfidence::High, ScoreConfidence::Low, ScoreConfidence::Neutral] {
+            assert_eq!(confidence.to_string().parse::<ScoreConfidence>().unwrap(), confidence);
+        }
+        assert_eq!("HIGH".parse::<ScoreConfidence>().unwrap(), ScoreConfidence::High);
+        assert!("extreme".parse::<ScoreConfidence>().is_err());
+    }
+
+    #[test]
+    fn test_smooth_series_kinds() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(smooth_series(&values, SmoothingKind::Ema, 1), values.to_vec());
+        assert_eq!(smooth_series(&values, SmoothingKind::Sma, 2), vec![1.0, 1.5, 2.5, 3.5]);
+        // Weights 1, 2: (3*1 + 4*2) / 3
+        assert!((smooth_series(&values, SmoothingKind::Wma, 2)[3] - 11.0 / 3.0).abs() < 1e-12);
+        // alpha = 2/3: 1, 5/3, 23/9
+        assert!((smooth_series(&values, SmoothingKind::Ema, 2)[2] - 23.0 / 9.0).abs() < 1e-12);
+    }
+}
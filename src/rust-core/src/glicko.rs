@@ -1,21 +1,27 @@
 use crate::{KlineData, GlickoRating, Result};
-use crate::data::{HybridScore, MovingStats};
-use std::collections::HashMap;
+use crate::data::HybridScore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
 
 // Glicko-2 constants
-const TAU: f64 = 0.5; // System constant (volatility change)
+pub const TAU: f64 = 0.5; // System constant (volatility change)
 const EPSILON: f64 = 0.000001;
+const MAX_VOLATILITY_ITERATIONS: usize = 50;
 const GLICKO2_SCALE: f64 = 173.7178;
 const DEFAULT_RATING: f64 = 1500.0;
 const DEFAULT_RD: f64 = 350.0;
 const DEFAULT_VOLATILITY: f64 = 0.06;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlickoPlayer {
     pub symbol: String,
     pub rating: f64,
     pub rating_deviation: f64,
     pub volatility: f64,
+    /// Epoch ms of the last rated kline (or decay); `None` until first rated
+    #[serde(default)]
+    pub last_updated: Option<i64>,
 }
 
 impl GlickoPlayer {
@@ -25,6 +31,7 @@ impl GlickoPlayer {
             rating: DEFAULT_RATING,
             rating_deviation: DEFAULT_RD,
             volatility: DEFAULT_VOLATILITY,
+            last_updated: None,
         }
     }
 
@@ -41,22 +48,171 @@ impl GlickoPlayer {
     }
 }
 
-// E(mu, mu_j, phi_j) function from Glicko-2 spec
-fn e_function(mu: f64, mu_j: f64, g_phi_j: f64) -> f64 {
+/// E(μ, μ_j, g(φ_j)) function from the Glicko-2 spec (inputs on the Glicko-2 scale).
+pub fn e_function(mu: f64, mu_j: f64, g_phi_j: f64) -> f64 {
     1.0 / (1.0 + (-g_phi_j * (mu - mu_j)).exp())
 }
 
-// g(phi) function from Glicko-2 spec
-fn g_function(phi: f64) -> f64 {
+/// g(φ) function from the Glicko-2 spec (φ on the Glicko-2 scale).
+pub fn g_function(phi: f64) -> f64 {
     1.0 / (1.0 + 3.0 * phi.powi(2) / std::f64::consts::PI.powi(2)).sqrt()
 }
 
+/// Expected score (win probability) of `player` against an opponent given on the
+/// original rating scale.
+///
+/// ```
+/// use glicko_core::glicko::{expected_score, GlickoPlayer};
+///
+/// let player = GlickoPlayer::new("BTCUSDT".to_string());
+/// assert!((expected_score(&player, 1500.0, 50.0) - 0.5).abs() < 1e-12);
+/// assert!(expected_score(&player, 1300.0, 50.0) > 0.5);
+/// ```
+pub fn expected_score(player: &GlickoPlayer, opponent_rating: f64, opponent_rd: f64) -> f64 {
+    let (mu, _) = player.to_glicko2_scale();
+    let mu_j = (opponent_rating - DEFAULT_RATING) / GLICKO2_SCALE;
+    let phi_j = opponent_rd / GLICKO2_SCALE;
+    e_function(mu, mu_j, g_function(phi_j))
+}
+
+/// Probability that the next period is a "win" for `rating`'s symbol against a benchmark
+/// with the given rating and RD. Both deviations widen the uncertainty, so the
+/// probability is pulled towards 0.5 as either RD grows; equal ratings give exactly 0.5.
+pub fn win_probability(rating: &GlickoRating, benchmark_rating: f64, benchmark_rd: f64) -> f64 {
+    let mu = (rating.rating - DEFAULT_RATING) / GLICKO2_SCALE;
+    let mu_j = (benchmark_rating - DEFAULT_RATING) / GLICKO2_SCALE;
+    let phi = rating.rating_deviation.hypot(benchmark_rd) / GLICKO2_SCALE;
+    e_function(mu, mu_j, g_function(phi))
+}
+
+/// Lower/upper clamps applied to any newly computed volatility.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolatilityBounds {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for VolatilityBounds {
+    fn default() -> Self {
+        // Reasonable range used by the live engine
+        Self { min: 0.01, max: 0.2 }
+    }
+}
+
+impl VolatilityBounds {
+    fn apply(&self, sigma: f64) -> f64 {
+        sigma.clamp(self.min, self.max)
+    }
+}
+
 // Simplified volatility calculation (matches live engine algorithm)
 // σ' = √(σ² + δ²/v)
 fn calculate_new_volatility(sigma: f64, delta: f64, v: f64) -> f64 {
     let new_sigma = (sigma.powi(2) + (delta.powi(2) / v)).sqrt();
-    // Bound volatility to reasonable range [0.01, 0.2]
-    new_sigma.max(0.01).min(0.2)
+    VolatilityBounds::default().apply(new_sigma)
+}
+
+/// Full Glicko-2 step 5: solve for σ' with the Illinois (regula falsi) iteration.
+///
+/// f(x) = e^x(Δ² - φ² - v - e^x) / (2(φ² + v + e^x)²) - (x - ln σ²) / τ²,  σ' = e^(x/2)
+///
+/// Hardened for pathological inputs (e.g. a huge Δ from an extreme score):
+/// - both the bracket search and the iteration stop after 50 steps; if the bracket has
+///   not closed, the endpoint with the smaller |f| is used rather than blindly taking A
+/// - a non-finite result falls back to the previous σ
+/// - the returned σ' is clamped to `bounds` so one period cannot blow up the next RD
+pub fn find_new_volatility(
+    sigma: f64,
+    phi: f64,
+    v: f64,
+    delta: f64,
+    tau: f64,
+    bounds: VolatilityBounds,
+) -> f64 {
+    find_new_volatility_diagnostic(sigma, phi, v, delta, tau, bounds).volatility
+}
+
+/// Convergence report from the Illinois volatility solver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolatilityDiagnostics {
+    /// Clamped σ', identical to what `find_new_volatility` returns
+    pub volatility: f64,
+    /// Illinois iterations performed (capped at 50)
+    pub iterations: usize,
+    /// |f(x)| at the chosen root estimate, before clamping
+    pub residual: f64,
+    /// Whether the bracket closed to within EPSILON before the iteration cap
+    pub converged: bool,
+}
+
+/// Same solver as `find_new_volatility`, but also reports iteration count and the final
+/// residual so non-convergence on hard inputs (or a badly chosen τ) can be detected.
+pub fn find_new_volatility_diagnostic(
+    sigma: f64,
+    phi: f64,
+    v: f64,
+    delta: f64,
+    tau: f64,
+    bounds: VolatilityBounds,
+) -> VolatilityDiagnostics {
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let denom = phi.powi(2) + v + ex;
+        ex * (delta.powi(2) - phi.powi(2) - v - ex) / (2.0 * denom.powi(2)) - (x - a) / tau.powi(2)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta.powi(2) > phi.powi(2) + v {
+        (delta.powi(2) - phi.powi(2) - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 && k < MAX_VOLATILITY_ITERATIONS as f64 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    let mut iterations = 0;
+
+    while (big_b - big_a).abs() > EPSILON && iterations < MAX_VOLATILITY_ITERATIONS {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+
+        big_b = big_c;
+        f_b = f_c;
+        iterations += 1;
+    }
+
+    let converged = (big_b - big_a).abs() <= EPSILON;
+    let (x, residual) = if converged || f_a.abs() <= f_b.abs() {
+        (big_a, f_a.abs())
+    } else {
+        (big_b, f_b.abs())
+    };
+
+    let new_sigma = (x / 2.0).exp();
+    let volatility = if new_sigma.is_finite() {
+        bounds.apply(new_sigma)
+    } else {
+        bounds.apply(sigma)
+    };
+
+    VolatilityDiagnostics {
+        volatility,
+        iterations,
+        residual,
+        converged,
+    }
 }
 
 pub fn update_rating(
@@ -73,6 +229,7 @@ pub fn update_rating(
             rating: opponent_rating,
             rating_deviation: opponent_rd,
             volatility: 0.06,
+            last_updated: None,
         };
         temp_player.to_glicko2_scale()
     };
@@ -103,60 +260,466 @@ pub fn update_rating(
         rating: new_rating,
         rating_deviation: new_rd,
         volatility: final_volatility,
+        last_updated: player.last_updated,
     }
 }
 
-pub fn calculate_ratings(mut klines: Vec<KlineData>) -> Result<Vec<GlickoRating>> {
-    // Sort klines by timestamp
-    klines.sort_by_key(|k| k.open_time);
+/// Decay stale ratings between calculation runs.
+///
+/// Each player with a `last_updated` time is pulled toward the default rating, and its
+/// RD inflated toward the default RD, by `0.5^(elapsed / half_life_ms)`: after one
+/// half-life the distance to 1500/350 halves. Players never rated, or updated at
+/// `now`, are untouched. `last_updated` is advanced to `now`, so repeated calls compose
+/// (decaying t0→t1 then t1→t2 equals decaying t0→t2).
+pub fn decay_inactive(players: &mut [GlickoPlayer], now: i64, half_life_ms: i64) {
+    if half_life_ms <= 0 {
+        return;
+    }
 
-    let mut players: HashMap<String, GlickoPlayer> = HashMap::new();
-    let mut ratings: Vec<GlickoRating> = Vec::new();
+    for player in players.iter_mut() {
+        let Some(last_updated) = player.last_updated else {
+            continue;
+        };
+        let elapsed = now - last_updated;
+        if elapsed <= 0 {
+            continue;
+        }
+
+        let retained = 0.5_f64.powf(elapsed as f64 / half_life_ms as f64);
+        player.rating = DEFAULT_RATING + (player.rating - DEFAULT_RATING) * retained;
+        player.rating_deviation = DEFAULT_RD - (DEFAULT_RD - player.rating_deviation) * retained;
+        player.last_updated = Some(now);
+    }
+}
 
-    // Benchmark opponent (USDT baseline)
-    let benchmark_rating = 1500.0;
-    let benchmark_rd = 50.0;
+/// Turns a kline into a Glicko match result in [0, 1] (1 = win against the opponent).
+pub trait MatchScorer {
+    fn score(&self, kline: &KlineData) -> f64;
+}
 
-    for kline in klines {
-        // Get or create player
-        let player = players
-            .entry(kline.symbol.clone())
-            .or_insert_with(|| GlickoPlayer::new(kline.symbol.clone()));
+/// The default scorer: `HybridScore`'s continuous price-change scaling.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HybridScorer;
 
-        // Calculate hybrid performance score
+impl MatchScorer for HybridScorer {
+    fn score(&self, kline: &KlineData) -> f64 {
         let taker_sell_volume = kline.volume - kline.taker_buy_base_asset_volume;
-        let hybrid_score = HybridScore::calculate(
+        HybridScore::calculate(
             kline.open,
             kline.close,
             kline.taker_buy_base_asset_volume,
             taker_sell_volume,
-        );
+        )
+        .score
+    }
+}
 
-        // Update player rating
-        let updated_player = update_rating(
-            player,
-            benchmark_rating,
-            benchmark_rd,
-            hybrid_score.score,
-        );
+/// How candles with zero traded volume are rated. Their taker split is meaningless (the
+/// derived sell volume can even go negative), so illiquid pairs may prefer not to let
+/// the bare price move count.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroVolume {
+    /// Score the price move like any other candle
+    #[default]
+    Score,
+    /// Score as a draw (0.5)
+    Draw,
+    /// Leave the rating untouched and emit no rating for the candle
+    Skip,
+}
+
+/// `scorer`'s match result for `kline` under the zero-volume treatment; `None` when the
+/// candle should not be rated at all.
+fn match_score<S: MatchScorer + ?Sized>(scorer: &S, kline: &KlineData, zero_volume: ZeroVolume) -> Option<f64> {
+    match zero_volume {
+        _ if kline.volume > 0.0 => Some(scorer.score(kline)),
+        ZeroVolume::Score => Some(scorer.score(kline)),
+        ZeroVolume::Draw => Some(0.5),
+        ZeroVolume::Skip => None,
+    }
+}
+
+/// Default number of klines between progress callbacks.
+pub const PROGRESS_INTERVAL: usize = 100_000;
+
+// Benchmark opponent (USDT baseline)
+const BENCHMARK_RATING: f64 = 1500.0;
+const BENCHMARK_RD: f64 = 50.0;
+
+/// Tuning for the static benchmark every kline is played against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlickoConfig {
+    pub benchmark_rating: f64,
+    /// Opponent RD: a low value makes each candle a high-information match that moves the
+    /// rating further from a given state, a high one damps each update. Over a long run
+    /// the symbol's own RD also settles higher against a high-RD benchmark, which claws
+    /// back part of the damping
+    pub benchmark_rd: f64,
+    pub zero_volume: ZeroVolume,
+}
+
+impl Default for GlickoConfig {
+    fn default() -> Self {
+        Self {
+            benchmark_rating: BENCHMARK_RATING,
+            benchmark_rd: BENCHMARK_RD,
+            zero_volume: ZeroVolume::default(),
+        }
+    }
+}
+
+/// Play one kline, with match result `score`, against the given opponent, updating
+/// `player` in place.
+fn rate_kline(
+    player: &mut GlickoPlayer,
+    kline: KlineData,
+    opponent_rating: f64,
+    opponent_rd: f64,
+    score: f64,
+) -> GlickoRating {
+    // Update player rating
+    let mut updated_player = update_rating(
+        player,
+        opponent_rating,
+        opponent_rd,
+        score,
+    );
+
+    // Store the updated player
+    updated_player.last_updated = Some(kline.open_time);
+    *player = updated_player.clone();
+
+    // Create rating record
+    GlickoRating {
+        symbol: kline.symbol,
+        timestamp: kline.open_time,
+        rating: updated_player.rating,
+        rating_deviation: updated_player.rating_deviation,
+        volatility: updated_player.volatility,
+        performance_score: score,
+    }
+}
+
+pub fn calculate_ratings(klines: Vec<KlineData>) -> Result<Vec<GlickoRating>> {
+    calculate_ratings_with_progress(klines, PROGRESS_INTERVAL, |_, _| {})
+}
+
+/// Same as `calculate_ratings`, but against the benchmark described by `config`.
+pub fn calculate_ratings_with_config(klines: Vec<KlineData>, config: &GlickoConfig) -> Result<Vec<GlickoRating>> {
+    let benchmark = (config.benchmark_rating, config.benchmark_rd);
+    rate_klines(klines, HashMap::new(), PROGRESS_INTERVAL, |_, _| {}, |_| benchmark, &HybridScorer, config.zero_volume)
+        .map(|(ratings, _)| ratings)
+}
+
+/// Same as `calculate_ratings`, but with `scorer` deciding each kline's match result
+/// instead of `HybridScorer`.
+pub fn calculate_ratings_with_scorer<S: MatchScorer + ?Sized>(
+    klines: Vec<KlineData>,
+    scorer: &S,
+) -> Result<Vec<GlickoRating>> {
+    rate_klines(klines, HashMap::new(), PROGRESS_INTERVAL, |_, _| {}, |_| (BENCHMARK_RATING, BENCHMARK_RD), scorer, ZeroVolume::default())
+        .map(|(ratings, _)| ratings)
+}
+
+/// Same as `calculate_ratings`, but invokes `on_progress(processed, total)` every
+/// `interval` klines and once more on completion so long runs can report progress.
+pub fn calculate_ratings_with_progress<F>(
+    klines: Vec<KlineData>,
+    interval: usize,
+    on_progress: F,
+) -> Result<Vec<GlickoRating>>
+where
+    F: FnMut(usize, usize),
+{
+    rate_klines(klines, HashMap::new(), interval, on_progress, |_| (BENCHMARK_RATING, BENCHMARK_RD), &HybridScorer, ZeroVolume::default())
+        .map(|(ratings, _)| ratings)
+}
+
+/// Rate klines with per-symbol starting parameters.
+///
+/// `seeds` maps a symbol to its initial `(rating, rating_deviation, volatility)`, e.g. from
+/// known prior behaviour or a previous run; symbols without a seed start at 1500/350/0.06.
+pub fn calculate_ratings_with_seeds(
+    klines: Vec<KlineData>,
+    seeds: HashMap<String, (f64, f64, f64)>,
+) -> Result<Vec<GlickoRating>> {
+    let players = seeds
+        .into_iter()
+        .map(|(symbol, (rating, rating_deviation, volatility))| {
+            let player = GlickoPlayer {
+                rating,
+                rating_deviation,
+                volatility,
+                ..GlickoPlayer::new(symbol.clone())
+            };
+            (symbol, player)
+        })
+        .collect();
+
+    rate_klines(klines, players, PROGRESS_INTERVAL, |_, _| {}, |_| (BENCHMARK_RATING, BENCHMARK_RD), &HybridScorer, ZeroVolume::default())
+        .map(|(ratings, _)| ratings)
+}
+
+/// Rate klines one at a time as they arrive, for inputs too large to materialize.
+///
+/// Yields one rating per kline, in input order, and holds only the per-symbol player
+/// state, so memory is bounded by the number of symbols rather than the number of klines.
+/// The batch entry points sort by `open_time` first; a stream cannot, so its input must
+/// already be in `open_time` order (an earlier kline than the last one is an error).
+/// Given sorted input the ratings are identical to `calculate_ratings_with_config`.
+pub struct RatingStream<I> {
+    klines: I,
+    players: HashMap<String, GlickoPlayer>,
+    config: GlickoConfig,
+    last_open_time: Option<i64>,
+}
+
+impl<I> RatingStream<I>
+where
+    I: Iterator<Item = Result<KlineData>>,
+{
+    /// Stream `klines`, resuming from `prior_state` like `calculate_ratings_incremental`.
+    pub fn new(klines: I, prior_state: Vec<GlickoPlayer>, config: GlickoConfig) -> Self {
+        let players = prior_state
+            .into_iter()
+            .map(|player| (player.symbol.clone(), player))
+            .collect();
+        Self { klines, players, config, last_open_time: None }
+    }
+
+    /// Final state of every player seen (sorted by symbol), for the next incremental run.
+    pub fn into_state(self) -> Vec<GlickoPlayer> {
+        let mut state: Vec<GlickoPlayer> = self.players.into_values().collect();
+        state.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        state
+    }
+}
+
+impl<I> Iterator for RatingStream<I>
+where
+    I: Iterator<Item = Result<KlineData>>,
+{
+    type Item = Result<GlickoRating>;
+
+    fn next(&mut self) -> Option<Result<GlickoRating>> {
+        loop {
+            let kline = match self.klines.next()? {
+                Ok(kline) => kline,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(last) = self.last_open_time.filter(|&last| kline.open_time < last) {
+                return Some(Err(format!(
+                    "Kline for {} at {} arrived after one at {}; streamed klines must be sorted by open_time",
+                    kline.symbol, kline.open_time, last
+                )
+                .into()));
+            }
+            self.last_open_time = Some(kline.open_time);
+
+            let Some(score) = match_score(&HybridScorer, &kline, self.config.zero_volume) else {
+                continue;
+            };
+            let player = self
+                .players
+                .entry(kline.symbol.clone())
+                .or_insert_with(|| GlickoPlayer::new(kline.symbol.clone()));
+            return Some(Ok(rate_kline(
+                player,
+                kline,
+                self.config.benchmark_rating,
+                self.config.benchmark_rd,
+                score,
+            )));
+        }
+    }
+}
+
+/// Resume rating from saved per-symbol player state instead of replaying full history.
+///
+/// Returns the ratings for `new_klines` together with the updated state of every known
+/// player (sorted by symbol), ready to persist for the next run. Symbols without prior
+/// state start from the defaults, exactly as in `calculate_ratings`.
+pub fn calculate_ratings_incremental(
+    prior_state: Vec<GlickoPlayer>,
+    new_klines: Vec<KlineData>,
+) -> Result<(Vec<GlickoRating>, Vec<GlickoPlayer>)> {
+    let players = prior_state
+        .into_iter()
+        .map(|player| (player.symbol.clone(), player))
+        .collect();
+
+    let (ratings, players) = rate_klines(
+        new_klines,
+        players,
+        PROGRESS_INTERVAL,
+        |_, _| {},
+        |_| (BENCHMARK_RATING, BENCHMARK_RD),
+        &HybridScorer,
+        ZeroVolume::default(),
+    )?;
+
+    let mut state: Vec<GlickoPlayer> = players.into_values().collect();
+    state.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    Ok((ratings, state))
+}
+
+/// Rate each kline against a time-varying benchmark instead of the static 1500/50 anchor.
+///
+/// `benchmark` is a rating timeline (e.g. the output of rating BTC or a market index);
+/// each kline faces the latest benchmark point at or before its `open_time`, so no
+/// future benchmark information leaks in. Klines older than the whole series use the
+/// first point. Ratings are then relative strength: a symbol must outpace a rising
+/// benchmark just to hold its gap above it.
+pub fn calculate_ratings_vs_benchmark(
+    klines: Vec<KlineData>,
+    benchmark: &[GlickoRating],
+) -> Result<Vec<GlickoRating>> {
+    if benchmark.is_empty() {
+        return Err("Benchmark series is empty".into());
+    }
+
+    let mut benchmark = benchmark.to_vec();
+    benchmark.sort_by_key(|b| b.timestamp);
+
+    let opponent = |kline: &KlineData| {
+        let index = benchmark
+            .partition_point(|b| b.timestamp <= kline.open_time)
+            .saturating_sub(1);
+        (benchmark[index].rating, benchmark[index].rating_deviation)
+    };
+    rate_klines(klines, HashMap::new(), PROGRESS_INTERVAL, |_, _| {}, opponent, &HybridScorer, ZeroVolume::default())
+        .map(|(ratings, _)| ratings)
+}
+
+/// Stitch rating timelines computed in chunks (e.g. one run per month) into one.
+///
+/// Ratings are keyed by symbol and timestamp; where chunks overlap, the later chunk's
+/// rating wins. The result is in timestamp order (symbols at the same timestamp in name
+/// order), like `calculate_ratings` output, so each symbol's series stays chronological.
+pub fn merge_ratings(chunks: Vec<Vec<GlickoRating>>) -> Vec<GlickoRating> {
+    let mut merged: BTreeMap<(i64, String), GlickoRating> = BTreeMap::new();
+    for rating in chunks.into_iter().flatten() {
+        merged.insert((rating.timestamp, rating.symbol.clone()), rating);
+    }
+    merged.into_values().collect()
+}
+
+/// Rate two symbols head-to-head for pairs trading.
+///
+/// Klines are aligned on `open_time` (periods present for only one leg are skipped).
+/// In each period the leg with the higher close/open return wins the match (score 1.0,
+/// the other 0.0; equal returns are a draw at 0.5). Both legs are updated against the
+/// other's pre-match rating, so the spread between the two timelines tracks relative
+/// momentum: long the higher-rated leg, short the lower.
+pub fn calculate_pairwise_rating(
+    symbol_a_klines: Vec<KlineData>,
+    symbol_b_klines: Vec<KlineData>,
+) -> Result<(Vec<GlickoRating>, Vec<GlickoRating>)> {
+    let b_by_time: HashMap<i64, KlineData> = symbol_b_klines
+        .into_iter()
+        .map(|k| (k.open_time, k))
+        .collect();
+
+    let mut pairs: Vec<(KlineData, KlineData)> = symbol_a_klines
+        .into_iter()
+        .filter_map(|a| b_by_time.get(&a.open_time).cloned().map(|b| (a, b)))
+        .collect();
+    pairs.sort_by_key(|(a, _)| a.open_time);
+
+    let Some((first_a, first_b)) = pairs.first() else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let mut player_a = GlickoPlayer::new(first_a.symbol.clone());
+    let mut player_b = GlickoPlayer::new(first_b.symbol.clone());
 
-        // Store the updated player
-        *player = updated_player.clone();
-
-        // Create rating record
-        let rating_record = GlickoRating {
-            symbol: kline.symbol,
-            timestamp: kline.open_time,
-            rating: updated_player.rating,
-            rating_deviation: updated_player.rating_deviation,
-            volatility: updated_player.volatility,
-            performance_score: hybrid_score.score,
+    let mut ratings_a = Vec::with_capacity(pairs.len());
+    let mut ratings_b = Vec::with_capacity(pairs.len());
+
+    for (a, b) in pairs {
+        let return_a = (a.close - a.open) / a.open;
+        let return_b = (b.close - b.open) / b.open;
+        let score_a = if (return_a - return_b).abs() < f64::EPSILON {
+            0.5
+        } else if return_a > return_b {
+            1.0
+        } else {
+            0.0
         };
 
-        ratings.push(rating_record);
+        let updated_a = update_rating(&player_a, player_b.rating, player_b.rating_deviation, score_a);
+        let updated_b = update_rating(&player_b, player_a.rating, player_a.rating_deviation, 1.0 - score_a);
+        player_a = GlickoPlayer { last_updated: Some(a.open_time), ..updated_a };
+        player_b = GlickoPlayer { last_updated: Some(b.open_time), ..updated_b };
+
+        for (player, kline, score, out) in [
+            (&player_a, &a, score_a, &mut ratings_a),
+            (&player_b, &b, 1.0 - score_a, &mut ratings_b),
+        ] {
+            out.push(GlickoRating {
+                symbol: kline.symbol.clone(),
+                timestamp: kline.open_time,
+                rating: player.rating,
+                rating_deviation: player.rating_deviation,
+                volatility: player.volatility,
+                performance_score: score,
+            });
+        }
+    }
+
+    Ok((ratings_a, ratings_b))
+}
+
+fn rate_klines<F, B, S>(
+    mut klines: Vec<KlineData>,
+    mut players: HashMap<String, GlickoPlayer>,
+    interval: usize,
+    mut on_progress: F,
+    mut opponent: B,
+    scorer: &S,
+    zero_volume: ZeroVolume,
+) -> Result<(Vec<GlickoRating>, HashMap<String, GlickoPlayer>)>
+where
+    F: FnMut(usize, usize),
+    B: FnMut(&KlineData) -> (f64, f64),
+    S: MatchScorer + ?Sized,
+{
+    let started = Instant::now();
+
+    // Sort klines by timestamp
+    klines.sort_by_key(|k| k.open_time);
+    let total = klines.len();
+    let interval = interval.max(1);
+
+    let mut ratings: Vec<GlickoRating> = Vec::new();
+
+    for (index, kline) in klines.into_iter().enumerate() {
+        if let Some(score) = match_score(scorer, &kline, zero_volume) {
+            // Get or create player
+            let player = players
+                .entry(kline.symbol.clone())
+                .or_insert_with(|| GlickoPlayer::new(kline.symbol.clone()));
+
+            let (opponent_rating, opponent_rd) = opponent(&kline);
+            ratings.push(rate_kline(player, kline, opponent_rating, opponent_rd, score));
+        }
+
+        let processed = index + 1;
+        if processed % interval == 0 && processed < total {
+            on_progress(processed, total);
+        }
     }
 
-    Ok(ratings)
+    on_progress(total, total);
+
+    tracing::info!(
+        klines = total,
+        symbols = players.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Calculated Glicko ratings"
+    );
+
+    Ok((ratings, players))
 }
 
 #[cfg(test)]
@@ -229,6 +792,115 @@ mod tests {
         assert!(result.unwrap().is_empty());
     }
 
+    #[test]
+    fn test_lower_benchmark_rd_moves_ratings_more() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        let klines: Vec<KlineData> = (0..6)
+            .map(|i| kline("BTCUSDT", i * HOUR_MS, 100.0, if i % 2 == 0 { 102.0 } else { 99.5 }))
+            .collect();
+        let default_ratings = calculate_ratings_with_config(klines.clone(), &GlickoConfig::default()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&default_ratings).unwrap(),
+            serde_json::to_string(&calculate_ratings(klines.clone()).unwrap()).unwrap()
+        );
+
+        // From the same player state, each candle moves the rating further against a
+        // low-RD benchmark than against a high-RD one
+        let rating_move = |rating_deviation: f64, kline: &KlineData, benchmark_rd: f64| {
+            let mut player = GlickoPlayer { rating_deviation, ..GlickoPlayer::new("BTCUSDT".to_string()) };
+            let config = GlickoConfig { benchmark_rd, ..GlickoConfig::default() };
+            let score = HybridScorer.score(kline);
+            let rated = rate_kline(&mut player, kline.clone(), config.benchmark_rating, config.benchmark_rd, score);
+            (rated.rating - DEFAULT_RATING).abs()
+        };
+        for rating_deviation in [DEFAULT_RD, 150.0, 60.0] {
+            for kline in &klines {
+                let tight = rating_move(rating_deviation, kline, 30.0);
+                let loose = rating_move(rating_deviation, kline, 300.0);
+                assert!(tight > loose, "RD {}: {} vs {}", rating_deviation, tight, loose);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streamed_ratings_match_batch() {
+        let mut klines: Vec<KlineData> = ["BTCUSDT", "ETHUSDT", "SOLUSDT"]
+            .iter()
+            .flat_map(|symbol| crate::test_utils::sample_klines(symbol, 5000))
+            .collect();
+        klines.sort_by_key(|k| k.open_time);
+
+        let batch = calculate_ratings(klines.clone()).unwrap();
+        let mut stream = RatingStream::new(klines.into_iter().map(Ok), Vec::new(), GlickoConfig::default());
+        let streamed = stream.by_ref().collect::<Result<Vec<_>>>().unwrap();
+
+        assert_eq!(serde_json::to_string(&streamed).unwrap(), serde_json::to_string(&batch).unwrap());
+        // All the stream retains after 15k klines is one player per symbol
+        assert_eq!(stream.players.len(), 3);
+
+        let unsorted = vec![
+            Ok(crate::test_utils::kline("BTCUSDT", 2 * crate::test_utils::HOUR_MS, 100.0, 101.0)),
+            Ok(crate::test_utils::kline("BTCUSDT", 0, 100.0, 101.0)),
+        ];
+        let results: Vec<Result<GlickoRating>> =
+            RatingStream::new(unsorted.into_iter(), Vec::new(), GlickoConfig::default()).collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_zero_volume_candle_treatment() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        let mut idle = kline("BTCUSDT", HOUR_MS, 100.0, 103.0);
+        idle.volume = 0.0;
+        idle.taker_buy_base_asset_volume = 0.0;
+        let klines = vec![kline("BTCUSDT", 0, 100.0, 101.0), idle.clone()];
+
+        assert_eq!(match_score(&HybridScorer, &idle, ZeroVolume::Draw), Some(0.5));
+        assert!(match_score(&HybridScorer, &idle, ZeroVolume::Score).unwrap() > 0.5);
+        assert_eq!(match_score(&HybridScorer, &idle, ZeroVolume::Skip), None);
+        // Traded candles are unaffected by the setting
+        assert_eq!(match_score(&HybridScorer, &klines[0], ZeroVolume::Draw), Some(HybridScorer.score(&klines[0])));
+
+        let rate = |zero_volume| {
+            let config = GlickoConfig { zero_volume, ..GlickoConfig::default() };
+            calculate_ratings_with_config(klines.clone(), &config).unwrap()
+        };
+        assert_eq!(rate(ZeroVolume::Draw)[1].performance_score, 0.5);
+        let skipped = rate(ZeroVolume::Skip);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].timestamp, 0);
+    }
+
+    #[test]
+    fn test_merge_ratings_prefers_later_chunk_on_overlap() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 30);
+        let mut klines_eth = crate::test_utils::sample_klines("ETHUSDT", 30);
+        klines_eth.extend(klines);
+        let full = calculate_ratings(klines_eth).unwrap();
+        // Two chunks overlapping on the middle ten hours; the second was rated separately
+        let first: Vec<GlickoRating> = full.iter().filter(|r| r.timestamp < full[40].timestamp).cloned().collect();
+        let mut second: Vec<GlickoRating> = full.iter().filter(|r| r.timestamp >= full[20].timestamp).cloned().collect();
+        for rating in &mut second {
+            rating.rating += 1.0;
+        }
+
+        let merged = merge_ratings(vec![first, second]);
+
+        assert_eq!(merged.len(), full.len());
+        let mut keys: Vec<(String, i64)> = merged.iter().map(|r| (r.symbol.clone(), r.timestamp)).collect();
+        keys.dedup();
+        assert_eq!(keys.len(), merged.len());
+        assert!(merged.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        for rating in &merged {
+            let original = full.iter().find(|r| r.symbol == rating.symbol && r.timestamp == rating.timestamp).unwrap();
+            let expected = if original.timestamp >= full[20].timestamp { original.rating + 1.0 } else { original.rating };
+            assert_eq!(rating.rating, expected);
+        }
+    }
+
     #[test]
     fn test_calculate_ratings_single_kline() {
         let klines = vec![KlineData {
@@ -257,4 +929,223 @@ mod tests {
         assert!(rating.rating > DEFAULT_RATING); // Should increase due to good performance
         assert_eq!(rating.performance_score, 1.0); // High-confidence win
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_progress_reporting_leaves_ratings_unchanged() {
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 250);
+
+        let mut reports = Vec::new();
+        let with_progress =
+            calculate_ratings_with_progress(klines.clone(), 100, |done, total| reports.push((done, total)))
+                .unwrap();
+        let without_progress = calculate_ratings(klines).unwrap();
+
+        assert_eq!(reports, vec![(100, 250), (200, 250), (250, 250)]);
+        assert_eq!(
+            serde_json::to_string(&with_progress).unwrap(),
+            serde_json::to_string(&without_progress).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rising_benchmark_dampens_relative_gains() {
+        let klines: Vec<KlineData> = (0..50)
+            .map(|i| crate::test_utils::kline("ETHUSDT", i * crate::test_utils::HOUR_MS, 100.0, 100.4))
+            .collect();
+        let benchmark: Vec<GlickoRating> = (0..50)
+            .map(|i| GlickoRating {
+                symbol: "BTCUSDT".to_string(),
+                timestamp: i * crate::test_utils::HOUR_MS,
+                rating: 1500.0 + i as f64 * 10.0,
+                rating_deviation: 50.0,
+                volatility: 0.06,
+                performance_score: 0.5,
+            })
+            .collect();
+
+        let static_ratings = calculate_ratings(klines.clone()).unwrap();
+        let relative_ratings = calculate_ratings_vs_benchmark(klines, &benchmark).unwrap();
+
+        let static_gain = static_ratings.last().unwrap().rating - 1500.0;
+        let relative_gain = relative_ratings.last().unwrap().rating - benchmark.last().unwrap().rating;
+        assert!(static_gain > 0.0);
+        assert!(relative_gain < static_gain);
+    }
+
+    #[test]
+    fn test_pairwise_rating_outperformer_ends_higher() {
+        use crate::test_utils::{kline, HOUR_MS};
+
+        let a: Vec<KlineData> = (0..30).map(|i| kline("SOLUSDT", i * HOUR_MS, 100.0, 101.0)).collect();
+        // B is missing one period, which must simply be skipped
+        let b: Vec<KlineData> = (0..30)
+            .filter(|&i| i != 7)
+            .map(|i| kline("ADAUSDT", i * HOUR_MS, 100.0, 100.2))
+            .collect();
+
+        let (ratings_a, ratings_b) = calculate_pairwise_rating(a, b).unwrap();
+
+        assert_eq!(ratings_a.len(), 29);
+        assert_eq!(ratings_b.len(), 29);
+        assert!(ratings_a.iter().all(|r| r.symbol == "SOLUSDT"));
+        assert!(ratings_a.last().unwrap().rating > ratings_b.last().unwrap().rating);
+        assert!(ratings_a.last().unwrap().rating > DEFAULT_RATING);
+    }
+
+    #[test]
+    fn test_find_new_volatility_matches_glickman_example() {
+        // Worked example from Glickman's Glicko-2 paper
+        let sigma = find_new_volatility(0.06, 1.1513, 1.7785, -0.4834, 0.5, VolatilityBounds::default());
+        assert!((sigma - 0.05999).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_find_new_volatility_clamps_extreme_delta() {
+        let bounds = VolatilityBounds { min: 0.01, max: 0.1 };
+        let sigma = find_new_volatility(0.06, 0.3, 0.05, 250.0, TAU, bounds);
+        assert!(sigma.is_finite());
+        assert!(sigma >= bounds.min && sigma <= bounds.max);
+
+        let unclamped = find_new_volatility(
+            0.06,
+            0.3,
+            0.05,
+            250.0,
+            TAU,
+            VolatilityBounds { min: 0.0, max: f64::INFINITY },
+        );
+        assert!(unclamped > bounds.max);
+    }
+
+    #[test]
+    fn test_volatility_diagnostics_report_iterations() {
+        let bounds = VolatilityBounds { min: 0.0, max: f64::INFINITY };
+        let easy = find_new_volatility_diagnostic(0.06, 1.1513, 1.7785, -0.4834, 0.5, bounds);
+        let hard = find_new_volatility_diagnostic(0.06, 0.01, 0.001, 40.0, 0.05, bounds);
+
+        assert!(easy.converged);
+        assert!(easy.iterations <= 5);
+        assert!(easy.residual < 1e-6);
+        assert_eq!(easy.volatility, find_new_volatility(0.06, 1.1513, 1.7785, -0.4834, 0.5, bounds));
+
+        assert!(hard.iterations >= 10);
+        assert!(hard.iterations > easy.iterations);
+    }
+
+    #[test]
+    fn test_decay_inactive_pulls_stale_ratings_toward_default() {
+        let day_ms = 24 * 60 * 60 * 1000;
+        let now = 100 * day_ms;
+        let mut players = vec![
+            GlickoPlayer {
+                rating: 1800.0,
+                rating_deviation: 60.0,
+                last_updated: Some(now - 30 * day_ms),
+                ..GlickoPlayer::new("STALE".to_string())
+            },
+            GlickoPlayer {
+                rating: 1800.0,
+                rating_deviation: 60.0,
+                last_updated: Some(now),
+                ..GlickoPlayer::new("FRESH".to_string())
+            },
+        ];
+
+        decay_inactive(&mut players, now, 10 * day_ms);
+
+        // Three half-lives: 1/8 of the distance to the defaults remains
+        assert!((players[0].rating - 1537.5).abs() < 1e-9);
+        assert!((players[0].rating_deviation - 313.75).abs() < 1e-9);
+        assert_eq!(players[1].rating, 1800.0);
+        assert_eq!(players[1].rating_deviation, 60.0);
+    }
+
+    #[test]
+    fn test_incremental_ratings_match_single_pass() {
+        let mut klines = crate::test_utils::sample_klines("BTCUSDT", 120);
+        klines.extend(crate::test_utils::sample_klines("ETHUSDT", 120));
+        let split = klines[60].open_time;
+        let (first, second): (Vec<_>, Vec<_>) =
+            klines.iter().cloned().partition(|k| k.open_time < split);
+        assert_eq!((first.len(), second.len()), (120, 120));
+
+        let full = calculate_ratings(klines).unwrap();
+
+        let (mut incremental, state) = calculate_ratings_incremental(Vec::new(), first).unwrap();
+        // State survives a JSON round trip, as it would between scheduled runs
+        let state: Vec<GlickoPlayer> =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        let (rest, final_state) = calculate_ratings_incremental(state, second).unwrap();
+        incremental.extend(rest);
+
+        assert_eq!(
+            serde_json::to_string(&full).unwrap(),
+            serde_json::to_string(&incremental).unwrap()
+        );
+        assert_eq!(final_state.len(), 2);
+        assert_eq!(final_state[0].symbol, "BTCUSDT");
+    }
+
+    #[test]
+    fn test_seeded_rating_overrides_defaults() {
+        let mut klines = crate::test_utils::sample_klines("BTCUSDT", 5);
+        klines.extend(crate::test_utils::sample_klines("ETHUSDT", 5));
+        let seeds = HashMap::from([("BTCUSDT".to_string(), (1900.0, 80.0, 0.05))]);
+
+        let default = calculate_ratings(klines.clone()).unwrap();
+        let seeded = calculate_ratings_with_seeds(klines, seeds).unwrap();
+
+        let first = |ratings: &[GlickoRating], symbol: &str| {
+            ratings.iter().find(|r| r.symbol == symbol).unwrap().clone()
+        };
+        let (default_btc, seeded_btc) = (first(&default, "BTCUSDT"), first(&seeded, "BTCUSDT"));
+        assert!(seeded_btc.rating > default_btc.rating + 300.0);
+        assert!(seeded_btc.rating_deviation < default_btc.rating_deviation);
+
+        // Unseeded symbols are unaffected
+        assert_eq!(first(&default, "ETHUSDT").rating, first(&seeded, "ETHUSDT").rating);
+    }
+
+    #[test]
+    fn test_custom_scorer_always_winning_raises_ratings() {
+        struct AlwaysWin;
+        impl MatchScorer for AlwaysWin {
+            fn score(&self, _kline: &KlineData) -> f64 {
+                1.0
+            }
+        }
+
+        let klines = crate::test_utils::sample_klines("BTCUSDT", 50);
+        let ratings = calculate_ratings_with_scorer(klines.clone(), &AlwaysWin).unwrap();
+        assert_eq!(ratings.len(), 50);
+        assert!(ratings.windows(2).all(|w| w[1].rating > w[0].rating));
+        assert!(ratings.iter().all(|r| r.performance_score == 1.0));
+
+        // The default scorer reproduces calculate_ratings
+        let default = calculate_ratings_with_scorer(klines.clone(), &HybridScorer).unwrap();
+        let reference = calculate_ratings(klines).unwrap();
+        assert_eq!(
+            serde_json::to_string(&default).unwrap(),
+            serde_json::to_string(&reference).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_win_probability_follows_rating_gap() {
+        let rating = |rating| GlickoRating {
+            symbol: "BTCUSDT".to_string(),
+            timestamp: 0,
+            rating,
+            rating_deviation: 80.0,
+            volatility: 0.06,
+            performance_score: 0.5,
+        };
+
+        assert!((win_probability(&rating(1500.0), 1500.0, 50.0) - 0.5).abs() < 1e-12);
+        let higher = win_probability(&rating(1700.0), 1500.0, 50.0);
+        let lower = win_probability(&rating(1300.0), 1500.0, 50.0);
+        assert!(higher > 0.5);
+        assert!(lower < 0.5);
+        assert!((higher + lower - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{BacktestOrder, ExitReason, OrderSide, Result};
+use flate2::read::MultiGzDecoder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// First two bytes of every gzip member (RFC 1952).
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read all of `reader` into a string, transparently gunzipping it when the
+/// input starts with the gzip magic bytes. Plain input passes through unchanged.
+pub fn read_input<R: Read>(mut reader: R) -> Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    decode_input(bytes)
+}
+
+/// Decode raw input bytes, decompressing gzip (including concatenated members).
+pub fn decode_input(bytes: Vec<u8>) -> Result<String> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut decoded = String::new();
+        MultiGzDecoder::new(bytes.as_slice()).read_to_string(&mut decoded)?;
+        Ok(decoded)
+    } else {
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+/// Lazily parse newline-delimited JSON, one record per non-blank line, so arbitrarily
+/// large inputs never sit in memory at once. Gzip input is detected and decompressed
+/// on the fly as with `read_input`.
+pub fn read_ndjson<R, T>(reader: R) -> Result<impl Iterator<Item = Result<T>>>
+where
+    R: Read + 'static,
+    T: DeserializeOwned,
+{
+    let mut reader = BufReader::new(reader);
+    let lines: Box<dyn BufRead> = if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    };
+    Ok(lines.lines().filter_map(|line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(serde_json::from_str(&line).map_err(Into::into)),
+        Err(e) => Some(Err(e.into())),
+    }))
+}
+
+/// CSV layout of a `BacktestOrder`; BUY orders leave the P&L columns blank.
+#[derive(Serialize, Deserialize)]
+struct OrderRow {
+    symbol: String,
+    side: OrderSide,
+    quantity: f64,
+    price: f64,
+    timestamp: i64,
+    reason: ExitReason,
+    profit_loss: Option<f64>,
+    profit_loss_percent: Option<f64>,
+}
+
+/// Write orders as CSV with a header row, for opening the trade log in a spreadsheet.
+pub fn write_orders_csv<W: Write>(writer: W, orders: &[BacktestOrder]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for order in orders {
+        writer.serialize(OrderRow {
+            symbol: order.symbol.clone(),
+            side: order.side,
+            quantity: order.quantity,
+            price: order.price,
+            timestamp: order.timestamp,
+            reason: order.reason,
+            profit_loss: order.profit_loss,
+            profit_loss_percent: order.profit_loss_percent,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read orders written by `write_orders_csv`.
+pub fn read_orders_csv<R: Read>(reader: R) -> Result<Vec<BacktestOrder>> {
+    csv::Reader::from_reader(reader)
+        .deserialize::<OrderRow>()
+        .map(|row| {
+            let row = row?;
+            Ok(BacktestOrder {
+                symbol: row.symbol,
+                side: row.side,
+                quantity: row.quantity,
+                price: row.price,
+                timestamp: row.timestamp,
+                timestamp_iso: None,
+                reason: row.reason,
+                profit_loss: row.profit_loss,
+                profit_loss_percent: row.profit_loss_percent,
+                warmup: false,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glicko::calculate_ratings;
+    use crate::test_utils::sample_klines;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_gzipped_klines_match_plain_klines() {
+        let plain = serde_json::to_string(&sample_klines("BTCUSDT", 200)).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(plain.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let from_plain = read_input(plain.as_bytes()).unwrap();
+        let from_gzip = read_input(gzipped.as_slice()).unwrap();
+        assert_eq!(from_plain, from_gzip);
+
+        let plain_ratings = calculate_ratings(serde_json::from_str(&from_plain).unwrap()).unwrap();
+        let gzip_ratings = calculate_ratings(serde_json::from_str(&from_gzip).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&plain_ratings).unwrap(),
+            serde_json::to_string(&gzip_ratings).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_orders_csv_round_trip() {
+        let orders = vec![
+            BacktestOrder {
+                symbol: "BTCUSDT".to_string(),
+                side: OrderSide::Buy,
+                quantity: 0.123456789,
+                price: 46216.93,
+                timestamp: 1640995200000,
+                timestamp_iso: None,
+                reason: ExitReason::Entry,
+                profit_loss: None,
+                profit_loss_percent: None,
+                warmup: false,
+            },
+            BacktestOrder {
+                symbol: "BTCUSDT".to_string(),
+                side: OrderSide::Sell,
+                quantity: 0.123456789,
+                price: 44000.1,
+                timestamp: 1641081600000,
+                timestamp_iso: None,
+                reason: ExitReason::ExitStop,
+                profit_loss: Some(-273.6901234),
+                profit_loss_percent: Some(-4.796),
+                warmup: false,
+            },
+        ];
+
+        let mut csv = Vec::new();
+        write_orders_csv(&mut csv, &orders).unwrap();
+        let text = String::from_utf8(csv.clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "symbol,side,quantity,price,timestamp,reason,profit_loss,profit_loss_percent"
+        );
+        assert!(lines[1].ends_with(",ENTRY,,"));
+
+        let restored = read_orders_csv(csv.as_slice()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&restored).unwrap(),
+            serde_json::to_string(&orders).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_ndjson_plain_and_gzipped() {
+        let klines = sample_klines("BTCUSDT", 5);
+        let mut ndjson = String::new();
+        for kline in &klines {
+            ndjson.push_str(&serde_json::to_string(kline).unwrap());
+            ndjson.push_str("\n\n");
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(ndjson.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let expected: Vec<crate::KlineData> =
+            ndjson.lines().filter(|line| !line.is_empty()).map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(expected.len(), klines.len());
+
+        for input in [ndjson.into_bytes(), gzipped] {
+            let parsed: Vec<crate::KlineData> = read_ndjson(std::io::Cursor::new(input))
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(serde_json::to_string(&parsed).unwrap(), serde_json::to_string(&expected).unwrap());
+        }
+    }
+}
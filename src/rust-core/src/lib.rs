@@ -1,11 +1,22 @@
 pub mod glicko;
 pub mod backtest;
 pub mod data;
+pub mod binance;
+pub mod io;
+pub mod validation;
+pub mod monte_carlo;
+mod non_finite;
+#[cfg(feature = "parquet")]
+pub mod parquet_io;
 
+#[cfg(test)]
+mod test_utils;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct KlineData {
     pub symbol: String,
     pub open_time: i64,
@@ -21,7 +32,7 @@ pub struct KlineData {
     pub taker_buy_quote_asset_volume: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct GlickoRating {
     pub symbol: String,
     pub timestamp: i64,
@@ -31,44 +42,847 @@ pub struct GlickoRating {
     pub performance_score: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BacktestConfig {
     pub base_asset: String,
     pub quote_asset: String,
     pub z_score_threshold: f64,
     pub moving_averages: usize,
-    pub profit_percent: f64,
+    /// Take-profit distance in percent; leave unset when using `risk_reward_ratio`
+    #[serde(default)]
+    pub profit_percent: Option<f64>,
     pub stop_loss_percent: f64,
+    /// Derive the take-profit as `stop_loss_percent * risk_reward_ratio` instead of
+    /// `profit_percent`; setting both is an error under `StopMode::Percent`
+    #[serde(default)]
+    pub risk_reward_ratio: Option<f64>,
     pub start_time: i64,
     pub end_time: i64,
     pub window_size: Option<usize>,
+    /// Whether `window_size` counts months (the default) or bars
+    #[serde(default)]
+    pub window_unit: WindowUnit,
+    /// Bars between window starts under `WindowUnit::Bars`; defaults to half a window
+    #[serde(default)]
+    pub window_step: Option<usize>,
+    /// How the rolling mean/std behind the z-score are computed
+    #[serde(default)]
+    pub z_score_method: ZScoreMethod,
+    /// EMA smoothing factor in (0, 1]; defaults to 2 / (moving_averages + 1)
+    #[serde(default)]
+    pub ema_smoothing: Option<f64>,
+    /// Emit Bollinger-style upper/lower bands alongside each z-score
+    #[serde(default)]
+    pub with_bands: bool,
+    /// Band width in standard deviations; defaults to `z_score_threshold`
+    #[serde(default)]
+    pub band_multiplier: Option<f64>,
+    /// Require RSI confirmation before acting on BUY/SELL signals
+    #[serde(default)]
+    pub rsi_filter: Option<RsiFilter>,
+    /// Entry filter: a BUY only stands when the trailing mean `performance_score` is at
+    /// least this; SELL exits are never filtered
+    #[serde(default)]
+    pub min_performance_score: Option<f64>,
+    /// Ratings averaged for `min_performance_score`, including the current one; defaults to 5
+    #[serde(default)]
+    pub performance_score_window: Option<usize>,
+    /// Symbols to trade together; defaults to `{base_asset}USDT`
+    #[serde(default)]
+    pub symbols: Option<Vec<String>>,
+    /// Rebalance open positions back to target weights every this many ms
+    #[serde(default)]
+    pub rebalance_interval_ms: Option<i64>,
+    /// Target weights used when rebalancing
+    #[serde(default)]
+    pub rebalance_weighting: RebalanceWeighting,
+    /// Also emit RFC 3339 (UTC) timestamps next to the epoch-millisecond ones
+    #[serde(default)]
+    pub iso_timestamps: bool,
+    /// Return periods per year for annualization; defaults to one per `rating_period_ms`
+    /// of the market calendar's year, or 365.25 (daily, 24/7 crypto markets) without one
+    #[serde(default)]
+    pub trading_periods_per_year: Option<f64>,
+    /// Spacing of the rating series in milliseconds; inferred from the klines with
+    /// `validation::infer_interval` when unset
+    #[serde(default)]
+    pub rating_period_ms: Option<i64>,
+    /// Time span the annualized return is measured over
+    #[serde(default)]
+    pub annualization_basis: AnnualizationBasis,
+    /// Which hours count towards trade durations, time in market and the annualization span
+    #[serde(default)]
+    pub market_calendar: MarketCalendar,
+    /// How `annualized_return` spreads the total return over the elapsed years
+    #[serde(default)]
+    pub return_basis: ReturnBasis,
+    /// Annual risk-free rate for Sharpe, Sortino and alpha when there is no `risk_free_curve`
+    #[serde(default = "BacktestConfig::default_risk_free_rate")]
+    pub risk_free_rate: f64,
+    /// `(timestamp, annual rate)` points for the risk-free rate in Sharpe/Sortino/alpha,
+    /// sorted by timestamp. Step-held: each rate applies from its timestamp until the next
+    /// point (the first rate also covers anything earlier). Without a curve the rate is
+    /// `risk_free_rate`.
+    #[serde(default)]
+    pub risk_free_curve: Option<Vec<(i64, f64)>>,
+    /// Value in `quote_asset` (the reporting currency) of one unit of each other quote
+    /// asset, as sorted `(timestamp, rate)` points, e.g. `{"BTC": [[0, 43000.0]]}`. A symbol
+    /// ending in one of these assets (ETHBTC) is sized and valued through its rate,
+    /// step-held like `risk_free_curve`; order prices and P&L stay in the pair's own quote.
+    #[serde(default)]
+    pub quote_conversions: Option<BTreeMap<String, Vec<(i64, f64)>>>,
+    /// Trailing number of returns per `rolling_sharpe` point; no series when unset
+    #[serde(default)]
+    pub rolling_sharpe_window: Option<usize>,
+    /// Notional exposure per unit of margin; positions are unleveraged when unset
+    #[serde(default)]
+    pub leverage: Option<f64>,
+    /// Upper bound applied to `leverage`; defaults to 10x
+    #[serde(default)]
+    pub max_leverage: Option<f64>,
+    /// Equity (as % of notional) below which a leveraged position is liquidated; defaults to 0.5%
+    #[serde(default)]
+    pub maintenance_margin_percent: Option<f64>,
+    /// Perpetual-futures funding rate charged to longs (credited if negative) per interval
+    #[serde(default)]
+    pub funding_rate: Option<f64>,
+    /// Funding period in ms; funding is settled at epoch-aligned multiples of it
+    #[serde(default)]
+    pub funding_interval_ms: Option<i64>,
+    /// Fund-style performance fee: percent of equity gains above the high-water mark,
+    /// deducted from cash at each fee period boundary
+    #[serde(default)]
+    pub performance_fee_percent: Option<f64>,
+    /// Fee period in ms, epoch-aligned like funding (default one year)
+    #[serde(default)]
+    pub performance_fee_interval_ms: Option<i64>,
+    /// Annual yield earned by uninvested cash (e.g. 0.05 for 5%), compounded over the
+    /// time between equity-curve points
+    #[serde(default)]
+    pub cash_yield_annual: Option<f64>,
+    /// Round every cash posting, and the cash balance after it, to this many decimals
+    /// (2 = cents), like a real ledger. Stops float error from accumulating over many
+    /// round trips, at the cost of up to half a unit of rounding per posting; unset keeps
+    /// full float precision
+    #[serde(default)]
+    pub cash_decimals: Option<u32>,
+    /// Half the bid/ask spread in basis points: buys fill at the ask, sells at the bid
+    #[serde(default)]
+    pub half_spread_bps: Option<f64>,
+    /// Close positions still open at the end at the final price (EXIT_END) instead of
+    /// reporting them in `open_positions`
+    #[serde(default)]
+    pub close_at_end: Option<bool>,
+    /// Smallest order notional the venue accepts (e.g. 10 USDT on Binance); smaller entries are skipped
+    #[serde(default)]
+    pub min_notional: Option<f64>,
+    /// Lot step size; entry quantities are floored to a multiple of it (e.g. 0.001 BTC)
+    #[serde(default)]
+    pub step_size: Option<f64>,
+    /// Only trade whole units: entry quantities are floored to an integer, and entries that
+    /// cannot afford one unit are skipped
+    #[serde(default)]
+    pub integer_quantities: Option<bool>,
+    /// Moving average used to pre-smooth each rating series before z-scores
+    #[serde(default)]
+    pub smoothing: SmoothingKind,
+    /// Pre-smoothing window; 1 (the default) leaves ratings untouched
+    #[serde(default)]
+    pub smoothing_period: Option<usize>,
+    /// Close a long once its z-score reverts below this level, instead of waiting for
+    /// the opposite extreme (`-z_score_threshold`)
+    #[serde(default)]
+    pub exit_z_score: Option<f64>,
+    /// Swap BUY and SELL signals to trade the contrarian side of the z-score
+    #[serde(default)]
+    pub invert_signals: Option<bool>,
+    /// Consecutive bars the z-score must stay beyond the threshold (on the same side)
+    /// before BUY/SELL fires; 1 (the default) acts on the first breach
+    #[serde(default)]
+    pub signal_confirmation_bars: Option<usize>,
+    /// Widen the z-score threshold by the rating's uncertainty:
+    /// `threshold * (1 + confidence_scale * RD / 350)`
+    #[serde(default)]
+    pub confidence_scaled_threshold: Option<bool>,
+    /// `k` in the confidence-scaled threshold (default 1)
+    #[serde(default)]
+    pub confidence_scale: Option<f64>,
+    /// UTC `(start_hour, end_hour)` window in which new entries are allowed; exits are
+    /// never restricted. `start > end` wraps past midnight.
+    #[serde(default)]
+    pub session_hours: Option<(u8, u8)>,
+    /// Maximum simultaneous positions; further BUY signals are skipped until one closes
+    #[serde(default)]
+    pub max_open_positions: Option<usize>,
+    /// Minimum number of a symbol's own bars from one entry to the next on that symbol
+    #[serde(default)]
+    pub min_bars_between_trades: Option<usize>,
+    /// Number of bars, counted from the first bar carrying a signal, whose entries are
+    /// still simulated but left out of the trade statistics and return-based risk metrics
+    #[serde(default)]
+    pub warmup_bars: Option<usize>,
+    /// What to do with ratings repeated for the same symbol and timestamp
+    #[serde(default)]
+    pub duplicate_ratings: DuplicateRatings,
+    /// What `allocation_percent` is a share of when sizing a new position
+    #[serde(default)]
+    pub sizing_base: SizingBase,
+    /// Upper bound on a new position's notional as a multiple of equity, enforced after
+    /// allocation and leverage sizing
+    #[serde(default)]
+    pub max_notional_multiple: Option<f64>,
+    /// Kill switch: once equity is this many percent below its peak, every position is
+    /// closed (EXIT_CIRCUIT_BREAKER) and no new entries are made for the rest of the run
+    #[serde(default)]
+    pub max_drawdown_stop_percent: Option<f64>,
+    /// Kline price that marks positions and triggers stops when klines feed the backtest
+    #[serde(default)]
+    pub signal_price: PriceSource,
+    /// Kline price orders fill at when klines feed the backtest
+    #[serde(default)]
+    pub fill_price: PriceSource,
+    /// Which OCO level is assumed hit first when one kline's range touches both
+    #[serde(default)]
+    pub intrabar_assumption: IntrabarAssumption,
+    /// How the OCO levels are placed around the entry price
+    #[serde(default)]
+    pub stop_mode: StopMode,
+    /// Return the per-bar prices the simulation acted on in `price_series`
+    #[serde(default)]
+    pub include_prices: bool,
+    /// Return the equity curve and its drawdown (underwater) curve
+    #[serde(default)]
+    pub include_equity_curve: bool,
+    /// Symbol whose buy-and-hold is the alpha/beta benchmark; defaults to the (first)
+    /// traded symbol. With klines input its klines must be included.
+    #[serde(default)]
+    pub benchmark_symbol: Option<String>,
+    /// Refuse entries that would push estimated portfolio CVaR (95%, one period) above this % of equity
+    #[serde(default)]
+    pub max_portfolio_risk_percent: Option<f64>,
+    /// Order in which symbols signalling at the same timestamp compete for cash
+    #[serde(default)]
+    pub entry_priority: EntryPriority,
+    /// Shrink new entries while the portfolio is in drawdown
+    #[serde(default)]
+    pub drawdown_throttle: Option<DrawdownThrottle>,
+}
+
+impl BacktestConfig {
+    /// Symbols traded by the backtest, in the order they were configured.
+    pub fn traded_symbols(&self) -> Vec<String> {
+        match &self.symbols {
+            Some(symbols) if !symbols.is_empty() => symbols.clone(),
+            _ => vec![format!("{}USDT", self.base_asset)],
+        }
+    }
+
+    fn default_risk_free_rate() -> f64 {
+        0.02
+    }
+
+    /// Leverage actually applied to new positions: `leverage` clamped to `[1, max_leverage]`.
+    pub fn effective_leverage(&self) -> f64 {
+        let max_leverage = self.max_leverage.unwrap_or(10.0).max(1.0);
+        self.leverage.unwrap_or(1.0).clamp(1.0, max_leverage)
+    }
+
+    /// Take-profit distance in percent for `StopMode::Percent`: `stop_loss_percent *
+    /// risk_reward_ratio` when a ratio is configured, else `profit_percent` (0 if unset).
+    pub fn effective_profit_percent(&self) -> f64 {
+        match self.risk_reward_ratio {
+            Some(ratio) => self.stop_loss_percent * ratio,
+            None => self.profit_percent.unwrap_or(0.0),
+        }
+    }
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            z_score_threshold: 2.0,
+            moving_averages: 20,
+            profit_percent: Some(5.0),
+            stop_loss_percent: 2.5,
+            risk_reward_ratio: None,
+            start_time: 0,
+            end_time: 0,
+            window_size: None,
+            window_unit: WindowUnit::default(),
+            window_step: None,
+            z_score_method: ZScoreMethod::default(),
+            ema_smoothing: None,
+            with_bands: false,
+            band_multiplier: None,
+            rsi_filter: None,
+            min_performance_score: None,
+            performance_score_window: None,
+            symbols: None,
+            rebalance_interval_ms: None,
+            rebalance_weighting: RebalanceWeighting::default(),
+            iso_timestamps: false,
+            trading_periods_per_year: None,
+            rating_period_ms: None,
+            leverage: None,
+            max_leverage: None,
+            maintenance_margin_percent: None,
+            funding_rate: None,
+            funding_interval_ms: None,
+            performance_fee_percent: None,
+            performance_fee_interval_ms: None,
+            cash_yield_annual: None,
+            cash_decimals: None,
+            half_spread_bps: None,
+            close_at_end: None,
+            min_notional: None,
+            step_size: None,
+            integer_quantities: None,
+            smoothing: SmoothingKind::default(),
+            smoothing_period: None,
+            exit_z_score: None,
+            invert_signals: None,
+            signal_confirmation_bars: None,
+            confidence_scaled_threshold: None,
+            confidence_scale: None,
+            session_hours: None,
+            max_open_positions: None,
+            min_bars_between_trades: None,
+            warmup_bars: None,
+            duplicate_ratings: DuplicateRatings::default(),
+            sizing_base: SizingBase::default(),
+            max_notional_multiple: None,
+            max_drawdown_stop_percent: None,
+            signal_price: PriceSource::default(),
+            fill_price: PriceSource::default(),
+            intrabar_assumption: IntrabarAssumption::default(),
+            stop_mode: StopMode::default(),
+            include_prices: false,
+            include_equity_curve: false,
+            annualization_basis: AnnualizationBasis::default(),
+            market_calendar: MarketCalendar::default(),
+            return_basis: ReturnBasis::default(),
+            risk_free_rate: Self::default_risk_free_rate(),
+            risk_free_curve: None,
+            quote_conversions: None,
+            rolling_sharpe_window: None,
+            benchmark_symbol: None,
+            max_portfolio_risk_percent: None,
+            entry_priority: EntryPriority::default(),
+            drawdown_throttle: None,
+        }
+    }
+}
+
+/// Drawdown-based de-risking: once equity is more than `throttle_trigger_percent` below
+/// its peak, entry allocations are multiplied by `throttle_factor` until the drawdown
+/// shrinks back to `reset_percent` or less (0 = a new equity high).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DrawdownThrottle {
+    pub throttle_trigger_percent: f64,
+    pub throttle_factor: f64,
+    #[serde(default)]
+    pub reset_percent: f64,
+}
+
+/// RSI confirmation for z-score signals, computed over the rating series.
+///
+/// A BUY only stands when RSI < `rsi_oversold` and a SELL only when RSI > `rsi_overbought`;
+/// unconfirmed signals are downgraded to HOLD.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RsiFilter {
+    #[serde(default = "RsiFilter::default_period")]
+    pub rsi_period: usize,
+    #[serde(default = "RsiFilter::default_oversold")]
+    pub rsi_oversold: f64,
+    #[serde(default = "RsiFilter::default_overbought")]
+    pub rsi_overbought: f64,
+}
+
+impl RsiFilter {
+    fn default_period() -> usize {
+        14
+    }
+
+    fn default_oversold() -> f64 {
+        30.0
+    }
+
+    fn default_overbought() -> f64 {
+        70.0
+    }
+}
+
+impl Default for RsiFilter {
+    fn default() -> Self {
+        Self {
+            rsi_period: Self::default_period(),
+            rsi_oversold: Self::default_oversold(),
+            rsi_overbought: Self::default_overbought(),
+        }
+    }
+}
+
+/// Unit of the walk-forward window length in `run_windowed_backtest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowUnit {
+    /// Wall-clock windows of `window_size` 30-day months
+    #[default]
+    TimeMs,
+    /// Windows of `window_size` consecutive rating timestamps, for sparse or irregular data
+    Bars,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ZScoreMethod {
+    /// Equal-weighted rolling window (`MovingStats`)
+    #[default]
+    Sma,
+    /// Exponentially weighted window (`EmaStats`), reacts faster to recent ratings
+    Ema,
+}
+
+/// Which point of a kline a price is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    Open,
+    High,
+    Low,
+    #[default]
+    Close,
+    /// (high + low + close) / 3
+    Typical,
+}
+
+impl PriceSource {
+    pub fn of(self, kline: &KlineData) -> f64 {
+        match self {
+            PriceSource::Open => kline.open,
+            PriceSource::High => kline.high,
+            PriceSource::Low => kline.low,
+            PriceSource::Close => kline.close,
+            PriceSource::Typical => (kline.high + kline.low + kline.close) / 3.0,
+        }
+    }
+}
+
+/// Distance of the OCO stop-loss and take-profit levels from the entry price.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum StopMode {
+    /// `stop_loss_percent` / `profit_percent` of the entry price
+    #[default]
+    Percent,
+    /// Fixed price distances, e.g. 5.0 puts a $100 entry's stop at $95
+    AbsoluteOffset { stop_offset: f64, profit_offset: f64 },
+    /// Multiples of the symbol's average true range over the last `atr_period` bars
+    /// (kline high/low/close; the bar-to-bar move for rating-derived prices)
+    AtrMultiple {
+        stop_multiple: f64,
+        profit_multiple: f64,
+        #[serde(default = "StopMode::default_atr_period")]
+        atr_period: usize,
+    },
+}
+
+impl StopMode {
+    fn default_atr_period() -> usize {
+        14
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Resolution of a kline whose low reaches the stop-loss and whose high reaches the
+/// take-profit, where the candle alone cannot say which came first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IntrabarAssumption {
+    #[default]
+    PessimisticStopFirst,
+    OptimisticProfitFirst,
+}
+
+/// What `annualized_return` treats as the elapsed time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnualizationBasis {
+    /// The backtest span (`end_time - start_time`), or elapsed periods with
+    /// `trading_periods_per_year`
+    #[default]
+    Calendar,
+    /// Summed holding time of all positions, so idle periods do not dilute the return
+    TimeInMarket,
+}
+
+/// Which hours a market trades in, for elapsed-time metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketCalendar {
+    /// Every hour counts
+    #[default]
+    Crypto24x7,
+    /// Weekends (Saturday and Sunday, UTC) are skipped and a year is 365.25 * 5/7
+    /// trading days. Holidays are not modelled
+    TradingDays,
+}
+
+impl MarketCalendar {
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    /// Milliseconds from `from` to `to` that fall on trading time.
+    pub fn elapsed_ms(self, from: i64, to: i64) -> i64 {
+        match self {
+            MarketCalendar::Crypto24x7 => to - from,
+            MarketCalendar::TradingDays => Self::weekday_ms_since_epoch(to) - Self::weekday_ms_since_epoch(from),
+        }
+    }
+
+    /// Length of a year in trading milliseconds.
+    pub fn year_ms(self) -> f64 {
+        let calendar_year = 365.25 * Self::DAY_MS as f64;
+        match self {
+            MarketCalendar::Crypto24x7 => calendar_year,
+            MarketCalendar::TradingDays => calendar_year * 5.0 / 7.0,
+        }
+    }
+
+    /// Weekday milliseconds in `[0, timestamp)` (negative before the epoch).
+    fn weekday_ms_since_epoch(timestamp: i64) -> i64 {
+        // Weekday flags for the days of a week starting on the epoch's Thursday
+        const IS_WEEKDAY: [i64; 7] = [1, 1, 0, 0, 1, 1, 1];
+        let day = timestamp.div_euclid(Self::DAY_MS);
+        let (weeks, day_in_week) = (day.div_euclid(7), day.rem_euclid(7) as usize);
+        let whole_days = weeks * 5 + IS_WEEKDAY[..day_in_week].iter().sum::<i64>();
+        whole_days * Self::DAY_MS + IS_WEEKDAY[day_in_week] * timestamp.rem_euclid(Self::DAY_MS)
+    }
+}
+
+/// How `annualized_return` is derived from the total return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnBasis {
+    /// Geometric: `(final / initial)^(1 / years) - 1`
+    #[default]
+    Compounded,
+    /// Arithmetic: `total_return / years`
+    Simple,
+}
+
+/// Moving average applied to ratings before the z-score window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SmoothingKind {
+    /// Simple moving average
+    #[default]
+    Sma,
+    /// Exponential moving average with alpha = 2 / (period + 1)
+    Ema,
+    /// Linearly weighted moving average, newest value heaviest
+    Wma,
+}
+
+/// Which of several simultaneous entry candidates is funded first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryPriority {
+    /// Symbol name order
+    #[default]
+    Alphabetical,
+    /// Largest |z-score| first
+    HighestZScore,
+    /// Highest Glicko rating first
+    HighestRating,
+}
+
+/// Handling of ratings that share a symbol and timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateRatings {
+    /// Keep the last rating for each (symbol, timestamp) and note the drop in diagnostics
+    #[default]
+    KeepLast,
+    /// Reject the input with `BacktestError::DuplicateRating`
+    Error,
+}
+
+/// Base that a new position's `allocation_percent` is applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SizingBase {
+    /// Cash not tied up in open positions
+    #[default]
+    AvailableCash,
+    /// Cash plus open positions (as of the last mark), capped at available cash
+    TotalEquity,
+}
+
+/// How a rebalance splits the invested value across open positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RebalanceWeighting {
+    /// Every open position targets the same share
+    #[default]
+    Equal,
+    /// Shares proportional to each symbol's latest Glicko rating
+    RatingWeighted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BacktestResult {
     pub total_return: f64,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
     pub annualized_return: f64,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
     pub sharpe_ratio: f64,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
     pub sortino_ratio: f64,
+    /// Standard deviation of per-step equity returns times sqrt(periods per year)
+    #[serde(default)]
+    pub annualized_volatility: f64,
+    /// Annualized Jensen's alpha against buy-and-hold of the benchmark symbol
     pub alpha: f64,
+    /// Sensitivity of per-period returns to the benchmark's
+    #[serde(default)]
+    pub beta: f64,
+    /// Mean strategy return over the benchmark's up periods, divided by the benchmark's
+    /// mean return over them
+    #[serde(default)]
+    pub upside_capture: f64,
+    /// The same ratio over the benchmark's down periods; below 1 means declines were
+    /// (partly) sidestepped
+    #[serde(default)]
+    pub downside_capture: f64,
     pub max_drawdown: f64,
+    /// Worst peak-to-trough fall of (strategy equity - benchmark buy-and-hold equity),
+    /// as a fraction of the starting equity; 0 when the strategy never lost ground
+    /// against the benchmark
+    #[serde(default)]
+    pub relative_max_drawdown: f64,
     pub win_ratio: f64,
     pub total_trades: usize,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
     pub profit_factor: f64,
     pub avg_trade_duration: f64,
+    /// Largest `profit_loss_percent` among closed trades (0.0 without trades)
+    #[serde(default)]
+    pub best_trade_percent: f64,
+    /// Smallest `profit_loss_percent` among closed trades (0.0 without trades)
+    #[serde(default)]
+    pub worst_trade_percent: f64,
+    /// Mean `profit_loss` of winning trades
+    #[serde(default)]
+    pub avg_win: f64,
+    /// Mean `profit_loss` of losing trades (negative)
+    #[serde(default)]
+    pub avg_loss: f64,
+    /// Expected P&L per trade: `win_ratio * avg_win - (1 - win_ratio) * |avg_loss|`
+    #[serde(default)]
+    pub expectancy: f64,
     pub orders: Vec<BacktestOrder>,
+    /// Each completed round trip, pairing its entry with its exit
+    #[serde(default)]
+    pub trade_ledger: Vec<TradeLedgerEntry>,
+    /// Closed-trade breakdown for each symbol that completed at least one trade
+    #[serde(default)]
+    pub per_symbol: BTreeMap<String, SymbolStats>,
+    /// Positions still open when the backtest ended, marked to the final price
+    #[serde(default)]
+    pub open_positions: Vec<OpenPositionReport>,
+    /// Notes on signals that could not be acted on (e.g. entries skipped below min notional)
+    #[serde(default)]
+    pub diagnostics: Vec<String>,
+    /// `(symbol, rating count)` for symbols with too little history to reach the
+    /// moving-average window; they produce no signals and are not traded
+    #[serde(default)]
+    pub skipped_symbols: Vec<(String, usize)>,
+    /// Whether the `max_drawdown_stop_percent` kill switch fired
+    #[serde(default)]
+    pub circuit_breaker_triggered: bool,
+    /// Every evaluated bar's (mid) price, in evaluation order; only with `include_prices`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_series: Option<Vec<PricePoint>>,
+    /// `(timestamp, portfolio value)` after every evaluated timestamp, starting from the
+    /// initial cash at `start_time`; only with `include_equity_curve`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equity_curve: Option<Vec<(i64, f64)>>,
+    /// `(timestamp, (peak_so_far - value) / peak_so_far)` for each equity point; only with
+    /// `include_equity_curve`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drawdown_curve: Option<Vec<(i64, f64)>>,
+    /// `(timestamp, realized_pnl, unrealized_pnl)` for each equity point; only with
+    /// `include_equity_curve`. Unrealized is open positions marked against their cost
+    /// basis; realized is everything else already in cash (closed trades, fees, funding)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pnl_curve: Option<Vec<(i64, f64, f64)>>,
+    /// `(timestamp, Sharpe of the trailing window ending there)`; only with
+    /// `rolling_sharpe_window`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rolling_sharpe: Option<Vec<(i64, f64)>>,
+}
+
+/// Which of two compared results did better on a metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Better {
+    A,
+    B,
+    Tie,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MetricComparison {
+    pub metric: String,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
+    pub a: f64,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
+    pub b: f64,
+    /// `b - a` (0 whenever the values are equal, including equal infinities)
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
+    pub delta: f64,
+    pub better: Better,
+}
+
+/// Metric-by-metric comparison of two backtest results, from `compare_results`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ResultComparison {
+    pub metrics: Vec<MetricComparison>,
+}
+
+/// Step sizes for `backtest::sensitivity`; each parameter is tried at `value ± delta`.
+/// A zero delta leaves that parameter out.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SensitivityDeltas {
+    #[serde(default)]
+    pub z_score_threshold: f64,
+    #[serde(default)]
+    pub profit_percent: f64,
+    #[serde(default)]
+    pub stop_loss_percent: f64,
+    #[serde(default)]
+    pub moving_averages: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SensitivityPoint {
+    pub parameter: String,
+    pub delta: f64,
+    /// The perturbed parameter value
+    pub value: f64,
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
+    pub sharpe_ratio: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SensitivityReport {
+    #[serde(with = "non_finite")]
+    #[schemars(schema_with = "non_finite::schema")]
+    pub baseline_sharpe: f64,
+    pub perturbations: Vec<SensitivityPoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SymbolStats {
+    pub total_trades: usize,
+    pub win_ratio: f64,
+    /// Sum of realized P&L of the symbol's closed trades
+    pub net_pnl: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PricePoint {
+    pub timestamp: i64,
+    pub symbol: String,
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OpenPositionReport {
+    pub symbol: String,
+    pub quantity: f64,
+    pub entry_price: f64,
+    pub entry_time: i64,
+    pub mark_price: f64,
+    pub unrealized_pnl: f64,
+    pub unrealized_pnl_percent: f64,
+}
+
+/// One round trip from entry to exit. Equity is read from the equity curve: as of the
+/// bar before the entry and as of the exit bar.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TradeLedgerEntry {
+    pub symbol: String,
+    pub entry_time: i64,
+    pub exit_time: i64,
+    pub entry_price: f64,
+    pub exit_price: f64,
+    /// Quantity closed by the exit order
+    pub quantity: f64,
+    pub exit_reason: ExitReason,
+    /// Realized P&L of the exit plus any rebalance trims taken while the trade was open
+    pub profit_loss: f64,
+    pub entry_equity: f64,
+    pub exit_equity: f64,
+    /// Holding time in hours
+    pub duration_hours: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BacktestOrder {
     pub symbol: String,
-    pub side: String,
+    pub side: OrderSide,
     pub quantity: f64,
     pub price: f64,
     pub timestamp: i64,
-    pub reason: String,
+    /// RFC 3339 form of `timestamp`, only set when `iso_timestamps` is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_iso: Option<String>,
+    pub reason: ExitReason,
     pub profit_loss: Option<f64>,
     pub profit_loss_percent: Option<f64>,
+    /// Part of a trade opened during `warmup_bars`; excluded from the metrics
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warmup: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+/// Why an order was placed. Serialized with the historical order reason strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExitReason {
+    /// Z-score BUY opening a position
+    Entry,
+    /// Stop-loss leg of the OCO pair
+    ExitStop,
+    /// Take-profit leg of the OCO pair
+    ExitProfit,
+    /// Z-score SELL or reversion into the `exit_z_score` band
+    #[serde(rename = "EXIT_ZSCORE")]
+    ExitZScore,
+    /// Leveraged position equity fell to maintenance margin
+    ExitLiquidation,
+    /// Closed at the final bar with `close_at_end`
+    ExitEnd,
+    /// Resize to target weights, either side
+    Rebalance,
+    /// Flattened by the `max_drawdown_stop_percent` kill switch
+    ExitCircuitBreaker,
 }
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
@@ -86,4 +900,219 @@ pub fn run_windowed_backtest(
     ratings: Vec<GlickoRating>
 ) -> Result<Vec<BacktestResult>> {
     backtest::run_windowed_backtest(config, ratings)
-}
\ No newline at end of file
+}
+
+/// Generate per-symbol z-score signals without simulating trades.
+pub fn generate_signals(config: &BacktestConfig, ratings: &[GlickoRating]) -> Result<backtest::SymbolSignals> {
+    Ok(backtest::calculate_z_score_signals(ratings, config)?)
+}
+
+/// Calculate ratings from klines and backtest them in one pass, skipping the
+/// intermediate JSON round-trip of the ratings array.
+pub fn run_pipeline(config: BacktestConfig, klines: Vec<KlineData>) -> Result<BacktestResult> {
+    let ratings = glicko::calculate_ratings(klines)?;
+    backtest::run_backtest(config, ratings)
+}
+
+/// Compare every scored metric of `b` against `a`. Higher is better except for
+/// volatility, drawdowns and downside capture, where lower is better.
+pub fn compare_results(a: &BacktestResult, b: &BacktestResult) -> ResultComparison {
+    type Metric = fn(&BacktestResult) -> f64;
+    let metrics: [(&str, Metric, bool); 18] = [
+        ("total_return", |r| r.total_return, true),
+        ("annualized_return", |r| r.annualized_return, true),
+        ("sharpe_ratio", |r| r.sharpe_ratio, true),
+        ("sortino_ratio", |r| r.sortino_ratio, true),
+        ("annualized_volatility", |r| r.annualized_volatility, false),
+        ("alpha", |r| r.alpha, true),
+        ("upside_capture", |r| r.upside_capture, true),
+        ("downside_capture", |r| r.downside_capture, false),
+        ("max_drawdown", |r| r.max_drawdown, false),
+        ("relative_max_drawdown", |r| r.relative_max_drawdown, false),
+        ("win_ratio", |r| r.win_ratio, true),
+        ("profit_factor", |r| r.profit_factor, true),
+        ("best_trade_percent", |r| r.best_trade_percent, true),
+        ("worst_trade_percent", |r| r.worst_trade_percent, true),
+        ("avg_win", |r| r.avg_win, true),
+        ("avg_loss", |r| r.avg_loss, true),
+        ("expectancy", |r| r.expectancy, true),
+        ("net_pnl", |r| r.per_symbol.values().map(|stats| stats.net_pnl).sum(), true),
+    ];
+
+    let metrics = metrics
+        .into_iter()
+        .map(|(metric, value, higher_is_better)| {
+            let (a, b) = (value(a), value(b));
+            let better = match a.partial_cmp(&b) {
+                Some(std::cmp::Ordering::Less) if higher_is_better => Better::B,
+                Some(std::cmp::Ordering::Less) => Better::A,
+                Some(std::cmp::Ordering::Greater) if higher_is_better => Better::A,
+                Some(std::cmp::Ordering::Greater) => Better::B,
+                _ => Better::Tie,
+            };
+            MetricComparison {
+                metric: metric.to_string(),
+                a,
+                b,
+                delta: if a == b { 0.0 } else { b - a },
+                better,
+            }
+        })
+        .collect();
+    ResultComparison { metrics }
+}
+
+/// JSON Schemas for the public input/output types, keyed by type name, so
+/// integrators can validate payloads before handing them to the CLI.
+pub fn json_schemas() -> std::collections::BTreeMap<&'static str, schemars::schema::RootSchema> {
+    std::collections::BTreeMap::from([
+        ("KlineData", schemars::schema_for!(KlineData)),
+        ("GlickoRating", schemars::schema_for!(GlickoRating)),
+        ("BacktestConfig", schemars::schema_for!(BacktestConfig)),
+        ("BacktestResult", schemars::schema_for!(BacktestResult)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sample_klines;
+
+    fn pipeline_config() -> BacktestConfig {
+        BacktestConfig {
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            z_score_threshold: 1.0,
+            moving_averages: 10,
+            profit_percent: Some(5.0),
+            stop_loss_percent: 2.5,
+            start_time: 1640995200000,
+            end_time: 1640995200000 + 500 * 60 * 60 * 1000,
+            ..BacktestConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_pipeline_matches_separate_steps() {
+        let klines = sample_klines("BTCUSDT", 500);
+
+        let ratings = calculate_glicko_ratings(klines.clone()).unwrap();
+        let separate = run_backtest(pipeline_config(), ratings).unwrap();
+        let combined = run_pipeline(pipeline_config(), klines).unwrap();
+
+        assert!(combined.total_trades > 0);
+        assert_eq!(
+            serde_json::to_string(&combined).unwrap(),
+            serde_json::to_string(&separate).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trading_days_calendar_skips_weekends() {
+        let day_ms = 24 * 60 * 60 * 1000;
+        let monday = 1704067200000; // 2024-01-01
+        let friday_noon = monday + 4 * day_ms + day_ms / 2;
+
+        assert_eq!(MarketCalendar::Crypto24x7.elapsed_ms(monday, monday + 7 * day_ms), 7 * day_ms);
+        assert_eq!(MarketCalendar::TradingDays.elapsed_ms(monday, monday + 7 * day_ms), 5 * day_ms);
+        assert_eq!(MarketCalendar::TradingDays.elapsed_ms(friday_noon, friday_noon + 3 * day_ms), day_ms);
+        // Saturday to Sunday is no trading time at all
+        assert_eq!(MarketCalendar::TradingDays.elapsed_ms(monday + 5 * day_ms, monday + 7 * day_ms), 0);
+    }
+
+    #[test]
+    fn test_compare_results_against_itself_is_all_ties() {
+        let klines = sample_klines("BTCUSDT", 500);
+        let result = run_pipeline(pipeline_config(), klines).unwrap();
+
+        let comparison = compare_results(&result, &result);
+        assert!(!comparison.metrics.is_empty());
+        for metric in &comparison.metrics {
+            assert_eq!(metric.delta, 0.0, "{}", metric.metric);
+            assert_eq!(metric.better, Better::Tie, "{}", metric.metric);
+        }
+
+        let mut shallower = result.clone();
+        shallower.max_drawdown = result.max_drawdown / 2.0;
+        let comparison = compare_results(&result, &shallower);
+        let drawdown = comparison.metrics.iter().find(|m| m.metric == "max_drawdown").unwrap();
+        assert_eq!(drawdown.better, Better::B);
+        assert!(drawdown.delta < 0.0);
+    }
+
+    #[test]
+    fn test_generate_signals_labels_known_series() {
+        let ratings = crate::test_utils::ratings("BTCUSDT", &[1500.0, 1502.0, 1510.0, 1490.0, 1500.0], 1000);
+        let config = BacktestConfig {
+            moving_averages: 2,
+            z_score_threshold: 1.0,
+            ..BacktestConfig::default()
+        };
+
+        let signals = generate_signals(&config, &ratings).unwrap();
+        let labels: Vec<(i64, String)> = signals["BTCUSDT"]
+            .iter()
+            .map(|s| (s.timestamp, s.signal.to_string()))
+            .collect();
+
+        // Windows: [1500,1502]→1510 (z=9), [1502,1510]→1490 (z=-4), [1510,1490]→1500 (z=0)
+        assert_eq!(
+            labels,
+            vec![(2000, "BUY".to_string()), (3000, "SELL".to_string()), (4000, "HOLD".to_string())]
+        );
+        assert!((signals["BTCUSDT"][0].z_score - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_backtest_config_schema_field_types() {
+        let schemas = json_schemas();
+        let schema = serde_json::to_value(&schemas["BacktestConfig"]).unwrap();
+        let properties = &schema["properties"];
+
+        assert_eq!(properties["z_score_threshold"]["type"], "number");
+        assert_eq!(properties["window_size"]["type"], serde_json::json!(["integer", "null"]));
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&serde_json::json!("z_score_threshold")));
+        assert!(!required.contains(&serde_json::json!("window_size")));
+    }
+
+    #[test]
+    fn test_order_enums_serialize_as_legacy_strings() {
+        let sides = [(OrderSide::Buy, "BUY"), (OrderSide::Sell, "SELL")];
+        for (side, legacy) in sides {
+            assert_eq!(serde_json::to_string(&side).unwrap(), format!("\"{}\"", legacy));
+        }
+
+        let reasons = [
+            (ExitReason::Entry, "ENTRY"),
+            (ExitReason::ExitStop, "EXIT_STOP"),
+            (ExitReason::ExitProfit, "EXIT_PROFIT"),
+            (ExitReason::ExitZScore, "EXIT_ZSCORE"),
+            (ExitReason::ExitLiquidation, "EXIT_LIQUIDATION"),
+            (ExitReason::ExitEnd, "EXIT_END"),
+            (ExitReason::Rebalance, "REBALANCE"),
+            (ExitReason::ExitCircuitBreaker, "EXIT_CIRCUIT_BREAKER"),
+        ];
+        for (reason, legacy) in reasons {
+            assert_eq!(serde_json::to_string(&reason).unwrap(), format!("\"{}\"", legacy));
+            assert_eq!(serde_json::from_str::<ExitReason>(&format!("\"{}\"", legacy)).unwrap(), reason);
+        }
+
+        let order = BacktestOrder {
+            symbol: "BTCUSDT".to_string(),
+            side: OrderSide::Sell,
+            quantity: 1.0,
+            price: 100.0,
+            timestamp: 1000,
+            timestamp_iso: None,
+            reason: ExitReason::ExitZScore,
+            profit_loss: Some(5.0),
+            profit_loss_percent: Some(5.0),
+            warmup: false,
+        };
+        assert_eq!(
+            serde_json::to_string(&order).unwrap(),
+            r#"{"symbol":"BTCUSDT","side":"SELL","quantity":1.0,"price":100.0,"timestamp":1000,"reason":"EXIT_ZSCORE","profit_loss":5.0,"profit_loss_percent":5.0}"#
+        );
+    }
+}
@@ -1,61 +1,348 @@
-use clap::{Arg, Command};
-use glicko_core::{calculate_glicko_ratings, run_backtest, run_windowed_backtest, KlineData, BacktestConfig, GlickoRating};
-use std::io::{self, Read};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use glicko_core::{calculate_glicko_ratings, compare_results, generate_signals, json_schemas, run_backtest, run_pipeline, run_windowed_backtest, KlineData, BacktestConfig, BacktestResult, GlickoRating, SensitivityDeltas};
+use glicko_core::glicko::{
+    calculate_ratings_incremental, calculate_ratings_with_progress, GlickoConfig, GlickoPlayer, RatingStream,
+    PROGRESS_INTERVAL,
+};
+use glicko_core::backtest::sensitivity;
+use glicko_core::io::{read_input, read_ndjson, write_orders_csv};
+use glicko_core::monte_carlo::{bootstrap_trade_returns, MonteCarloConfig};
+use glicko_core::validation::validate_klines;
+use std::io::{self, IsTerminal, Write};
+use tracing::Level;
 use anyhow::Result;
 
-fn main() -> Result<()> {
-    let matches = Command::new("glicko-core")
+fn cli() -> Command {
+    Command::new("glicko-core")
         .version("1.0")
         .author("Trading Bot System")
         .about("High-performance Glicko-2 rating and backtesting engine")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::Count)
+                .global(true)
+                .help("Increase log verbosity on stderr (-v info, -vv debug, -vvv trace)"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_parser(clap::value_parser!(u64))
+                .global(true)
+                .help("Seed for randomized commands so runs are reproducible (default: time-based)"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .value_parser(clap::value_parser!(usize))
+                .global(true)
+                .help("Worker threads for parallel work (0 or absent: one per core)"),
+        )
+        .after_help("JSON input on stdin may be gzip-compressed; it is detected and decompressed automatically.")
         .subcommand(
             Command::new("calculate-glicko")
                 .about("Calculate Glicko-2 ratings from klines data")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .help("Read klines from a file instead of stdin (.parquet requires the `parquet` feature)"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("Write ratings to a file instead of stdout (.parquet requires the `parquet` feature)"),
+                )
+                .arg(
+                    Arg::new("progress")
+                        .long("progress")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["state-in", "state-out"])
+                        .help("Report progress to stderr every 100k klines"),
+                )
+                .arg(
+                    Arg::new("state-in")
+                        .long("state-in")
+                        .help("Resume from player state saved by a previous --state-out"),
+                )
+                .arg(
+                    Arg::new("state-out")
+                        .long("state-out")
+                        .help("Write the final player state as JSON for the next incremental run"),
+                )
+                .arg(
+                    Arg::new("ndjson")
+                        .long("ndjson")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("progress")
+                        .help("Stream one kline per input line and one rating per output line; input must be sorted by open_time"),
+                )
         )
         .subcommand(
             Command::new("run-backtest")
                 .about("Run a backtest with given configuration")
+                .arg(iso_timestamps_arg())
+                .arg(
+                    Arg::new("orders-csv")
+                        .long("orders-csv")
+                        .help("Also write the order list as CSV to this path"),
+                )
         )
         .subcommand(
             Command::new("run-windowed-backtest")
                 .about("Run windowed backtest with walk-forward analysis")
+                .arg(iso_timestamps_arg())
+        )
+        .subcommand(
+            Command::new("generate-signals")
+                .about("Output per-symbol z-score signals for ratings without running a backtest")
+        )
+        .subcommand(
+            Command::new("pipeline")
+                .about("Calculate ratings from klines and backtest them in one pass")
+                .arg(iso_timestamps_arg())
+        )
+        .subcommand(
+            Command::new("monte-carlo")
+                .about("Backtest, then bootstrap closed-trade returns into a distribution of total returns")
+        )
+        .subcommand(
+            Command::new("sensitivity")
+                .about("Report the Sharpe of backtests with each tuned parameter nudged by the given deltas")
+        )
+        .subcommand(
+            Command::new("check-klines")
+                .about("Report duplicate open times and missing candles per symbol")
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Compare two run-backtest result files metric by metric")
+                .arg(Arg::new("a").required(true).help("Baseline result JSON"))
+                .arg(Arg::new("b").required(true).help("Result JSON compared against the baseline")),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print JSON Schemas for the public input/output types")
+                .arg(
+                    Arg::new("type")
+                        .long("type")
+                        .value_parser(["KlineData", "GlickoRating", "BacktestConfig", "BacktestResult"])
+                        .help("Print only this type's schema"),
+                )
+        )
+        .subcommand(
+            Command::new("info").about("Print the version, compiled-in features and subcommands as JSON"),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("fetch-klines")
+                .about("Fetch klines from the Binance REST API (requires the `binance` feature)")
+                .arg(Arg::new("symbol").long("symbol").required(true))
+                .arg(Arg::new("interval").long("interval").default_value("1h"))
+                .arg(
+                    Arg::new("start")
+                        .long("start")
+                        .required(true)
+                        .value_parser(clap::value_parser!(i64)),
+                )
+                .arg(
+                    Arg::new("end")
+                        .long("end")
+                        .required(true)
+                        .value_parser(clap::value_parser!(i64)),
+                )
+        )
+}
+
+fn iso_timestamps_arg() -> Arg {
+    Arg::new("iso-timestamps")
+        .long("iso-timestamps")
+        .action(ArgAction::SetTrue)
+        .help("Add RFC 3339 timestamps (UTC) alongside epoch millis in the output")
+}
+
+/// Map the repeated `-v` count to a tracing level; warnings are always shown.
+fn log_level(verbosity: u8) -> Level {
+    match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// Rayon pool settings for `--threads`; rayon treats 0 as "one thread per core".
+fn thread_pool_builder(threads: usize) -> rayon::ThreadPoolBuilder {
+    rayon::ThreadPoolBuilder::new().num_threads(threads)
+}
+
+fn main() -> Result<()> {
+    let matches = cli().get_matches();
+
+    // Must happen before anything touches the global pool
+    if let Some(&threads) = matches.get_one::<usize>("threads") {
+        thread_pool_builder(threads).build_global()?;
+    }
+
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_max_level(log_level(matches.get_count("verbose")))
+        .init();
 
     match matches.subcommand() {
-        Some(("calculate-glicko", _)) => {
-            let mut input = String::new();
-            io::stdin().read_to_string(&mut input)?;
-            
-            let klines: Vec<KlineData> = serde_json::from_str(&input)?;
-            let ratings = calculate_glicko_ratings(klines).map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            
-            println!("{}", serde_json::to_string(&ratings)?);
+        Some(("calculate-glicko", sub_matches)) if sub_matches.get_flag("ndjson") => {
+            stream_glicko(sub_matches)?;
+        },
+        Some(("calculate-glicko", sub_matches)) => {
+            let klines = match sub_matches.get_one::<String>("input") {
+                Some(path) if is_parquet(path) => read_parquet_klines(path)?,
+                Some(path) => {
+                    let file = std::fs::File::open(path)?;
+                    let input = read_input(file).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                    serde_json::from_str(&input)?
+                }
+                None => {
+                    let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                    serde_json::from_str(&input)?
+                }
+            };
+
+            let ratings = calculate_glicko(sub_matches, klines)?;
+            write_ratings(sub_matches, &ratings)?;
         },
-        Some(("run-backtest", _)) => {
-            let mut input = String::new();
-            io::stdin().read_to_string(&mut input)?;
+        Some(("run-backtest", sub_matches)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
             
             let data: serde_json::Value = serde_json::from_str(&input)?;
-            let config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            let mut config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            config.iso_timestamps |= sub_matches.get_flag("iso-timestamps");
             let ratings: Vec<GlickoRating> = serde_json::from_value(data["ratings"].clone())?;
             
             let result = run_backtest(config, ratings).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            if let Some(path) = sub_matches.get_one::<String>("orders-csv") {
+                write_orders_csv(std::fs::File::create(path)?, &result.orders)
+                    .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            }
             
             println!("{}", serde_json::to_string(&result)?);
         },
-        Some(("run-windowed-backtest", _)) => {
-            let mut input = String::new();
-            io::stdin().read_to_string(&mut input)?;
+        Some(("run-windowed-backtest", sub_matches)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
             
             let data: serde_json::Value = serde_json::from_str(&input)?;
-            let config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            let mut config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            config.iso_timestamps |= sub_matches.get_flag("iso-timestamps");
             let ratings: Vec<GlickoRating> = serde_json::from_value(data["ratings"].clone())?;
             
             let results = run_windowed_backtest(config, ratings).map_err(|e| anyhow::anyhow!(e.to_string()))?;
             
             println!("{}", serde_json::to_string(&results)?);
         },
+        Some(("generate-signals", _)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            let data: serde_json::Value = serde_json::from_str(&input)?;
+            let config = config_with_defaults(data["config"].clone())?;
+            let ratings: Vec<GlickoRating> = serde_json::from_value(data["ratings"].clone())?;
+
+            let signals = generate_signals(&config, &ratings).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            println!("{}", serde_json::to_string(&signals)?);
+        },
+        Some(("pipeline", sub_matches)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            let data: serde_json::Value = serde_json::from_str(&input)?;
+            let mut config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            config.iso_timestamps |= sub_matches.get_flag("iso-timestamps");
+            let klines: Vec<KlineData> = serde_json::from_value(data["klines"].clone())?;
+
+            let result = run_pipeline(config, klines).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            println!("{}", serde_json::to_string(&result)?);
+        },
+        Some(("monte-carlo", sub_matches)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            let data: serde_json::Value = serde_json::from_str(&input)?;
+            let config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            let ratings: Vec<GlickoRating> = serde_json::from_value(data["ratings"].clone())?;
+            let mut monte_carlo: MonteCarloConfig = match data.get("monte_carlo") {
+                Some(value) => serde_json::from_value(value.clone())?,
+                None => MonteCarloConfig::default(),
+            };
+            if let Some(seed) = sub_matches.get_one::<u64>("seed") {
+                monte_carlo.seed = Some(*seed);
+            }
+
+            let result = run_backtest(config, ratings).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            let summary = bootstrap_trade_returns(&result, &monte_carlo);
+
+            println!("{}", serde_json::to_string(&summary)?);
+        },
+        Some(("sensitivity", _)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            let data: serde_json::Value = serde_json::from_str(&input)?;
+            let config: BacktestConfig = serde_json::from_value(data["config"].clone())?;
+            let ratings: Vec<GlickoRating> = serde_json::from_value(data["ratings"].clone())?;
+            let klines: Vec<KlineData> = match data.get("klines") {
+                Some(value) => serde_json::from_value(value.clone())?,
+                None => Vec::new(),
+            };
+            let deltas: SensitivityDeltas = serde_json::from_value(data["deltas"].clone())?;
+
+            let report = sensitivity(&config, &ratings, &klines, &deltas).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            println!("{}", serde_json::to_string(&report)?);
+        },
+        Some(("check-klines", _)) => {
+            let input = read_input(io::stdin().lock()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            let klines: Vec<KlineData> = serde_json::from_str(&input)?;
+
+            let reports = validate_klines(&klines);
+            for (symbol, report) in reports.iter().filter(|(_, report)| !report.is_clean()) {
+                tracing::warn!(
+                    %symbol,
+                    duplicates = report.duplicates.len(),
+                    gaps = report.gaps.len(),
+                    "Kline integrity issues"
+                );
+            }
+
+            println!("{}", serde_json::to_string(&reports)?);
+        },
+        Some(("compare", sub_matches)) => {
+            let read_result = |name: &str| -> Result<BacktestResult> {
+                let file = std::fs::File::open(sub_matches.get_one::<String>(name).unwrap())?;
+                let input = read_input(file).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                Ok(serde_json::from_str(&input)?)
+            };
+            let comparison = compare_results(&read_result("a")?, &read_result("b")?);
+
+            println!("{}", serde_json::to_string(&comparison)?);
+        },
+        Some(("schema", sub_matches)) => {
+            let schemas = json_schemas();
+            match sub_matches.get_one::<String>("type") {
+                Some(name) => println!("{}", serde_json::to_string_pretty(&schemas[name.as_str()])?),
+                None => println!("{}", serde_json::to_string_pretty(&schemas)?),
+            }
+        },
+        Some(("info", _)) => {
+            println!("{}", serde_json::to_string(&build_info())?);
+        },
+        Some(("fetch-klines", sub_matches)) => {
+            let symbol = sub_matches.get_one::<String>("symbol").unwrap();
+            let interval = sub_matches.get_one::<String>("interval").unwrap();
+            let start = *sub_matches.get_one::<i64>("start").unwrap();
+            let end = *sub_matches.get_one::<i64>("end").unwrap();
+
+            let klines = fetch_klines(symbol, interval, start, end)?;
+
+            println!("{}", serde_json::to_string(&klines)?);
+        },
         _ => {
             eprintln!("No subcommand was used. Use --help for available commands.");
             std::process::exit(1);
@@ -65,6 +352,156 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Rate klines for `calculate-glicko`, resuming from/saving player state when
+/// `--state-in`/`--state-out` are given.
+fn calculate_glicko(sub_matches: &ArgMatches, klines: Vec<KlineData>) -> Result<Vec<GlickoRating>> {
+    let state_in = sub_matches.get_one::<String>("state-in");
+    let state_out = sub_matches.get_one::<String>("state-out");
+
+    if state_in.is_none() && state_out.is_none() {
+        let ratings = if sub_matches.get_flag("progress") {
+            calculate_ratings_with_progress(klines, PROGRESS_INTERVAL, |done, total| {
+                eprintln!(
+                    "Processed {}/{} klines ({:.1}%)",
+                    done,
+                    total,
+                    done as f64 / total.max(1) as f64 * 100.0
+                );
+            })
+        } else {
+            calculate_glicko_ratings(klines)
+        };
+        return ratings.map_err(|e| anyhow::anyhow!(e.to_string()));
+    }
+
+    let prior_state: Vec<GlickoPlayer> = match state_in {
+        Some(path) => {
+            let input = read_input(std::fs::File::open(path)?).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            serde_json::from_str(&input)?
+        }
+        None => Vec::new(),
+    };
+
+    let (ratings, state) =
+        calculate_ratings_incremental(prior_state, klines).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    if let Some(path) = state_out {
+        std::fs::write(path, serde_json::to_string(&state)?)?;
+    }
+
+    Ok(ratings)
+}
+
+/// Write `calculate-glicko` ratings to `--output`, or as JSON to stdout.
+fn write_ratings(sub_matches: &ArgMatches, ratings: &[GlickoRating]) -> Result<()> {
+    match sub_matches.get_one::<String>("output") {
+        Some(path) if is_parquet(path) => write_parquet_ratings(path, ratings)?,
+        Some(path) => std::fs::write(path, serde_json::to_string(ratings)?)?,
+        None => println!("{}", serde_json::to_string(ratings)?),
+    }
+    Ok(())
+}
+
+/// NDJSON mode of `calculate-glicko`: ratings are written as each kline is read, so
+/// memory stays bounded by the number of symbols.
+fn stream_glicko(sub_matches: &ArgMatches) -> Result<()> {
+    let paths = [sub_matches.get_one::<String>("input"), sub_matches.get_one::<String>("output")];
+    if paths.into_iter().flatten().any(|path| is_parquet(path)) {
+        anyhow::bail!("--ndjson does not support parquet files");
+    }
+
+    let prior_state: Vec<GlickoPlayer> = match sub_matches.get_one::<String>("state-in") {
+        Some(path) => {
+            let input = read_input(std::fs::File::open(path)?).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            serde_json::from_str(&input)?
+        }
+        None => Vec::new(),
+    };
+    let input: Box<dyn io::Read> = match sub_matches.get_one::<String>("input") {
+        Some(path) => Box::new(std::fs::File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let klines = read_ndjson(input).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let output: Box<dyn io::Write> = match sub_matches.get_one::<String>("output") {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut output = io::BufWriter::new(output);
+
+    let mut stream = RatingStream::new(klines, prior_state, GlickoConfig::default());
+    for rating in stream.by_ref() {
+        let rating = rating.map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        serde_json::to_writer(&mut output, &rating)?;
+        writeln!(output)?;
+    }
+    output.flush()?;
+
+    if let Some(path) = sub_matches.get_one::<String>("state-out") {
+        std::fs::write(path, serde_json::to_string(&stream.into_state())?)?;
+    }
+    Ok(())
+}
+
+/// Crate version, enabled cargo features and available subcommands, for orchestrators
+/// checking which binary they are invoking.
+fn build_info() -> serde_json::Value {
+    let features: Vec<&str> = [("binance", cfg!(feature = "binance")), ("parquet", cfg!(feature = "parquet"))]
+        .into_iter()
+        .filter_map(|(feature, enabled)| enabled.then_some(feature))
+        .collect();
+    let subcommands: Vec<String> = cli().get_subcommands().map(|command| command.get_name().to_string()).collect();
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "features": features,
+        "subcommands": subcommands,
+    })
+}
+
+/// Overlay a partial config object onto `BacktestConfig::default()`, for subcommands
+/// that only need a few fields (e.g. moving_averages and z_score_threshold).
+fn config_with_defaults(partial: serde_json::Value) -> Result<BacktestConfig> {
+    let mut merged = serde_json::to_value(BacktestConfig::default())?;
+    if let (Some(fields), serde_json::Value::Object(overrides)) = (merged.as_object_mut(), partial) {
+        fields.extend(overrides);
+    }
+    Ok(serde_json::from_value(merged)?)
+}
+
+fn is_parquet(path: &str) -> bool {
+    path.ends_with(".parquet")
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet_klines(path: &str) -> Result<Vec<KlineData>> {
+    glicko_core::parquet_io::read_klines(path).map_err(|e| anyhow::anyhow!(e.to_string()))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet_klines(_path: &str) -> Result<Vec<KlineData>> {
+    anyhow::bail!("Parquet input requires glicko-core to be built with `--features parquet`")
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet_ratings(path: &str, ratings: &[GlickoRating]) -> Result<()> {
+    glicko_core::parquet_io::write_ratings(path, ratings).map_err(|e| anyhow::anyhow!(e.to_string()))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet_ratings(_path: &str, _ratings: &[GlickoRating]) -> Result<()> {
+    anyhow::bail!("Parquet output requires glicko-core to be built with `--features parquet`")
+}
+
+#[cfg(feature = "binance")]
+fn fetch_klines(symbol: &str, interval: &str, start: i64, end: i64) -> Result<Vec<KlineData>> {
+    glicko_core::binance::fetch_klines(symbol, interval, start, end)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))
+}
+
+#[cfg(not(feature = "binance"))]
+fn fetch_klines(_symbol: &str, _interval: &str, _start: i64, _end: i64) -> Result<Vec<KlineData>> {
+    anyhow::bail!("fetch-klines requires glicko-core to be built with `--features binance`")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,12 +515,154 @@ mod tests {
 
     #[test]
     fn test_subcommands_exist() {
-        let app = Command::new("glicko-core")
-            .subcommand(Command::new("calculate-glicko"))
-            .subcommand(Command::new("run-backtest"))
-            .subcommand(Command::new("run-windowed-backtest"));
-        
-        let matches = app.try_get_matches_from(vec!["glicko-core", "calculate-glicko"]);
-        assert!(matches.is_ok());
-    }
-}
\ No newline at end of file
+        let app = cli();
+        let names: Vec<&str> = app.get_subcommands().map(|sub| sub.get_name()).collect();
+        for expected in [
+            "calculate-glicko",
+            "run-backtest",
+            "run-windowed-backtest",
+            "generate-signals",
+            "pipeline",
+            "monte-carlo",
+            "sensitivity",
+            "check-klines",
+            "compare",
+            "schema",
+            "info",
+            "fetch-klines",
+        ] {
+            assert!(names.contains(&expected), "missing subcommand {}", expected);
+        }
+    }
+
+    #[test]
+    fn test_verbose_flag_selects_log_level() {
+        let matches = cli()
+            .try_get_matches_from(vec!["glicko-core", "-vv", "calculate-glicko"])
+            .unwrap();
+        assert_eq!(log_level(matches.get_count("verbose")), Level::DEBUG);
+        assert_eq!(log_level(0), Level::WARN);
+    }
+
+    #[test]
+    fn test_iso_timestamps_flag() {
+        let matches = cli()
+            .try_get_matches_from(vec!["glicko-core", "run-backtest", "--iso-timestamps"])
+            .unwrap();
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        assert!(sub_matches.get_flag("iso-timestamps"));
+    }
+
+    #[test]
+    fn test_progress_conflicts_with_incremental_state() {
+        for state_arg in ["--state-in", "--state-out"] {
+            let err = cli()
+                .try_get_matches_from(vec!["glicko-core", "calculate-glicko", "--progress", state_arg, "state.json"])
+                .unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    fn sample_klines(count: usize) -> Vec<KlineData> {
+        let mut price = 100.0;
+        (0..count as i64)
+            .map(|i| {
+                let open = price;
+                price *= 1.0 + (i as f64 / 6.0).sin() * 0.01;
+                KlineData {
+                    symbol: "BTCUSDT".to_string(),
+                    open_time: i * 3_600_000,
+                    close_time: (i + 1) * 3_600_000 - 1,
+                    open,
+                    high: open.max(price),
+                    low: open.min(price),
+                    close: price,
+                    volume: 100.0,
+                    quote_asset_volume: 100.0 * price,
+                    number_of_trades: 10,
+                    taker_buy_base_asset_volume: 50.0,
+                    taker_buy_quote_asset_volume: 50.0 * price,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chained_state_runs_match_combined_run() {
+        let dir = std::env::temp_dir().join(format!("glicko-state-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let state_path = dir.join("state.json").to_string_lossy().to_string();
+
+        let klines = sample_klines(100);
+        let run = |args: &[&str], klines: Vec<KlineData>| {
+            let mut argv = vec!["glicko-core", "calculate-glicko"];
+            argv.extend_from_slice(args);
+            let matches = cli().try_get_matches_from(argv).unwrap();
+            let (_, sub_matches) = matches.subcommand().unwrap();
+            calculate_glicko(sub_matches, klines).unwrap()
+        };
+
+        let combined = run(&[], klines.clone());
+        let mut chained = run(&["--state-out", &state_path], klines[..40].to_vec());
+        chained.extend(run(&["--state-in", &state_path, "--state-out", &state_path], klines[40..].to_vec()));
+
+        assert_eq!(
+            serde_json::to_string(&combined).unwrap(),
+            serde_json::to_string(&chained).unwrap()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_progress_flag_leaves_written_ratings_unchanged() {
+        let dir = std::env::temp_dir().join(format!("glicko-progress-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let klines = sample_klines(100);
+        let run = |args: &[&str]| {
+            let output = dir.join("ratings.json").to_string_lossy().to_string();
+            let mut argv = vec!["glicko-core", "calculate-glicko", "--output", &output];
+            argv.extend_from_slice(args);
+            let matches = cli().try_get_matches_from(argv).unwrap();
+            let (_, sub_matches) = matches.subcommand().unwrap();
+            let ratings = calculate_glicko(sub_matches, klines.clone()).unwrap();
+            write_ratings(sub_matches, &ratings).unwrap();
+            std::fs::read(&output).unwrap()
+        };
+
+        assert_eq!(run(&[]), run(&["--progress"]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_single_thread_sensitivity_matches_default_pool() {
+        let matches = cli()
+            .try_get_matches_from(vec!["glicko-core", "--threads", "1", "sensitivity"])
+            .unwrap();
+        let threads = *matches.get_one::<usize>("threads").unwrap();
+
+        let ratings = calculate_glicko_ratings(sample_klines(300)).unwrap();
+        let config = BacktestConfig { moving_averages: 10, ..BacktestConfig::default() };
+        let deltas = SensitivityDeltas {
+            z_score_threshold: 0.5,
+            profit_percent: 1.0,
+            stop_loss_percent: 1.0,
+            moving_averages: 2,
+        };
+        let run = || serde_json::to_string(&sensitivity(&config, &ratings, &[], &deltas).unwrap()).unwrap();
+
+        let single = thread_pool_builder(threads).build().unwrap().install(run);
+        assert_eq!(single, run());
+    }
+
+    #[test]
+    fn test_info_reports_version_and_features() {
+        let info: serde_json::Value = serde_json::from_str(&build_info().to_string()).unwrap();
+
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert!(info["features"].is_array());
+        let subcommands = info["subcommands"].as_array().unwrap();
+        assert!(subcommands.iter().any(|name| name == "calculate-glicko"));
+        assert!(subcommands.iter().any(|name| name == "info"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Bootstrap Monte Carlo over a backtest's closed trades.
+//!
+//! Trade returns are resampled with replacement and compounded into alternative equity
+//! paths, giving a distribution of total returns rather than the single realized one.
+
+use crate::{BacktestResult, ExitReason, OrderSide};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Build the crate's RNG. With `seed` runs are reproducible; without one a time-based
+/// seed is used, so every run differs.
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    StdRng::seed_from_u64(seed)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MonteCarloConfig {
+    /// Number of resampled equity paths
+    #[serde(default = "MonteCarloConfig::default_iterations")]
+    pub iterations: usize,
+    /// RNG seed; a time-based seed is used when unset
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl MonteCarloConfig {
+    fn default_iterations() -> usize {
+        1000
+    }
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> Self {
+        Self {
+            iterations: Self::default_iterations(),
+            seed: None,
+        }
+    }
+}
+
+/// Percentiles of simulated total return (fractions, like `BacktestResult::total_return`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MonteCarloSummary {
+    pub iterations: usize,
+    pub trades_per_path: usize,
+    pub p5_return: f64,
+    pub p50_return: f64,
+    pub p95_return: f64,
+}
+
+/// Resample the result's closed-trade returns into `iterations` paths of the same length.
+/// Warm-up trades are left out, as they are from the backtest metrics.
+pub fn bootstrap_trade_returns(result: &BacktestResult, config: &MonteCarloConfig) -> MonteCarloSummary {
+    let trade_returns: Vec<f64> = result
+        .orders
+        .iter()
+        .filter(|o| o.side == OrderSide::Sell && o.reason != ExitReason::Rebalance)
+        .filter(|o| !o.warmup)
+        .filter_map(|o| o.profit_loss_percent)
+        .map(|pct| pct / 100.0)
+        .collect();
+
+    let mut rng = seeded_rng(config.seed);
+    let mut totals: Vec<f64> = (0..config.iterations)
+        .map(|_| {
+            let growth: f64 = (0..trade_returns.len())
+                .map(|_| 1.0 + trade_returns[rng.gen_range(0..trade_returns.len())])
+                .product();
+            growth - 1.0
+        })
+        .collect();
+    totals.sort_by(f64::total_cmp);
+
+    let percentile = |p: f64| -> f64 {
+        if totals.is_empty() {
+            return 0.0;
+        }
+        let index = ((totals.len() - 1) as f64 * p).round() as usize;
+        totals[index]
+    };
+
+    MonteCarloSummary {
+        iterations: config.iterations,
+        trades_per_path: trade_returns.len(),
+        p5_return: percentile(0.05),
+        p50_return: percentile(0.50),
+        p95_return: percentile(0.95),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BacktestOrder;
+
+    fn result_with_trades(returns_percent: &[f64]) -> BacktestResult {
+        let orders: Vec<BacktestOrder> = returns_percent
+            .iter()
+            .enumerate()
+            .map(|(i, &pct)| BacktestOrder {
+                symbol: "BTCUSDT".to_string(),
+                side: OrderSide::Sell,
+                quantity: 1.0,
+                price: 100.0 + pct,
+                timestamp: i as i64,
+                timestamp_iso: None,
+                reason: ExitReason::ExitZScore,
+                profit_loss: Some(pct),
+                profit_loss_percent: Some(pct),
+                warmup: false,
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "total_return": 0.0, "annualized_return": 0.0, "sharpe_ratio": 0.0,
+            "sortino_ratio": 0.0, "alpha": 0.0, "max_drawdown": 0.0, "win_ratio": 0.0,
+            "total_trades": 0, "profit_factor": 0.0, "avg_trade_duration": 0.0, "orders": orders,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_percentiles() {
+        let result = result_with_trades(&[5.0, -2.0, 3.0, -4.0, 8.0, 1.0]);
+        let run = |seed| {
+            bootstrap_trade_returns(&result, &MonteCarloConfig { iterations: 500, seed: Some(seed) })
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        let summary = run(7);
+        assert!(summary.p5_return <= summary.p50_return && summary.p50_return <= summary.p95_return);
+        assert_eq!(summary.trades_per_path, 6);
+    }
+
+    #[test]
+    fn test_warmup_trades_are_not_resampled() {
+        let mut result = result_with_trades(&[-50.0, 5.0, 3.0]);
+        result.orders[0].warmup = true;
+
+        let summary = bootstrap_trade_returns(&result, &MonteCarloConfig { iterations: 200, seed: Some(7) });
+        assert_eq!(summary.trades_per_path, 2);
+        assert!(summary.p5_return > 0.0);
+    }
+}
@@ -0,0 +1,48 @@
+//! Serde helpers for metrics that can legitimately be infinite or NaN.
+//!
+//! `serde_json` writes non-finite floats as `null`, which loses the distinction
+//! between "no losses" (profit factor = ∞) and "missing". These helpers write the
+//! strings `"Infinity"`, `"-Infinity"` and `"NaN"` instead and accept them (or plain
+//! numbers) when reading.
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, SingleOrVec};
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.is_nan() {
+        serializer.serialize_str("NaN")
+    } else if value.is_infinite() {
+        serializer.serialize_str(if *value > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        serializer.serialize_f64(*value)
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Number(f64),
+        Text(String),
+    }
+
+    match Repr::deserialize(deserializer)? {
+        Repr::Number(value) => Ok(value),
+        Repr::Text(text) => match text.as_str() {
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            "NaN" => Ok(f64::NAN),
+            other => Err(serde::de::Error::custom(format!("Invalid float `{}`", other))),
+        },
+    }
+}
+
+/// A number, or one of the non-finite sentinel strings.
+pub fn schema(_gen: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(SingleOrVec::Vec(vec![InstanceType::Number, InstanceType::String])),
+        ..Default::default()
+    }
+    .into()
+}
@@ -0,0 +1,211 @@
+//! Parquet input/output for klines and ratings (enabled with the `parquet` feature).
+//!
+//! Column names match the `KlineData` / `GlickoRating` field names so files written by
+//! pandas/polars with the same schema can be read directly.
+
+use crate::{GlickoRating, KlineData, Result};
+use arrow_array::{Array, Float64Array, Int64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+fn kline_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("symbol", DataType::Utf8, false),
+        Field::new("open_time", DataType::Int64, false),
+        Field::new("close_time", DataType::Int64, false),
+        Field::new("open", DataType::Float64, false),
+        Field::new("high", DataType::Float64, false),
+        Field::new("low", DataType::Float64, false),
+        Field::new("close", DataType::Float64, false),
+        Field::new("volume", DataType::Float64, false),
+        Field::new("quote_asset_volume", DataType::Float64, false),
+        Field::new("number_of_trades", DataType::UInt32, false),
+        Field::new("taker_buy_base_asset_volume", DataType::Float64, false),
+        Field::new("taker_buy_quote_asset_volume", DataType::Float64, false),
+    ])
+}
+
+fn rating_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("symbol", DataType::Utf8, false),
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("rating", DataType::Float64, false),
+        Field::new("rating_deviation", DataType::Float64, false),
+        Field::new("volatility", DataType::Float64, false),
+        Field::new("performance_score", DataType::Float64, false),
+    ])
+}
+
+pub fn write_klines<P: AsRef<Path>>(path: P, klines: &[KlineData]) -> Result<()> {
+    let f64_column = |f: fn(&KlineData) -> f64| -> Arc<dyn Array> {
+        Arc::new(Float64Array::from_iter_values(klines.iter().map(f)))
+    };
+
+    let batch = RecordBatch::try_new(
+        Arc::new(kline_schema()),
+        vec![
+            Arc::new(StringArray::from_iter_values(klines.iter().map(|k| k.symbol.as_str()))),
+            Arc::new(Int64Array::from_iter_values(klines.iter().map(|k| k.open_time))),
+            Arc::new(Int64Array::from_iter_values(klines.iter().map(|k| k.close_time))),
+            f64_column(|k| k.open),
+            f64_column(|k| k.high),
+            f64_column(|k| k.low),
+            f64_column(|k| k.close),
+            f64_column(|k| k.volume),
+            f64_column(|k| k.quote_asset_volume),
+            Arc::new(UInt32Array::from_iter_values(klines.iter().map(|k| k.number_of_trades))),
+            f64_column(|k| k.taker_buy_base_asset_volume),
+            f64_column(|k| k.taker_buy_quote_asset_volume),
+        ],
+    )?;
+
+    write_batch(path, batch)
+}
+
+pub fn read_klines<P: AsRef<Path>>(path: P) -> Result<Vec<KlineData>> {
+    let mut klines = Vec::new();
+
+    for batch in read_batches(path)? {
+        let symbol = column::<StringArray>(&batch, "symbol")?;
+        let open_time = column::<Int64Array>(&batch, "open_time")?;
+        let close_time = column::<Int64Array>(&batch, "close_time")?;
+        let open = column::<Float64Array>(&batch, "open")?;
+        let high = column::<Float64Array>(&batch, "high")?;
+        let low = column::<Float64Array>(&batch, "low")?;
+        let close = column::<Float64Array>(&batch, "close")?;
+        let volume = column::<Float64Array>(&batch, "volume")?;
+        let quote_asset_volume = column::<Float64Array>(&batch, "quote_asset_volume")?;
+        let number_of_trades = column::<UInt32Array>(&batch, "number_of_trades")?;
+        let taker_buy_base = column::<Float64Array>(&batch, "taker_buy_base_asset_volume")?;
+        let taker_buy_quote = column::<Float64Array>(&batch, "taker_buy_quote_asset_volume")?;
+
+        for i in 0..batch.num_rows() {
+            klines.push(KlineData {
+                symbol: symbol.value(i).to_string(),
+                open_time: open_time.value(i),
+                close_time: close_time.value(i),
+                open: open.value(i),
+                high: high.value(i),
+                low: low.value(i),
+                close: close.value(i),
+                volume: volume.value(i),
+                quote_asset_volume: quote_asset_volume.value(i),
+                number_of_trades: number_of_trades.value(i),
+                taker_buy_base_asset_volume: taker_buy_base.value(i),
+                taker_buy_quote_asset_volume: taker_buy_quote.value(i),
+            });
+        }
+    }
+
+    Ok(klines)
+}
+
+pub fn write_ratings<P: AsRef<Path>>(path: P, ratings: &[GlickoRating]) -> Result<()> {
+    let f64_column = |f: fn(&GlickoRating) -> f64| -> Arc<dyn Array> {
+        Arc::new(Float64Array::from_iter_values(ratings.iter().map(f)))
+    };
+
+    let batch = RecordBatch::try_new(
+        Arc::new(rating_schema()),
+        vec![
+            Arc::new(StringArray::from_iter_values(ratings.iter().map(|r| r.symbol.as_str()))),
+            Arc::new(Int64Array::from_iter_values(ratings.iter().map(|r| r.timestamp))),
+            f64_column(|r| r.rating),
+            f64_column(|r| r.rating_deviation),
+            f64_column(|r| r.volatility),
+            f64_column(|r| r.performance_score),
+        ],
+    )?;
+
+    write_batch(path, batch)
+}
+
+pub fn read_ratings<P: AsRef<Path>>(path: P) -> Result<Vec<GlickoRating>> {
+    let mut ratings = Vec::new();
+
+    for batch in read_batches(path)? {
+        let symbol = column::<StringArray>(&batch, "symbol")?;
+        let timestamp = column::<Int64Array>(&batch, "timestamp")?;
+        let rating = column::<Float64Array>(&batch, "rating")?;
+        let rating_deviation = column::<Float64Array>(&batch, "rating_deviation")?;
+        let volatility = column::<Float64Array>(&batch, "volatility")?;
+        let performance_score = column::<Float64Array>(&batch, "performance_score")?;
+
+        for i in 0..batch.num_rows() {
+            ratings.push(GlickoRating {
+                symbol: symbol.value(i).to_string(),
+                timestamp: timestamp.value(i),
+                rating: rating.value(i),
+                rating_deviation: rating_deviation.value(i),
+                volatility: volatility.value(i),
+                performance_score: performance_score.value(i),
+            });
+        }
+    }
+
+    Ok(ratings)
+}
+
+fn write_batch<P: AsRef<Path>>(path: P, batch: RecordBatch) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+fn read_batches<P: AsRef<Path>>(path: P) -> Result<Vec<RecordBatch>> {
+    let file = File::open(path)?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+    Ok(reader.collect::<std::result::Result<Vec<_>, _>>()?)
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<T>())
+        .ok_or_else(|| format!("Missing or mistyped Parquet column `{}`", name).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glicko::calculate_ratings;
+    use crate::test_utils::sample_klines;
+
+    #[test]
+    fn test_parquet_round_trip_yields_identical_ratings() {
+        let dir = std::env::temp_dir().join(format!("glicko-parquet-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let json_path = dir.join("klines.json");
+        let klines_path = dir.join("klines.parquet");
+        let ratings_path = dir.join("ratings.parquet");
+
+        // The same klines read back through the JSON and the Parquet input paths
+        let klines = sample_klines("BTCUSDT", 300);
+        std::fs::write(&json_path, serde_json::to_string(&klines).unwrap()).unwrap();
+        let from_json: Vec<KlineData> = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        write_klines(&klines_path, &klines).unwrap();
+        let from_parquet = read_klines(&klines_path).unwrap();
+
+        let json_ratings = calculate_ratings(from_json).unwrap();
+        let parquet_ratings = calculate_ratings(from_parquet).unwrap();
+        assert_eq!(
+            serde_json::to_string(&json_ratings).unwrap(),
+            serde_json::to_string(&parquet_ratings).unwrap()
+        );
+
+        write_ratings(&ratings_path, &parquet_ratings).unwrap();
+        let restored_ratings = read_ratings(&ratings_path).unwrap();
+        assert_eq!(
+            serde_json::to_string(&restored_ratings).unwrap(),
+            serde_json::to_string(&json_ratings).unwrap()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{GlickoRating, KlineData};
+
+pub const HOUR_MS: i64 = 60 * 60 * 1000;
+
+/// Build a single hourly kline with the given open/close and a 60% taker-buy share.
+pub fn kline(symbol: &str, open_time: i64, open: f64, close: f64) -> KlineData {
+    KlineData {
+        symbol: symbol.to_string(),
+        open_time,
+        close_time: open_time + HOUR_MS - 1,
+        open,
+        high: open.max(close) * 1.001,
+        low: open.min(close) * 0.999,
+        close,
+        volume: 100.0,
+        quote_asset_volume: 100.0 * close,
+        number_of_trades: 1000,
+        taker_buy_base_asset_volume: 60.0,
+        taker_buy_quote_asset_volume: 60.0 * close,
+    }
+}
+
+/// Build one rating per value, `spacing_ms` apart from t=0, with a fixed 100 RD and
+/// neutral performance score.
+pub fn ratings(symbol: &str, series: &[f64], spacing_ms: i64) -> Vec<GlickoRating> {
+    series
+        .iter()
+        .enumerate()
+        .map(|(i, &rating)| GlickoRating {
+            symbol: symbol.to_string(),
+            timestamp: i as i64 * spacing_ms,
+            rating,
+            rating_deviation: 100.0,
+            volatility: 0.06,
+            performance_score: 0.5,
+        })
+        .collect()
+}
+
+/// Build `count` hourly klines whose closes oscillate in slow waves around 100,
+/// which is enough to push Glicko ratings through both z-score thresholds.
+pub fn sample_klines(symbol: &str, count: usize) -> Vec<KlineData> {
+    let start = 1640995200000; // 2022-01-01
+    let mut price = 100.0;
+
+    (0..count)
+        .map(|i| {
+            let drift = (i as f64 / 12.0).sin() * 0.02;
+            let open = price;
+            price *= 1.0 + drift;
+            kline(symbol, start + i as i64 * HOUR_MS, open, price)
+        })
+        .collect()
+}
@@ -0,0 +1,143 @@
+//! Integrity checks for kline feeds before they are rated.
+
+use crate::KlineData;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// A run of missing candles between two consecutive `open_time`s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KlineGap {
+    /// `open_time` of the last candle before the gap
+    pub after: i64,
+    /// `open_time` of the first candle after the gap
+    pub before: i64,
+    /// Number of candles missing at the inferred interval
+    pub missing: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KlineIntegrityReport {
+    /// Most common spacing between consecutive candles; `None` with fewer than two distinct times
+    pub interval_ms: Option<i64>,
+    /// `open_time`s that appear more than once (listed once each)
+    pub duplicates: Vec<i64>,
+    pub gaps: Vec<KlineGap>,
+}
+
+impl KlineIntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.duplicates.is_empty() && self.gaps.is_empty()
+    }
+}
+
+/// Check each symbol's klines for duplicate `open_time`s and missing candles.
+///
+/// The interval is inferred per symbol as the most frequent spacing between distinct
+/// consecutive `open_time`s (ties go to the smaller spacing), so a handful of gaps do not
+/// skew it. Input order does not matter.
+pub fn validate_klines(klines: &[KlineData]) -> BTreeMap<String, KlineIntegrityReport> {
+    let mut times_by_symbol: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+    for kline in klines {
+        times_by_symbol.entry(kline.symbol.as_str()).or_default().push(kline.open_time);
+    }
+
+    times_by_symbol
+        .into_iter()
+        .map(|(symbol, mut times)| {
+            times.sort_unstable();
+
+            let mut duplicates: Vec<i64> = times.windows(2).filter(|w| w[0] == w[1]).map(|w| w[0]).collect();
+            duplicates.dedup();
+            times.dedup();
+
+            let mut spacing_counts: HashMap<i64, usize> = HashMap::new();
+            count_spacings(&times, &mut spacing_counts);
+            let interval_ms = modal_spacing(spacing_counts);
+
+            let gaps = match interval_ms {
+                Some(interval) => times
+                    .windows(2)
+                    .filter(|w| w[1] - w[0] > interval)
+                    .map(|w| KlineGap {
+                        after: w[0],
+                        before: w[1],
+                        missing: (w[1] - w[0]) / interval - 1,
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+
+            (symbol.to_string(), KlineIntegrityReport { interval_ms, duplicates, gaps })
+        })
+        .collect()
+}
+
+/// The candle interval of a kline feed: the most frequent spacing between distinct
+/// consecutive `open_time`s within each symbol, pooled across symbols (ties go to the
+/// smaller spacing). `None` when no symbol has two distinct times.
+pub fn infer_interval(klines: &[KlineData]) -> Option<i64> {
+    let mut times_by_symbol: HashMap<&str, Vec<i64>> = HashMap::new();
+    for kline in klines {
+        times_by_symbol.entry(kline.symbol.as_str()).or_default().push(kline.open_time);
+    }
+
+    let mut spacing_counts: HashMap<i64, usize> = HashMap::new();
+    for times in times_by_symbol.values_mut() {
+        times.sort_unstable();
+        times.dedup();
+        count_spacings(times, &mut spacing_counts);
+    }
+    modal_spacing(spacing_counts)
+}
+
+/// Tally the spacings between consecutive entries of sorted, deduplicated `times`.
+fn count_spacings(times: &[i64], spacing_counts: &mut HashMap<i64, usize>) {
+    for w in times.windows(2) {
+        *spacing_counts.entry(w[1] - w[0]).or_default() += 1;
+    }
+}
+
+fn modal_spacing(spacing_counts: HashMap<i64, usize>) -> Option<i64> {
+    spacing_counts
+        .into_iter()
+        .max_by(|(a_spacing, a_count), (b_spacing, b_count)| a_count.cmp(b_count).then(b_spacing.cmp(a_spacing)))
+        .map(|(spacing, _)| spacing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{kline, HOUR_MS};
+
+    #[test]
+    fn test_validate_klines_reports_duplicate_and_gap() {
+        let mut klines: Vec<KlineData> = [0, 1, 2, 3, 5, 6]
+            .iter()
+            .map(|&i| kline("BTCUSDT", i * HOUR_MS, 100.0, 101.0))
+            .collect();
+        klines.push(kline("BTCUSDT", 2 * HOUR_MS, 100.0, 101.0));
+        klines.push(kline("ETHUSDT", 0, 10.0, 11.0));
+        klines.push(kline("ETHUSDT", HOUR_MS, 11.0, 12.0));
+
+        let reports = validate_klines(&klines);
+
+        let btc = &reports["BTCUSDT"];
+        assert_eq!(btc.interval_ms, Some(HOUR_MS));
+        assert_eq!(btc.duplicates, vec![2 * HOUR_MS]);
+        assert_eq!(btc.gaps, vec![KlineGap { after: 3 * HOUR_MS, before: 5 * HOUR_MS, missing: 1 }]);
+        assert!(!btc.is_clean());
+        assert!(reports["ETHUSDT"].is_clean());
+    }
+
+    #[test]
+    fn test_infer_interval_ignores_gaps() {
+        let mut klines: Vec<KlineData> = [0, 1, 2, 3, 5, 6, 7, 10, 11]
+            .iter()
+            .map(|&i| kline("BTCUSDT", i * HOUR_MS, 100.0, 101.0))
+            .collect();
+        klines.extend([4, 0, 2].iter().map(|&i| kline("ETHUSDT", i * HOUR_MS, 10.0, 11.0)));
+
+        assert_eq!(infer_interval(&klines), Some(3_600_000));
+        assert_eq!(infer_interval(&klines[..1]), None);
+    }
+}